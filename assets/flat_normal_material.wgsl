@@ -22,23 +22,45 @@
 }
 #endif
 
+struct FlatNormalMaterial {
+    alpha: f32,
+    morph: f32,
+};
+
+@group(2) @binding(100)
+var<uniform> flat_normal_material: FlatNormalMaterial;
+
 struct Vertex {
     @builtin(instance_index) instance_index: u32,
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
     @location(5) color: vec4<f32>,
     @location(10) blend_color: vec4<f32>,
+    @location(11) emissive: vec4<f32>,
+    @location(12) morph_target: vec3<f32>,
+};
+
+// Bevy's VertexOutput has no room for extra values, so this mirrors the fields
+// we use at the same locations and carries the emissive color after them.
+struct HexVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(5) color: vec4<f32>,
+    @location(8) emissive: vec4<f32>,
 };
 
 @vertex
 fn vertex(
     in: Vertex,
     @builtin(vertex_index) index: u32,
-) -> VertexOutput {
-    var out: VertexOutput;
+) -> HexVertexOutput {
+    var out: HexVertexOutput;
 
+    // Part way to where the vertex sat on the coarser chunk this one replaced,
+    // so switching detail levels is a smooth change rather than a pop
     let position = vec4<f32>(
-        in.position,
+        mix(in.position, in.morph_target, flat_normal_material.morph),
         1.0
     );
 
@@ -46,25 +68,29 @@ fn vertex(
     out.world_position = mesh_functions::mesh_position_local_to_world(world_from_local, position);
     out.position = position_world_to_clip(out.world_position.xyz);
 
-#ifdef MESH_PIPELINE
     out.world_normal = mesh_functions::mesh_normal_local_to_world(
         in.normal,
         in.instance_index
     );
-#endif
-
-#ifdef VERTEX_COLORS
     out.color = in.color;
-#endif
+    out.emissive = in.emissive;
    
     return out;
 }
 
 @fragment
 fn fragment(
-    in: VertexOutput,
+    hex: HexVertexOutput,
     @builtin(front_facing) is_front: bool,
 ) -> FragmentOutput {
+    var in: VertexOutput;
+    in.position = hex.position;
+    in.world_position = hex.world_position;
+    in.world_normal = hex.world_normal;
+#ifdef VERTEX_COLORS
+    in.color = hex.color;
+#endif
+
     // generate a PbrInput struct from the StandardMaterial bindings
     var pbr_input = pbr_input_from_standard_material(in, is_front);
 
@@ -73,7 +99,12 @@ fn fragment(
     // pbr_input.world_normal = pbr_input.N;
     //
     // Or... just normala colours :)
-    pbr_input.material.base_color = in.color;
+    pbr_input.material.base_color = hex.color;
+    pbr_input.material.base_color.a *= flat_normal_material.alpha;
+
+    // Glowing cells (lava and the like), the alpha being the strength. An
+    // exposure weight of 0 keeps them bright regardless of the camera exposure
+    pbr_input.material.emissive = vec4<f32>(hex.emissive.rgb * hex.emissive.a, 0.0);
 
     // we can optionally modify the input before lighting and alpha_discard is applied
     // pbr_input.material.base_color.b = pbr_input.material.base_color.r;
@@ -91,7 +122,7 @@ fn fragment(
 
     // apply in-shader post processing (fog, alpha-premultiply, and also tonemapping, debanding if the camera is non-hdr)
     // note this does not include fullscreen postprocessing effects like bloom.
-    out.color = main_pass_post_lighting_processing(pbr_input, out.color*0.4);
+    out.color = main_pass_post_lighting_processing(pbr_input, vec4<f32>(out.color.rgb * 0.4, out.color.a));
 
     // out.color = vec4(pbr_input.N, 1.0); // Render Normals
     // out.color = in.color;
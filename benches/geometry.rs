@@ -0,0 +1,143 @@
+//! Benchmarks for the geometry pipeline: building the sphere, the octree over
+//! its cells, and carving chunks out of it. Every input is built the same way
+//! each run, so results can be compared across changes.
+
+use bevy::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use planets::fibonacci_sphere::fibonacci_sphere;
+use planets::geometry_data::GeometryData;
+use planets::octree::{Octree, Point};
+
+/// The planet as `setup_bodies` builds it, minus the last subdivision
+fn sphere() -> GeometryData {
+    GeometryData::icosahedron()
+        .subdivide_n(7)
+        .expect("the icosahedron is well formed")
+        .slerp()
+        .recell()
+        .dual()
+        .expect("recell puts a cell around every vertex")
+}
+
+fn subdivide(c: &mut Criterion) {
+    c.bench_function("subdivide_n(7)", |b| {
+        b.iter(|| black_box(GeometryData::icosahedron().subdivide_n(7)))
+    });
+}
+
+fn dual(c: &mut Criterion) {
+    let recelled = GeometryData::icosahedron()
+        .subdivide_n(6)
+        .expect("the icosahedron is well formed")
+        .slerp()
+        .recell();
+    c.bench_function("dual", |b| {
+        b.iter_batched(
+            || recelled.clone(),
+            |geometry| black_box(geometry.dual()),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn octree_build(c: &mut Criterion) {
+    let points = fibonacci_sphere(200_000);
+    c.bench_function("octree insert 200k", |b| {
+        b.iter(|| {
+            let mut octree = Octree::new(16, Vec3::ZERO, 1.0, 0, vec![]);
+            for (value, &position) in points.iter().enumerate() {
+                octree.insert(Point { position, value });
+            }
+            black_box(octree)
+        })
+    });
+}
+
+/// The cell nearest each of a fixed set of points, by the KD-tree, the octree
+/// and brute force
+fn nearest_cell(c: &mut Criterion) {
+    let geometry = sphere();
+    let octree = geometry.create_octree();
+    let kdtree = geometry.build_kdtree();
+    let queries = fibonacci_sphere(1000);
+    // Comfortably more than the spacing between cells at this subdivision
+    let radius = 0.02;
+
+    let mut group = c.benchmark_group("nearest cell x1000");
+    group.bench_function("kdtree", |b| {
+        b.iter(|| {
+            for &query in &queries {
+                black_box(kdtree.nearest(query));
+            }
+        })
+    });
+    group.bench_function("octree nearest", |b| {
+        b.iter(|| {
+            for &query in &queries {
+                black_box(octree.nearest(query));
+            }
+        })
+    });
+    group.bench_function("octree within_distance", |b| {
+        b.iter(|| {
+            for &query in &queries {
+                let nearest = octree
+                    .within_distance(query, radius)
+                    .into_iter()
+                    .min_by(|&a, &b| {
+                        let a = geometry.cell_normals[a].distance_squared(query);
+                        let b = geometry.cell_normals[b].distance_squared(query);
+                        a.total_cmp(&b)
+                    });
+                black_box(nearest);
+            }
+        })
+    });
+    group.bench_function("brute force", |b| {
+        b.iter(|| {
+            for &query in &queries {
+                let nearest = (0..geometry.cell_normals.len()).min_by(|&a, &b| {
+                    let a = geometry.cell_normals[a].distance_squared(query);
+                    let b = geometry.cell_normals[b].distance_squared(query);
+                    a.total_cmp(&b)
+                });
+                black_box(nearest);
+            }
+        })
+    });
+    group.finish();
+}
+
+fn chunk_indices(c: &mut Criterion) {
+    let geometry = sphere();
+    let octree = geometry.create_octree();
+    let pov = Vec3::new(1.0, 2.0, 3.0).normalize();
+    c.bench_function("get_chunk_indices", |b| {
+        b.iter(|| black_box(octree.get_chunk_indices(geometry.cells.len(), pov, 0.8)))
+    });
+}
+
+fn sub_geometry(c: &mut Criterion) {
+    let geometry = sphere();
+    // The 200 cells nearest a fixed point, roughly a chunk
+    let center = Vec3::new(1.0, 2.0, 3.0).normalize();
+    let mut cells: Vec<usize> = (0..geometry.cells.len()).collect();
+    cells.sort_by(|&a, &b| {
+        let a = geometry.cell_normals[a].distance_squared(center);
+        let b = geometry.cell_normals[b].distance_squared(center);
+        a.total_cmp(&b)
+    });
+    cells.truncate(200);
+
+    c.bench_function("sub_geometry 200 cells", |b| {
+        b.iter(|| black_box(geometry.sub_geometry(&cells)))
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = subdivide, dual, octree_build, nearest_cell, chunk_indices, sub_geometry
+}
+criterion_main!(benches);
@@ -0,0 +1,103 @@
+use std::collections::BTreeSet;
+
+use bevy::{
+    color::palettes::css::{RED, YELLOW},
+    prelude::*,
+};
+
+use crate::chunk_storage::{Body, POV};
+
+/// Settings for drawing the cell adjacency graph, a line from each cell center
+/// to each of its neighbors. Neighbors that don't list the cell back are drawn
+/// in `broken_color`, which makes bad `cell_neighbors` stand out.
+#[derive(Resource)]
+pub(crate) struct AdjacencyOverlay {
+    pub(crate) enabled: bool,
+    /// Only cells this close to the point under the camera are drawn, relative
+    /// to the body radius, as drawing every edge of a big planet is too slow
+    pub(crate) radius: f32,
+    pub(crate) color: Color,
+    pub(crate) broken_color: Color,
+}
+
+impl Default for AdjacencyOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.2,
+            color: YELLOW.with_alpha(0.6).into(),
+            broken_color: RED.into(),
+        }
+    }
+}
+
+pub(crate) struct AdjacencyOverlayPlugin;
+
+impl Plugin for AdjacencyOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AdjacencyOverlay>()
+            .add_systems(Update, (toggle_adjacency_overlay, draw_adjacency_overlay));
+    }
+}
+
+pub(crate) fn toggle_adjacency_overlay(
+    mut config: ResMut<AdjacencyOverlay>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::KeyJ) {
+        config.enabled = !config.enabled;
+    }
+}
+
+pub(crate) fn draw_adjacency_overlay(
+    config: Res<AdjacencyOverlay>,
+    cameras: Query<&GlobalTransform, With<POV>>,
+    bodies: Query<(&Body, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    if !config.enabled {
+        return;
+    }
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+
+    for (body, transform) in bodies.iter() {
+        let to_local = transform.affine().inverse();
+        let Some(under_camera) = to_local
+            .transform_point3(camera.translation())
+            .try_normalize()
+        else {
+            continue;
+        };
+        // Lift the lines off the surface a touch so they dont z-fight
+        let to_world = |p: Vec3| transform.transform_point(p * 1.003);
+
+        let geometry = &body.geometry;
+        let nearby: BTreeSet<usize> = body
+            .octree
+            .within_distance(under_camera, config.radius)
+            .into_iter()
+            .collect();
+        for &cell in &nearby {
+            for &neighbor in &geometry.cell_neighbors[cell] {
+                let mutual = geometry.cell_neighbors[neighbor].contains(&cell);
+                // Sound edges between two nearby cells are drawn once, from the lower
+                if mutual && neighbor < cell && nearby.contains(&neighbor) {
+                    continue;
+                }
+
+                let color = if mutual {
+                    config.color
+                } else {
+                    config.broken_color
+                };
+                gizmos.line(
+                    to_world(geometry.cell_normals[cell]),
+                    to_world(geometry.cell_normals[neighbor]),
+                    color,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use bevy::prelude::*;
+
+use crate::chunk_storage::Body;
+
+/// Draws the local X/Y/Z axes of the body it is on, in red/green/blue
+#[derive(Component, Default)]
+pub(crate) struct ShowAxes;
+
+/// How far the axes reach, relative to the body radius
+const AXIS_LENGTH: f32 = 1.5;
+
+pub(crate) struct AxesPlugin;
+
+impl Plugin for AxesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (toggle_axes, draw_axes));
+    }
+}
+
+/// Adds or removes `ShowAxes` on every body
+pub(crate) fn toggle_axes(
+    mut commands: Commands,
+    bodies: Query<(Entity, Has<ShowAxes>), With<Body>>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if !input.just_pressed(KeyCode::KeyX) {
+        return;
+    }
+
+    for (entity, shown) in bodies.iter() {
+        if shown {
+            commands.entity(entity).remove::<ShowAxes>();
+        } else {
+            commands.entity(entity).insert(ShowAxes);
+        }
+    }
+}
+
+pub(crate) fn draw_axes(bodies: Query<&GlobalTransform, With<ShowAxes>>, mut gizmos: Gizmos) {
+    for transform in bodies.iter() {
+        gizmos.axes(*transform, AXIS_LENGTH);
+    }
+}
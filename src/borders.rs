@@ -0,0 +1,256 @@
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use rand::random_range;
+
+use crate::chunk_storage::{Body, Chunk, ChunkCells, LineChunk};
+
+/// Which owner (e.g. country) each cell of the body it is on belongs to.
+/// Bodies with this get borders drawn between their cells.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub(crate) struct CellOwners {
+    // The owner of each cell
+    pub(crate) owners: Vec<u32>,
+    // A list of indices into cells whose owner changed
+    pub(crate) changed: BTreeSet<usize>,
+}
+
+impl CellOwners {
+    /// Every cell owned by 0
+    pub(crate) fn new(cells: usize) -> Self {
+        Self {
+            owners: vec![0; cells],
+            changed: BTreeSet::new(),
+        }
+    }
+
+    /// Hands `cell` over to `owner`, redrawing its borders if that changes anything
+    pub(crate) fn set(&mut self, cell: usize, owner: u32) {
+        if self.owners[cell] != owner {
+            self.owners[cell] = owner;
+            self.changed.insert(cell);
+        }
+    }
+}
+
+/// How one kind of border is drawn
+#[derive(Clone, Copy, Debug, Reflect)]
+pub(crate) struct BorderStyle {
+    /// Width of the strip, relative to the body radius
+    pub(crate) width: f32,
+    pub(crate) color: Color,
+}
+
+/// The styles of the borders between cells with the same owner and with
+/// different owners
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub(crate) struct PoliticalBorders {
+    pub(crate) internal: BorderStyle,
+    pub(crate) external: BorderStyle,
+}
+
+impl Default for PoliticalBorders {
+    fn default() -> Self {
+        Self {
+            internal: BorderStyle {
+                width: 0.0005,
+                color: Color::srgb(0.25, 0.25, 0.25),
+            },
+            external: BorderStyle {
+                width: 0.002,
+                color: Color::srgb(0.05, 0.02, 0.02),
+            },
+        }
+    }
+}
+
+/// A plain unlit material for the border strips. The chunk material expects
+/// the blend, emissive and morph attributes, which the strips dont carry.
+#[derive(Resource)]
+pub(crate) struct BorderMaterial(pub(crate) Handle<StandardMaterial>);
+
+/// The mesh of the border overlay of a chunk, which is spawned as its child
+#[derive(Component)]
+pub(crate) struct ChunkBorders(pub(crate) Handle<Mesh>);
+
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub(crate) struct NeedsBorders;
+
+pub(crate) struct BordersPlugin;
+
+impl Plugin for BordersPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<CellOwners>()
+            .register_type::<PoliticalBorders>()
+            .init_resource::<PoliticalBorders>()
+            .add_systems(Startup, setup_border_material)
+            .add_systems(Update, claim_territory)
+            .add_systems(
+                FixedUpdate,
+                (mark_chunks_for_borders, update_chunk_borders).chain(),
+            );
+    }
+}
+
+fn setup_border_material(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    let material = materials.add(StandardMaterial {
+        base_color: Color::WHITE,
+        unlit: true,
+        ..default()
+    });
+    commands.insert_resource(BorderMaterial(material));
+}
+
+/// Hands the cells around a random cell of each body to a new owner on C,
+/// giving bodies their owners the first time
+fn claim_territory(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    mut bodies: Query<(Entity, &Body, Option<&mut CellOwners>)>,
+) {
+    if !input.just_pressed(KeyCode::KeyC) {
+        return;
+    }
+
+    for (entity, body, owners) in bodies.iter_mut() {
+        let geometry = &body.geometry;
+        if geometry.cells.is_empty() {
+            continue;
+        }
+
+        let mut new_owners = None;
+        let owners = match owners {
+            Some(owners) => owners.into_inner(),
+            None => new_owners.insert(CellOwners::new(geometry.cells.len())),
+        };
+
+        // Two rings of neighbours around the capital
+        let owner = owners.owners.iter().max().map_or(1, |max| max + 1);
+        let capital = random_range(0..geometry.cells.len());
+        let mut claimed = BTreeSet::from([capital]);
+        for _ in 0..2 {
+            let ring: Vec<usize> = claimed
+                .iter()
+                .flat_map(|&cell| geometry.cell_neighbors[cell].iter().copied())
+                .collect();
+            claimed.extend(ring);
+        }
+        for cell in claimed {
+            owners.set(cell, owner);
+        }
+
+        if let Some(owners) = new_owners {
+            commands.entity(entity).insert(owners);
+        }
+    }
+}
+
+/// Flags new chunks, and chunks holding cells whose owner changed
+pub(crate) fn mark_chunks_for_borders(
+    mut commands: Commands,
+    styles: Res<PoliticalBorders>,
+    mut bodies: Query<&mut CellOwners>,
+    chunks: Query<(Entity, &Chunk, &ChunkCells, Ref<Mesh3d>), Without<LineChunk>>,
+) {
+    for (entity, chunk, chunk_cells, mesh) in chunks.iter() {
+        // Only read, so this doesnt flag the owners as changed
+        let Ok(owners) = bodies.get_mut(chunk.body) else {
+            continue;
+        };
+
+        let changed = chunk_cells
+            .cells
+            .as_ref()
+            .is_some_and(|cells| owners.changed.iter().any(|cell| cells.contains(cell)));
+        if mesh.is_added() || styles.is_changed() || owners.is_added() || changed {
+            commands.entity(entity).insert(NeedsBorders);
+        }
+    }
+
+    for mut owners in bodies.iter_mut() {
+        if !owners.changed.is_empty() {
+            owners.changed.clear();
+        }
+    }
+}
+
+pub(crate) fn update_chunk_borders(
+    mut commands: Commands,
+    styles: Res<PoliticalBorders>,
+    material: Res<BorderMaterial>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    bodies: Query<(&Body, &CellOwners)>,
+    chunks: Query<(Entity, &Chunk, &ChunkCells, Option<&ChunkBorders>), With<NeedsBorders>>,
+) {
+    let time = Instant::now();
+    for (entity, chunk, chunk_cells, borders) in chunks.iter() {
+        if Instant::now().duration_since(time) > Duration::from_millis(3) {
+            return;
+        }
+
+        let Ok((body, CellOwners { owners, .. })) = bodies.get(chunk.body) else {
+            commands.entity(entity).remove::<NeedsBorders>();
+            continue;
+        };
+        let Some(cells) = &chunk_cells.cells else {
+            continue;
+        };
+
+        // Simplified chunks are too far away to make out the borders inside countries
+        let simplified = chunk_cells
+            .local_geometry
+            .as_ref()
+            .is_some_and(|local| local.cells.len() < cells.len());
+
+        let cells: Vec<usize> = cells.iter().copied().collect();
+        let mesh = body.geometry.styled_border_mesh(&cells, |a, b| {
+            if owners.get(a) != owners.get(b) {
+                Some(styles.external)
+            } else if simplified {
+                None
+            } else {
+                Some(styles.internal)
+            }
+        });
+
+        match borders {
+            Some(borders) => {
+                meshes.insert(&borders.0, mesh);
+            }
+            None => {
+                let mesh = meshes.add(mesh);
+                commands.entity(entity).with_child((
+                    Mesh3d(mesh.clone()),
+                    MeshMaterial3d(material.0.clone()),
+                    Transform::IDENTITY,
+                    Name::new("Borders"),
+                ));
+                commands.entity(entity).insert(ChunkBorders(mesh));
+            }
+        }
+        commands.entity(entity).remove::<NeedsBorders>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_only_records_real_changes() {
+        let mut owners = CellOwners::new(4);
+        owners.set(1, 0);
+        assert!(owners.changed.is_empty());
+
+        owners.set(1, 7);
+        owners.set(2, 7);
+        assert_eq!(owners.owners, vec![0, 7, 7, 0]);
+        assert_eq!(owners.changed, BTreeSet::from([1, 2]));
+    }
+}
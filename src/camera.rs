@@ -2,41 +2,92 @@ use bevy::{
     color::palettes::css::RED,
     input::mouse::{MouseMotion, MouseWheel},
     prelude::*,
+    render::camera::ScalingMode,
 };
 
-use crate::chunk_storage::POV;
+use crate::chunk_storage::{Body, POV};
+use crate::helpers::slerp_points;
 // Spherical camera shenangigans
 // Needs to map the camera position to the nearest point on the sphere
 // Camera transform gets set to that point
 // Mouse drag should move the camerai
 
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub(crate) struct CameraTarget {
     pub(crate) radius: f32,
 }
 
+/// Where the camera starts out. Insert it before adding [`CameraPlugin`] to
+/// start somewhere else.
+#[derive(Resource, Reflect, Clone, Copy, Debug)]
+#[reflect(Resource)]
+pub(crate) struct CameraStart {
+    /// Which side of the target the camera looks at it from
+    pub(crate) direction: Vec3,
+    /// How far from the center of the target the camera is held, in multiples
+    /// of its radius
+    pub(crate) distance: f32,
+    pub(crate) fov: f32,
+}
+
+impl Default for CameraStart {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::Z,
+            distance: 2.0,
+            fov: std::f32::consts::FRAC_PI_4,
+        }
+    }
+}
+
 pub(crate) struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            FixedUpdate,
-            (
-                position_camera,
-                mouse_drag.before(position_camera),
-                mouse_scroll.before(position_camera),
-            ),
-        )
-        .add_systems(Startup, setup_camera);
+        app.register_type::<CameraTarget>()
+            .register_type::<CameraStart>()
+            .init_resource::<CameraStart>()
+            .add_event::<FocusCell>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    position_camera,
+                    mouse_drag.before(position_camera),
+                    mouse_scroll.before(position_camera),
+                    run_camera_tour.before(position_camera),
+                    focus_on_cells.before(position_camera),
+                    toggle_camera_tour,
+                    toggle_projection,
+                ),
+            )
+            // After startup, so the target bodies are spawned to place it by
+            .add_systems(PostStartup, setup_camera);
     }
 }
 
 #[derive(Component)]
 pub(crate) struct GameCamera;
 
-pub(crate) fn setup_camera(mut commands: Commands) {
+pub(crate) fn setup_camera(
+    mut commands: Commands,
+    start: Res<CameraStart>,
+    target: Query<(&Transform, &CameraTarget)>,
+) {
+    let (center, radius) = target
+        .get_single()
+        .map_or((Vec3::ZERO, 1.0), |(transform, target)| {
+            (transform.translation, target.radius)
+        });
+    let direction = start.direction.normalize_or(Vec3::Z);
+
     commands.spawn((
         Camera3d::default(),
-        Transform::from_xyz(0.0, 0.0, 1.0),
+        Projection::Perspective(PerspectiveProjection {
+            fov: start.fov,
+            ..default()
+        }),
+        Transform::from_translation(center + direction * radius * start.distance)
+            .looking_at(center, Vec3::Y),
         GameCamera,
         POV(Vec3::ZERO, 0.0),
     ));
@@ -45,6 +96,7 @@ pub(crate) fn setup_camera(mut commands: Commands) {
 pub(crate) fn position_camera(
     mut camera: Query<&mut Transform, With<GameCamera>>,
     target: Query<(&Transform, &CameraTarget), Without<GameCamera>>,
+    start: Res<CameraStart>,
     mut gizmos: Gizmos<DefaultGizmoConfigGroup>,
 ) {
     if camera.is_empty() || target.is_empty() {
@@ -56,10 +108,58 @@ pub(crate) fn position_camera(
 
     let tt = target_transform.translation;
     let ct = camera_transform.translation;
-    camera_transform.translation = (ct - tt).normalize() * (radius * 2.0);
+    camera_transform.translation = tt + (ct - tt).normalize() * (radius * start.distance);
     camera_transform.look_at(tt, Vec3::Y);
 
-    gizmos.sphere((ct - tt).normalize() * radius, 0.2, RED);
+    gizmos.sphere(tt + (ct - tt).normalize() * radius, 0.2, RED);
+}
+
+/// Asks for the camera to jump to straight above `cell` of `body`
+#[derive(Event, Clone, Copy, Debug)]
+pub(crate) struct FocusCell {
+    pub(crate) body: Entity,
+    pub(crate) cell: usize,
+}
+
+/// Moves `camera` round its orbit so it looks straight down at `cell`, keeping
+/// its distance from the body. Follows the bodies translation and rotation, so
+/// it works for bodies anywhere in the scene.
+pub(crate) fn focus_camera_on_cell(
+    camera: &mut Transform,
+    body: &Body,
+    body_transform: &Transform,
+    cell: usize,
+) {
+    let center = body_transform.translation;
+    let direction = body_transform.rotation * body.geometry.cell_normals[cell].normalize();
+    let distance = camera.translation.distance(center);
+
+    camera.translation = center + direction * distance;
+    // Straight down over a pole, Y can't be up
+    let up = if direction.abs().y > 0.999 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    camera.look_at(center, up);
+}
+
+pub(crate) fn focus_on_cells(
+    mut requests: EventReader<FocusCell>,
+    mut camera: Query<&mut Transform, With<GameCamera>>,
+    bodies: Query<(&Body, &Transform), Without<GameCamera>>,
+) {
+    let Ok(mut camera) = camera.get_single_mut() else {
+        return;
+    };
+    for &FocusCell { body, cell } in requests.read() {
+        let Ok((body, body_transform)) = bodies.get(body) else {
+            continue;
+        };
+        if cell < body.geometry.cells.len() {
+            focus_camera_on_cell(&mut camera, body, body_transform, cell);
+        }
+    }
 }
 
 pub(crate) fn mouse_drag(
@@ -67,6 +167,7 @@ pub(crate) fn mouse_drag(
     buttons: Res<ButtonInput<MouseButton>>,
     target: Query<(&Transform, &CameraTarget), Without<GameCamera>>,
     mut camera: Query<&mut Transform, With<GameCamera>>,
+    start: Res<CameraStart>,
 ) {
     if !buttons.pressed(MouseButton::Left) {
         return;
@@ -86,7 +187,9 @@ pub(crate) fn mouse_drag(
         let local_delta = (-ev.delta.x * x_axis - ev.delta.y * y_axis) * 0.1;
 
         transform.translation += local_delta;
-        if transform.translation.y > radius * 1.99 || transform.translation.y < radius * -1.99 {
+        // Stop just short of the poles, where looking at the target flips over
+        let max_y = radius * start.distance * 0.995;
+        if transform.translation.y > max_y || transform.translation.y < -max_y {
             transform.translation -= -ev.delta.y * y_axis * 0.1;
         }
     }
@@ -96,20 +199,213 @@ pub(crate) fn mouse_scroll(
     mut evr_motion: EventReader<MouseWheel>,
     mut camera: Query<&mut Projection, With<GameCamera>>,
 ) {
-    let Projection::Perspective(projection) = camera.single_mut().into_inner() else {
-        return;
+    // Perspective cameras zoom by narrowing the fov, orthographic ones by scaling
+    let (zoom, min, max): (&mut f32, f32, f32) = match camera.single_mut().into_inner() {
+        Projection::Perspective(projection) => {
+            (&mut projection.fov, 0.1, 1.0 * std::f32::consts::PI)
+        }
+        Projection::Orthographic(projection) => (&mut projection.scale, 0.01, 2.0),
     };
 
     for ev in evr_motion.read() {
-        match ev.unit {
-            bevy::input::mouse::MouseScrollUnit::Line => {
-                projection.fov = 0.1f32
-                    .max((projection.fov.sqrt() - ev.y * 0.1).powi(2))
-                    .min(1.0 * std::f32::consts::PI);
+        // Trackpads scroll in pixels, roughly a hundred to a line
+        let lines = match ev.unit {
+            bevy::input::mouse::MouseScrollUnit::Line => ev.y,
+            bevy::input::mouse::MouseScrollUnit::Pixel => ev.y / 100.0,
+        };
+        *zoom = min.max((zoom.sqrt() - lines * 0.1).powi(2)).min(max);
+    }
+}
+
+/// The field of view a perspective camera `distance` from what it looks at
+/// would need to see as much as `projection` does, so level of detail can be
+/// picked the same way for both
+pub(crate) fn effective_fov(projection: &Projection, distance: f32) -> f32 {
+    match projection {
+        Projection::Perspective(projection) => projection.fov,
+        Projection::Orthographic(projection) => {
+            2.0 * (projection.area.height() / (2.0 * distance)).atan()
+        }
+    }
+}
+
+/// Switches between perspective and a top down orthographic map view,
+/// keeping roughly the same part of the planet in view
+pub(crate) fn toggle_projection(
+    input: Res<ButtonInput<KeyCode>>,
+    mut camera: Query<(&mut Projection, &Transform), With<GameCamera>>,
+    target: Query<&Transform, (With<CameraTarget>, Without<GameCamera>)>,
+) {
+    if !input.just_pressed(KeyCode::KeyM) {
+        return;
+    }
+    let Ok((mut projection, transform)) = camera.get_single_mut() else {
+        return;
+    };
+    let distance = target
+        .get_single()
+        .map(|target| transform.translation.distance(target.translation))
+        .unwrap_or(transform.translation.length());
+
+    *projection = match &*projection {
+        Projection::Perspective(perspective) => Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::FixedVertical {
+                viewport_height: 2.0 * distance * (perspective.fov / 2.0).tan(),
+            },
+            ..OrthographicProjection::default_3d()
+        }),
+        orthographic @ Projection::Orthographic(_) => {
+            Projection::Perspective(PerspectiveProjection {
+                fov: effective_fov(orthographic, distance),
+                ..default()
+            })
+        }
+    };
+}
+
+/// Flies the camera along great circle arcs between waypoints, looping
+/// back to the first once the last is reached.
+#[derive(Component)]
+pub(crate) struct CameraTour {
+    /// Directions from the target to fly through
+    pub(crate) waypoints: Vec<Vec3>,
+    /// Angular speed in radians per second
+    pub(crate) speed: f32,
+    pub(crate) paused: bool,
+    /// The waypoint we are currently leaving
+    segment: usize,
+    /// How far along the current arc we are, from 0 to 1
+    progress: f32,
+}
+
+impl Default for CameraTour {
+    /// Once round the equator, dipping over both poles on the way
+    fn default() -> Self {
+        Self::new(
+            vec![
+                Vec3::Z,
+                Vec3::new(1.0, 0.5, 0.0),
+                Vec3::new(0.0, 0.9, -1.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(0.0, -0.9, 1.0),
+            ],
+            0.2,
+        )
+    }
+}
+
+impl CameraTour {
+    pub(crate) fn new(waypoints: Vec<Vec3>, speed: f32) -> Self {
+        Self {
+            waypoints: waypoints.into_iter().map(|w| w.normalize()).collect(),
+            speed,
+            paused: false,
+            segment: 0,
+            progress: 0.0,
+        }
+    }
+
+    fn arc(&self) -> (Vec3, Vec3) {
+        let len = self.waypoints.len();
+        (
+            self.waypoints[self.segment % len],
+            self.waypoints[(self.segment + 1) % len],
+        )
+    }
+}
+
+pub(crate) fn run_camera_tour(
+    time: Res<Time>,
+    mut camera: Query<(&mut Transform, &mut CameraTour), With<GameCamera>>,
+    target: Query<&Transform, (With<CameraTarget>, Without<GameCamera>)>,
+) {
+    let Ok((mut transform, mut tour)) = camera.get_single_mut() else {
+        return;
+    };
+    let Ok(target_transform) = target.get_single() else {
+        return;
+    };
+    if tour.paused || tour.waypoints.len() < 2 {
+        return;
+    }
+
+    let (a, b) = tour.arc();
+    let angle = a.angle_between(b).max(f32::EPSILON);
+    tour.progress += tour.speed * time.delta_secs() / angle;
+    while tour.progress >= 1.0 {
+        tour.progress -= 1.0;
+        tour.segment = (tour.segment + 1) % tour.waypoints.len();
+    }
+
+    let (a, b) = tour.arc();
+    let tt = target_transform.translation;
+    let distance = (transform.translation - tt).length();
+    transform.translation = tt + slerp_points(a, b, tour.progress) * distance;
+}
+
+/// Starts the default tour on P, pausing and resuming it after that
+pub(crate) fn toggle_camera_tour(
+    mut commands: Commands,
+    mut cameras: Query<(Entity, Option<&mut CameraTour>), With<GameCamera>>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::KeyP) {
+        for (entity, tour) in cameras.iter_mut() {
+            match tour {
+                Some(mut tour) => tour.paused = !tour.paused,
+                None => {
+                    commands.entity(entity).insert(CameraTour::default());
+                }
             }
-            bevy::input::mouse::MouseScrollUnit::Pixel => {
-                todo!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn camera_starts_a_multiple_of_the_target_radius_away() {
+        let mut world = World::new();
+        world.init_resource::<CameraStart>();
+        world.spawn((
+            Transform::from_xyz(5.0, 0.0, 0.0),
+            CameraTarget { radius: 32.0 },
+        ));
+        world.run_system_once(setup_camera).unwrap();
+
+        let camera = world
+            .query_filtered::<&Transform, With<GameCamera>>()
+            .single(&world);
+        let distance = camera.translation.distance(Vec3::new(5.0, 0.0, 0.0));
+        assert!((distance - 32.0 * CameraStart::default().distance).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pixel_scrolls_zoom_like_lines() {
+        use bevy::input::mouse::MouseScrollUnit;
+
+        let zoom_after = |unit: MouseScrollUnit, y: f32| {
+            let mut world = World::new();
+            world.init_resource::<Events<MouseWheel>>();
+            world.spawn((GameCamera, Projection::default()));
+            world.send_event(MouseWheel {
+                unit,
+                x: 0.0,
+                y,
+                window: Entity::PLACEHOLDER,
+            });
+            world.run_system_once(mouse_scroll).unwrap();
+            match world.query::<&Projection>().single(&world) {
+                Projection::Perspective(projection) => projection.fov,
+                _ => unreachable!("the camera is perspective"),
             }
         };
+
+        let lines = zoom_after(MouseScrollUnit::Line, 1.0);
+        assert!(lines < PerspectiveProjection::default().fov);
+        assert!((zoom_after(MouseScrollUnit::Pixel, 100.0) - lines).abs() < 1e-6);
     }
 }
@@ -1,45 +1,115 @@
 use crate::{
-    camera::CameraTarget,
-    chunking::HexsphereMaterial,
+    camera::{effective_fov, CameraTarget},
     colors::{HexColors, NeedsColoring},
-    flatnormal::{FlatNormalMaterial, ATTRIBUTE_BLEND_COLOR},
-    geometry_data::GeometryData,
-    octree::Octree,
+    flatnormal::{FlatNormalMaterial, HexsphereMaterial, ATTRIBUTE_MORPH_TARGET},
+    geometry_cache::{load_or_build, GeometryParams, GEOMETRY_CACHE_PATH},
+    geometry_data::{ChunkBufferPool, GeometryData},
+    octree::{Octree, OctreeConfig},
+    preset::{PlanetPreset, Terrain},
     Wireframeable,
 };
 use bevy::{
-    pbr::{ExtendedMaterial, OpaqueRendererMethod},
+    ecs::{component::ComponentId, world::DeferredWorld},
+    math::{bounding::Aabb3d, Vec3A},
+    pbr::ExtendedMaterial,
     prelude::*,
+    render::mesh::PrimitiveTopology,
     tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
 };
 use std::{
     collections::{BTreeMap, BTreeSet},
+    path::Path,
     sync::Arc,
+    time::Duration,
 };
 
 pub type ChunkIndex = Vec<u8>;
 
-#[derive(Component)]
+/// The material every chunk is drawn with
+type ChunkMaterial = ExtendedMaterial<StandardMaterial, FlatNormalMaterial>;
+type ChunkMaterialHandle = MeshMaterial3d<ChunkMaterial>;
+
+/// Navigation of the octree paths [`ChunkIndex`]es are made of
+pub trait ChunkIndexExt {
+    /// The index of the node containing this one, `None` for the root
+    fn parent(&self) -> Option<ChunkIndex>;
+    /// Every node containing this one, nearest first and ending at the root
+    fn ancestors(&self) -> impl Iterator<Item = ChunkIndex>;
+    /// Whether `other` lies strictly inside this node. A node is not its own
+    /// ancestor.
+    fn is_ancestor_of(&self, other: &[u8]) -> bool;
+}
+
+impl ChunkIndexExt for [u8] {
+    fn parent(&self) -> Option<ChunkIndex> {
+        self.split_last().map(|(_, parent)| parent.to_vec())
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = ChunkIndex> {
+        std::iter::successors(self.parent(), |index| index.parent())
+    }
+
+    fn is_ancestor_of(&self, other: &[u8]) -> bool {
+        other.len() > self.len() && other.starts_with(self)
+    }
+}
+
+/// The deepest node containing both `a` and `b`, which is the root (an empty
+/// index) when they are in different top level children
+pub fn common_prefix(a: &[u8], b: &[u8]) -> ChunkIndex {
+    a.iter()
+        .zip(b)
+        .take_while(|(a, b)| a == b)
+        .map(|(&a, _)| a)
+        .collect()
+}
+
+/// Renders an octree path readably, e.g. `[0, 3, 1]` as `root/0/3/1`
+pub fn chunk_index_to_string(index: &[u8]) -> String {
+    let mut path = String::from("root");
+    for child in index {
+        path.push('/');
+        path.push_str(&child.to_string());
+    }
+    path
+}
+
+/// Parses a path written by [`chunk_index_to_string`] back into a `ChunkIndex`.
+/// Returns `None` unless it starts at `root` and every child is in `0..8`.
+pub fn parse_chunk_index(path: &str) -> Option<ChunkIndex> {
+    let mut parts = path.split('/');
+    if parts.next()? != "root" {
+        return None;
+    }
+    parts
+        .map(|part| part.parse::<u8>().ok().filter(|&child| child < 8))
+        .collect()
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component, from_reflect = false)]
 pub struct Body {
+    #[reflect(ignore)]
     pub geometry: Arc<GeometryData>,
+    #[reflect(ignore)]
     pub octree: Arc<Octree>,
 }
 
 impl Body {
     pub fn new(geometry: GeometryData) -> Self {
-        let capacity = 16;
-        let bounds = 1.0;
-        let center = Vec3::ZERO;
-
-        let mut octree = Octree::new(capacity, center, bounds, 0, vec![]);
-        // Insert geometry points into the octree
-        for (cell_index, &position) in geometry.cell_normals.iter().enumerate() {
-            octree.insert(crate::octree::Point {
-                position,
-                value: cell_index,
-            });
-        }
+        Self::new_with_config(geometry, OctreeConfig::default())
+    }
+
+    /// Like [`Self::new`], with the octree (and so the chunks) built as
+    /// `config` says
+    pub fn new_with_config(geometry: GeometryData, config: OctreeConfig) -> Self {
+        let octree = config.build(&geometry.cell_normals);
+        Self::from_parts(geometry, octree)
+    }
 
+    /// A body from geometry whose octree has already been built, e.g. both
+    /// read from the geometry cache
+    pub fn from_parts(geometry: GeometryData, octree: Octree) -> Self {
         Self {
             geometry: Arc::new(geometry),
             octree: Arc::new(octree),
@@ -47,7 +117,7 @@ impl Body {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Reflect)]
 pub struct ChunkData {
     pub mesh_handle: Option<Handle<Mesh>>,
     pub entity: Option<Entity>,
@@ -56,13 +126,29 @@ pub struct ChunkData {
 }
 
 #[derive(Component)]
+#[component(on_replace = recycle_local_geometry)]
 pub struct ChunkCells {
     pub cells: Option<BTreeSet<usize>>,
     pub cells_to_local: Option<BTreeMap<usize, usize>>,
     pub local_geometry: Option<GeometryData>,
 }
 
-#[derive(Component, Default)]
+/// Hands a chunks local geometry back to the [`ChunkBufferPool`] when the chunk
+/// is remeshed or despawned, for the next chunk to build in
+fn recycle_local_geometry(mut world: DeferredWorld, entity: Entity, _: ComponentId) {
+    let Some(geometry) = world
+        .get_mut::<ChunkCells>(entity)
+        .and_then(|mut cells| cells.local_geometry.take())
+    else {
+        return;
+    };
+    if let Some(pool) = world.get_resource::<ChunkBufferPool>() {
+        pool.recycle(geometry);
+    }
+}
+
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
 pub struct ChunkStorage(pub BTreeMap<ChunkIndex, ChunkData>);
 
 #[derive(Component, Default)]
@@ -74,10 +160,30 @@ pub enum ChunkRef {
     Cleanup(Entity),
 }
 
+/// Tracks a freshly spawned body until the chunks it first needed all have
+/// meshes, then fires [`BodyLoaded`] and is removed. Chunks deferred by
+/// [`MaxActiveChunks`] on that first pass don't count.
 #[derive(Component, Default)]
+pub struct InitialLoad {
+    /// The chunks still without a mesh, `None` until the body is first chunked
+    pub pending: Option<BTreeSet<ChunkIndex>>,
+    /// How many of the chunks have been meshed so far
+    pub satisfied: usize,
+}
+
+/// Fired once per body, when the chunks it needed when it was spawned are all
+/// meshed and visible
+#[derive(Event, Clone, Copy, Debug)]
+pub struct BodyLoaded {
+    pub body: Entity,
+}
+
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
 pub struct POV(pub Vec3, pub f32);
 
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 pub struct Chunk {
     pub body: Entity,
     pub index: ChunkIndex,
@@ -87,78 +193,353 @@ pub struct Chunk {
 #[component(storage = "SparseSet")]
 pub struct NeedsMesh;
 
+/// Everything `build_chunk` makes for a chunk: its cells, its local geometry,
+/// a map from global to local cells and its mesh
+pub type BuiltChunk = (Vec<usize>, GeometryData, BTreeMap<usize, usize>, Mesh);
+
 #[derive(Component)]
 #[component(storage = "SparseSet")]
-pub struct GeneratingMesh(
-    pub Task<Option<(Vec<usize>, GeometryData, BTreeMap<usize, usize>, Mesh)>>,
-);
+pub enum GeneratingMesh {
+    /// Being built on the async compute pool
+    Task(Task<Option<BuiltChunk>>),
+    /// Already built on the main thread, as there was no task pool to use
+    Ready(Option<Box<BuiltChunk>>),
+}
 
 #[derive(Component, Default)]
 #[component(storage = "SparseSet")]
 pub struct AwaitingDeletion(Vec<ChunkIndex>);
 
+/// How long a chunk takes to fade out once the chunks replacing it all have
+/// meshes, so that LOD changes blend rather than pop. Zero despawns chunks as
+/// soon as they are replaced.
+#[derive(Resource, Clone, Copy)]
+pub struct ChunkFade(pub Duration);
+
+impl Default for ChunkFade {
+    fn default() -> Self {
+        Self(Duration::from_millis(200))
+    }
+}
+
+/// A replaced chunk on its way out, drawn with its own blended copy of the
+/// hexsphere material so its alpha can drop without touching other chunks
+#[derive(Component)]
+pub struct FadingOut(pub Timer);
+
+/// The world space bounds of a spawned chunk, i.e. of its octree node
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ChunkBounds(pub Aabb3d);
+
+/// Finds the spawned chunk of `body` covering the surface in direction `dir`
+/// (in world space) from the bodies center. Where chunks overlap while the LOD
+/// changes, the most detailed one wins.
+pub fn chunk_at_direction(
+    chunks: &Query<(Entity, &Chunk, &ChunkBounds)>,
+    body: Entity,
+    body_transform: &Transform,
+    dir: Vec3,
+) -> Option<Entity> {
+    let point = Vec3A::from(body_transform.translation + dir.normalize() * body_transform.scale);
+
+    chunks
+        .iter()
+        .filter(|(_, chunk, ChunkBounds(bounds))| {
+            chunk.body == body && bounds.min.cmple(point).all() && point.cmple(bounds.max).all()
+        })
+        .max_by_key(|(_, chunk, _)| chunk.index.len())
+        .map(|(entity, _, _)| entity)
+}
+
+/// Marks a chunk whose mesh is a line list of cell borders rather than filled cells
+#[derive(Component, Default)]
+pub struct LineChunk;
+
+/// Marks a chunk whose cells share vertices (see [`ChunkShading::Welded`])
+#[derive(Component, Default)]
+pub struct WeldedChunk;
+
+/// How the vertices of a chunks cells are laid out
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum ChunkShading {
+    /// Every face gets its own vertices, so each cell can have its own flat normal
+    #[default]
+    Duplicated,
+    /// Neighboring faces share vertices, using about a third as many. Welded
+    /// chunks are always smooth shaded, and colors blend across cell borders.
+    Welded,
+}
+
+/// Controls how chunk meshes are built depending on how many cells they hold
+#[derive(Resource, Clone, Copy)]
+pub struct ChunkMeshConfig {
+    /// Chunks with more cells than this are collapsed into one cell by `simplify`
+    pub simplify_threshold: usize,
+    /// Chunks with more cells than this are drawn as just their cell borders.
+    /// `None` disables this tier.
+    pub line_threshold: Option<usize>,
+    /// The vertex layout of filled chunks. Only affects chunks meshed after it changes.
+    pub shading: ChunkShading,
+}
+
+/// Freezes the chunking pipeline, so the scene stays static for profiling and
+/// screenshots. Mesh tasks already in flight still finish in the background,
+/// and are picked up once unpaused.
+#[derive(Resource, Default, Clone, Copy)]
+pub struct ChunkingPaused(pub bool);
+
+fn chunking_running(paused: Res<ChunkingPaused>) -> bool {
+    !paused.0
+}
+
+pub(crate) fn toggle_chunking_paused(
+    mut paused: ResMut<ChunkingPaused>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::KeyF) {
+        paused.0 = !paused.0;
+    }
+}
+
+/// Pins every chunk to one octree depth, whatever the distance to the camera,
+/// for comparing the levels of detail. `None` picks chunks by distance as usual.
+#[derive(Resource, Default, Clone, Copy)]
+pub struct ForcedLod(pub Option<usize>);
+
+impl ForcedLod {
+    /// The deepest level `toggle_forced_lod` cycles through
+    const MAX_DEPTH: usize = 5;
+}
+
+/// Cycles through forcing each depth in turn, then back to no forcing
+pub(crate) fn toggle_forced_lod(mut forced: ResMut<ForcedLod>, input: Res<ButtonInput<KeyCode>>) {
+    if input.just_pressed(KeyCode::KeyL) {
+        forced.0 = match forced.0 {
+            None => Some(0),
+            Some(depth) if depth < ForcedLod::MAX_DEPTH => Some(depth + 1),
+            Some(_) => None,
+        };
+        info!("Forced LOD: {:?}", forced.0);
+    }
+}
+
+/// Per body level of detail settings. Bodies without one use the defaults.
+#[derive(Component, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct BodyLod {
+    /// Multiplies the zoom the LOD heuristic sees, above 1 gives coarser chunks
+    pub bias: f32,
+    /// Past this distance from the camera the body has no chunks at all
+    pub cull_distance: Option<f32>,
+    /// The fraction of [`MaxActiveChunks`] this body may use on its own
+    pub budget_share: f32,
+}
+
+impl Default for BodyLod {
+    fn default() -> Self {
+        Self {
+            bias: 1.0,
+            cull_distance: None,
+            budget_share: 1.0,
+        }
+    }
+}
+
+/// The most chunk entities (active or awaiting cleanup, across all bodies) that may
+/// exist at once. New chunks past this are deferred until old ones are cleaned up.
+#[derive(Resource, Clone, Copy)]
+pub struct MaxActiveChunks(pub usize);
+
+impl Default for MaxActiveChunks {
+    fn default() -> Self {
+        Self(4096)
+    }
+}
+
+impl Default for ChunkMeshConfig {
+    fn default() -> Self {
+        Self {
+            simplify_threshold: 256,
+            line_threshold: None,
+            shading: ChunkShading::default(),
+        }
+    }
+}
+
 pub struct ChunkingPlugin;
 
 impl Plugin for ChunkingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_bodies).add_systems(
-            FixedUpdate,
-            (
-                calculate_povs,
-                despawn_chunks.after(spawn_ready_chunks),
-                generate_meshes.after(calculate_povs),
-                poll_mesh_tasks.after(generate_meshes),
-                spawn_ready_chunks.after(poll_mesh_tasks),
-            ),
-        );
+        app.register_type::<Body>()
+            .register_type::<BodyLod>()
+            .register_type::<Chunk>()
+            .register_type::<ChunkStorage>()
+            .register_type::<POV>()
+            .init_resource::<ChunkMeshConfig>()
+            .init_resource::<ChunkBufferPool>()
+            .init_resource::<MaxActiveChunks>()
+            .init_resource::<ChunkingPaused>()
+            .init_resource::<ForcedLod>()
+            .init_resource::<ChunkFade>()
+            .add_event::<BodyLoaded>()
+            .add_systems(Startup, setup_bodies)
+            .add_systems(
+                FixedUpdate,
+                (
+                    calculate_povs,
+                    despawn_chunks.after(spawn_ready_chunks),
+                    fade_chunks.after(despawn_chunks),
+                    update_chunk_morphs.after(spawn_ready_chunks),
+                    generate_meshes.after(calculate_povs),
+                    poll_mesh_tasks.after(generate_meshes),
+                    spawn_ready_chunks.after(poll_mesh_tasks),
+                    check_bodies_loaded.after(spawn_ready_chunks),
+                    log_loaded_bodies.after(check_bodies_loaded),
+                )
+                    .run_if(chunking_running),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_chunking_paused,
+                    toggle_forced_lod,
+                    finish_loading_bodies,
+                ),
+            );
     }
 }
 
-fn create_material(
-    flat_materials: &mut ResMut<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>,
-) -> Handle<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>> {
-    let extended_material = ExtendedMaterial {
-        base: StandardMaterial {
-            opaque_render_method: OpaqueRendererMethod::Auto,
-            ..Default::default()
-        },
-        extension: FlatNormalMaterial {},
-    };
-    flat_materials.add(extended_material)
-}
+/// A body as chunking sees it, with its LOD settings if it has its own and
+/// its initial load until that has finished
+type ChunkedBody<'a> = (
+    Entity,
+    &'a Body,
+    &'a mut ChunkRefs,
+    &'a Transform,
+    Option<&'a BodyLod>,
+    Option<&'a mut InitialLoad>,
+);
 
 fn calculate_povs(
     mut commands: Commands,
     mut pov_query: Query<(&Transform, &mut POV, &Projection)>,
-    mut body_query: Query<(Entity, &Body, &mut ChunkRefs, &Transform)>,
+    mut body_query: Query<ChunkedBody>,
+    chunk_query: Query<(), With<Chunk>>,
+    max_chunks: Res<MaxActiveChunks>,
+    forced_lod: Res<ForcedLod>,
+    // How many chunks there were after the last pass, if it deferred any
+    mut deferred_at: Local<Option<usize>>,
 ) {
     let Ok((camera_transform, mut pov, projection)) = pov_query.get_single_mut() else {
         return;
     };
 
-    let Projection::Perspective(persp) = projection else {
-        return;
-    };
+    let fov = effective_fov(projection, camera_transform.translation.length());
+    let mut chunk_count = chunk_query.iter().len();
 
+    // Deferred chunks are retried once cleanup has made room for them
+    let room_freed = deferred_at.is_some_and(|count| chunk_count < count);
     if pov.0.distance_squared(camera_transform.translation) < 0.0001
-        && (pov.1 - persp.fov).abs() < 0.0001
+        && (pov.1 - fov).abs() < 0.0001
+        && !forced_lod.is_changed()
+        && !room_freed
     {
         return;
     }
 
     pov.0 = camera_transform.translation;
-    pov.1 = persp.fov;
-
-    for (body_entity, body, mut chunk_refs, transform) in body_query.iter_mut() {
-        let cell_count = body.geometry.cells.len();
-        let needed_indices = body.octree.get_chunk_indices(
-            cell_count,
-            (camera_transform.translation - transform.translation).normalize(),
-            persp.fov.sqrt(),
-        );
-        let needed_indices: BTreeSet<_> = needed_indices.into_iter().collect();
+    pov.1 = fov;
+
+    let was_deferred = deferred_at.is_some();
+    let mut any_deferred = false;
 
-        let existing_set: BTreeSet<_> = chunk_refs.0.keys().cloned().collect();
+    for (body_entity, body, mut chunk_refs, transform, lod, initial_load) in body_query.iter_mut() {
+        let lod = lod.copied().unwrap_or_default();
+        let offset = camera_transform.translation - transform.translation;
+
+        let mut needed_indices = BTreeSet::new();
+        if lod
+            .cull_distance
+            .is_none_or(|cull_distance| offset.length() <= cull_distance)
+        {
+            if let Some(depth) = forced_lod.0 {
+                needed_indices.extend(body.octree.indices_at_depth(depth));
+            } else {
+                let cell_count = body.geometry.cells.len();
+                let fov = effective_fov(projection, offset.length());
+                needed_indices.extend(body.octree.get_chunk_indices(
+                    cell_count,
+                    offset.normalize(),
+                    fov.sqrt() * lod.bias,
+                ));
+            }
+            debug_assert!(body.octree.is_valid_selection(&needed_indices));
+        }
+
+        // Hand out the remaining chunk budget to brand new chunks, nearest first
+        let mut new_indices: Vec<_> = needed_indices
+            .iter()
+            .filter(|index| !chunk_refs.0.contains_key(*index))
+            .map(|index| {
+                let distance = body
+                    .octree
+                    .center_for_index(index)
+                    .map(|c| {
+                        transform
+                            .transform_point(c)
+                            .distance_squared(camera_transform.translation)
+                    })
+                    .unwrap_or(f32::INFINITY);
+                (distance, index.clone())
+            })
+            .collect();
+        new_indices.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let body_max_chunks = (max_chunks.0 as f32 * lod.budget_share) as usize;
+        let mut body_chunk_count = chunk_refs.0.len();
+        let mut deferred = BTreeSet::new();
+        for (_, index) in new_indices {
+            if chunk_count < max_chunks.0 && body_chunk_count < body_max_chunks {
+                chunk_count += 1;
+                body_chunk_count += 1;
+            } else {
+                deferred.insert(index);
+            }
+        }
+
+        if !deferred.is_empty() {
+            if !was_deferred {
+                warn!(
+                    "Hit the chunk limit of {} ({} for this body), deferring {} chunks",
+                    max_chunks.0,
+                    body_max_chunks,
+                    deferred.len()
+                );
+            }
+            any_deferred = true;
+            needed_indices.retain(|index| !deferred.contains(index));
+        }
+
+        if let Some(mut initial_load) = initial_load {
+            if initial_load.pending.is_none() {
+                initial_load.pending = Some(needed_indices.clone());
+            }
+        }
+
+        // Chunks overlapping a deferred one have to stay until it exists,
+        // otherwise they would leave a hole. Two chunks overlap when one
+        // contains the other, so the shorter index is all common prefix.
+        let overlaps_deferred = |index: &ChunkIndex| {
+            deferred
+                .iter()
+                .any(|d| common_prefix(d, index).len() == d.len().min(index.len()))
+        };
+        let existing_set: BTreeSet<_> = chunk_refs
+            .0
+            .keys()
+            .filter(|index| !overlaps_deferred(index))
+            .cloned()
+            .collect();
 
         for index in &needed_indices {
             let entity = match chunk_refs.0.get(index) {
@@ -178,7 +559,7 @@ fn calculate_povs(
                             index: index.clone(),
                         },
                         NeedsMesh,
-                        Name::new(format!("Chunk {:?}", index)),
+                        Name::new(format!("Chunk {}", chunk_index_to_string(index))),
                     ))
                     .id(),
             };
@@ -196,8 +577,7 @@ fn calculate_povs(
 
         for index in &needed_indices {
             // Crawl up, see if there is any obsolete parent
-            for i in (0..index.len()).rev() {
-                let parent_index = index[0..i].to_vec();
+            for parent_index in index.ancestors() {
                 // if the parent chunk exists already and is obsolete
                 if obsolete_indices.contains(&parent_index) {
                     replacing
@@ -209,8 +589,7 @@ fn calculate_povs(
         }
         for index in &obsolete_indices {
             // Crawl up, see if there is any brand new parent
-            for i in (0..index.len()).rev() {
-                let parent_index = index[0..i].to_vec();
+            for parent_index in index.ancestors() {
                 // There is a parent that is currently needed!
                 if needed_indices.contains(&parent_index) {
                     replacing
@@ -252,19 +631,37 @@ fn calculate_povs(
             }
         }
     }
+
+    if any_deferred {
+        *deferred_at = Some(chunk_count);
+    } else {
+        if was_deferred {
+            info!("Back under the chunk limit of {}", max_chunks.0);
+        }
+        *deferred_at = None;
+    }
 }
 
 pub(crate) fn despawn_chunks(
     mut commands: Commands,
-    chunk_query: Query<(Entity, &Chunk, &AwaitingDeletion)>,
+    chunk_query: Query<(
+        Entity,
+        &Chunk,
+        &AwaitingDeletion,
+        Option<&ChunkMaterialHandle>,
+    )>,
     has_mesh: Query<Option<&Mesh3d>>,
     mut body_query: Query<(&mut ChunkRefs, &mut ChunkStorage)>,
+    fade: Res<ChunkFade>,
+    hexsphere: Res<HexsphereMaterial>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
 ) {
     // If all the things that replaced us (potentially 1) have meshes,
     // or they no longer exist, then we can delete ourself.
     // This way, chunks never despawn and leave empty loading holes.
+    // Chunks with a mesh fade out over `ChunkFade` first, see `fade_chunks`.
 
-    for (chunk_entity, chunk, AwaitingDeletion(pending)) in chunk_query.iter() {
+    for (chunk_entity, chunk, AwaitingDeletion(pending), material) in chunk_query.iter() {
         let Ok((mut chunk_refs, mut storage)) = body_query.get_mut(chunk.body) else {
             commands.entity(chunk_entity).despawn_recursive();
             continue;
@@ -288,24 +685,167 @@ pub(crate) fn despawn_chunks(
 
             storage.0.remove(&chunk.index);
 
+            // Fade from the chunks own material if it has one, so a morph in
+            // progress isn't undone
+            let meshed = matches!(has_mesh.get(chunk_entity), Ok(Some(_)));
+            let current = material.map_or(&hexsphere.0, |material| &material.0);
+            match materials.get(current).cloned() {
+                Some(mut material) if meshed && !fade.0.is_zero() => {
+                    material.base.alpha_mode = AlphaMode::Blend;
+                    commands
+                        .entity(chunk_entity)
+                        .remove::<AwaitingDeletion>()
+                        .insert((
+                            FadingOut(Timer::new(fade.0, TimerMode::Once)),
+                            MeshMaterial3d(materials.add(material)),
+                        ));
+                }
+                _ => commands.entity(chunk_entity).despawn_recursive(),
+            }
+        }
+    }
+}
+
+/// How much further past splitting (see [`Octree::split_pressure`]) a chunks
+/// parent gets before the chunk has fully morphed from the parents surface
+/// into its own. The parent splits at 1, so children start out looking just
+/// like it and settle into their own shape by `1 + MORPH_BAND`.
+const MORPH_BAND: f32 = 1.0;
+
+/// Meshed chunks that aren't fading out
+type Settled = (With<Mesh3d>, Without<FadingOut>);
+
+/// Sets how far each chunk is morphed towards the surface of its parent, see
+/// [`ATTRIBUTE_MORPH_TARGET`]. Morphing chunks get their own copy of the
+/// hexsphere material to hold their morph, and go back to the shared one
+/// once done, so only the few chunks near a change in detail need their own.
+fn update_chunk_morphs(
+    pov_query: Query<(&Transform, &Projection), With<POV>>,
+    body_query: Query<(&Body, &Transform, Option<&BodyLod>)>,
+    mut chunk_query: Query<(Entity, &Chunk, &ChunkMaterialHandle), Settled>,
+    mut commands: Commands,
+    forced_lod: Res<ForcedLod>,
+    hexsphere: Res<HexsphereMaterial>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    let Ok((camera_transform, projection)) = pov_query.get_single() else {
+        return;
+    };
+
+    for (chunk_entity, chunk, material) in chunk_query.iter_mut() {
+        let morph = match (
+            forced_lod.0,
+            chunk.index.parent(),
+            body_query.get(chunk.body),
+        ) {
+            (None, Some(parent), Ok((body, transform, lod))) => {
+                // Mirrors the selection in `calculate_povs`
+                let lod = lod.copied().unwrap_or_default();
+                let offset = camera_transform.translation - transform.translation;
+                let fov = effective_fov(projection, offset.length());
+                body.octree
+                    .split_pressure(
+                        &parent,
+                        body.geometry.cells.len(),
+                        offset.normalize(),
+                        fov.sqrt() * lod.bias,
+                    )
+                    .map_or(0.0, |pressure| {
+                        (1.0 - (pressure - 1.0) / MORPH_BAND).clamp(0.0, 1.0)
+                    })
+            }
+            _ => 0.0,
+        };
+
+        let shared = material.0 == hexsphere.0;
+        if morph <= 0.0 {
+            if !shared {
+                commands
+                    .entity(chunk_entity)
+                    .insert(MeshMaterial3d(hexsphere.0.clone()));
+            }
+        } else if shared {
+            if let Some(mut own) = materials.get(&hexsphere.0).cloned() {
+                own.extension.morph = morph;
+                commands
+                    .entity(chunk_entity)
+                    .insert(MeshMaterial3d(materials.add(own)));
+            }
+        } else if materials
+            .get(&material.0)
+            .is_some_and(|own| (own.extension.morph - morph).abs() > 0.001)
+        {
+            if let Some(own) = materials.get_mut(&material.0) {
+                own.extension.morph = morph;
+            }
+        }
+    }
+}
+
+/// Lowers the alpha of fading chunks over [`ChunkFade`], despawning them once
+/// they are gone. Their material goes with them, as nothing else holds it.
+fn fade_chunks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut chunk_query: Query<(Entity, &mut FadingOut, &MeshMaterial3d<ChunkMaterial>)>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    for (chunk_entity, mut fading, material) in chunk_query.iter_mut() {
+        fading.0.tick(time.delta());
+        if fading.0.finished() {
             commands.entity(chunk_entity).despawn_recursive();
+        } else if let Some(material) = materials.get_mut(&material.0) {
+            material.extension.alpha = fading.0.fraction_remaining();
         }
     }
 }
 
+/// Chunks waiting on a mesh that no task is building yet
+type Unmeshed = (With<NeedsMesh>, Without<GeneratingMesh>);
+
 fn generate_meshes(
     mut commands: Commands,
-    query: Query<(Entity, &Chunk), (With<NeedsMesh>, Without<GeneratingMesh>)>,
-    has_mesh: Query<(), With<Mesh3d>>,
+    query: Query<(Entity, &Chunk, Has<Mesh3d>), Unmeshed>,
     generating: Query<(), With<GeneratingMesh>>,
-    body_query: Query<(&Body, &ChunkStorage)>,
+    body_query: Query<(&Body, &ChunkStorage, &Transform)>,
+    camera_query: Query<&Transform, With<POV>>,
+    config: Res<ChunkMeshConfig>,
+    pool: Res<ChunkBufferPool>,
 ) {
     let mut i = generating.iter().len();
 
-    let thread_pool = AsyncComputeTaskPool::get(); // or use bevy's default
+    // Minimal apps (and tests) may never have set up the task pools, in which
+    // case chunks are built right here instead
+    let thread_pool = AsyncComputeTaskPool::try_get();
 
-    for (chunk_entity, chunk) in query.iter() {
-        if has_mesh.get(chunk_entity).is_ok() {
+    // Mesh the chunks closest to the camera first, so that whatever is being
+    // looked at resolves before the far side of the planet
+    let camera_position = camera_query
+        .get_single()
+        .map(|t| t.translation)
+        .unwrap_or_default();
+    let mut pending: Vec<_> = query
+        .iter()
+        .map(|(chunk_entity, chunk, has_mesh)| {
+            let distance = body_query
+                .get(chunk.body)
+                .ok()
+                .and_then(|(body, _, transform)| {
+                    let center = body.octree.center_for_index(&chunk.index)?;
+                    Some(
+                        transform
+                            .transform_point(center)
+                            .distance_squared(camera_position),
+                    )
+                })
+                .unwrap_or(f32::INFINITY);
+            (distance, chunk_entity, chunk, has_mesh)
+        })
+        .collect();
+    pending.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    for (_, chunk_entity, chunk, has_mesh) in pending {
+        if has_mesh {
             commands.entity(chunk_entity).remove::<NeedsMesh>();
             continue;
         }
@@ -313,7 +853,7 @@ fn generate_meshes(
             return;
         }
         // Look up the body to get geometry / octree
-        let Ok((body, storage)) = body_query.get(chunk.body) else {
+        let Ok((body, storage, _)) = body_query.get(chunk.body) else {
             continue; // or handle error
         };
 
@@ -327,39 +867,68 @@ fn generate_meshes(
         let index_clone = chunk.index.clone();
         let geometry = body.geometry.clone();
         let octree = body.octree.clone();
+        let config = *config;
+        let pool = pool.clone();
 
-        let task = thread_pool.spawn(async move {
-            let Some(cells) = octree.get_cells_for_index(&index_clone) else {
-                return None;
-            };
-
-            let (mut local_geometry, mut cell_map) = geometry.sub_geometry(&cells);
-            if local_geometry.cells.len() > 256 {
-                local_geometry = local_geometry.simplify();
-                for v in cell_map.values_mut() {
-                    // all original cells point into the ONE simple cell
-                    *v = 0;
-                }
-            } else {
-                local_geometry = local_geometry.duplicate();
-            }
-            let mut mesh = local_geometry.mesh();
-            mesh.insert_attribute(
-                ATTRIBUTE_BLEND_COLOR,
-                vec![[1.0, 0.0, 0.0, 1.0]; local_geometry.vertices.len()],
-            );
-
-            Some((cells, local_geometry, cell_map, mesh))
-        });
+        let generating = match thread_pool {
+            Some(thread_pool) => GeneratingMesh::Task(thread_pool.spawn(async move {
+                build_chunk(&geometry, &octree, &index_clone, config, &pool)
+            })),
+            None => GeneratingMesh::Ready(
+                build_chunk(&geometry, &octree, &index_clone, config, &pool).map(Box::new),
+            ),
+        };
 
         commands
             .entity(chunk_entity)
             // .remove::<NeedsMesh>()
-            .insert(GeneratingMesh(task));
+            .insert(generating);
+        if config.shading == ChunkShading::Welded {
+            commands.entity(chunk_entity).insert(WeldedChunk);
+        }
         i += 1;
     }
 }
 
+/// Builds the mesh of the chunk at `index`, as a line list of its cell borders
+/// if it has more cells than the configs `line_threshold`
+fn build_chunk(
+    geometry: &GeometryData,
+    octree: &Octree,
+    index: &[u8],
+    config: ChunkMeshConfig,
+    pool: &ChunkBufferPool,
+) -> Option<BuiltChunk> {
+    let cells = octree.get_cells_for_index(index)?;
+    let drawn_as_lines = |cells: usize| {
+        config
+            .line_threshold
+            .is_some_and(|threshold| cells > threshold)
+    };
+
+    if drawn_as_lines(cells.len()) {
+        return Some(geometry.build_chunk_border_mesh(&cells));
+    }
+
+    let mut built =
+        geometry.build_chunk_mesh_pooled(&cells, config.simplify_threshold, config.shading, pool);
+
+    // Where the parent was simplified, morph from its surface (see `update_chunk_morphs`)
+    if let Some(parent_cells) = index
+        .parent()
+        .and_then(|parent| octree.get_cells_for_index(&parent))
+        .filter(|parent_cells| {
+            parent_cells.len() > config.simplify_threshold && !drawn_as_lines(parent_cells.len())
+        })
+    {
+        let (_, local_geometry, _, mesh) = &mut built;
+        let targets = geometry.morph_targets(&parent_cells, &local_geometry.vertices);
+        mesh.insert_attribute(ATTRIBUTE_MORPH_TARGET, targets);
+    }
+
+    Some(built)
+}
+
 fn poll_mesh_tasks(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -367,16 +936,23 @@ fn poll_mesh_tasks(
     mut body_query: Query<&mut ChunkStorage>,
 ) {
     for (chunk_entity, chunk, mut gen_mesh) in query.iter_mut() {
-        if !gen_mesh.0.is_finished() {
-            continue;
-        }
-        if let Some(Some((cells, local_geometry, cells_to_local, mesh))) =
-            block_on(future::poll_once(&mut gen_mesh.0))
-        {
+        let built = match &mut *gen_mesh {
+            GeneratingMesh::Task(task) => {
+                if !task.is_finished() {
+                    continue;
+                }
+                block_on(future::poll_once(task)).flatten()
+            }
+            GeneratingMesh::Ready(built) => built.take().map(|built| *built),
+        };
+        if let Some((cells, local_geometry, cells_to_local, mesh)) = built {
             let index = chunk.index.clone();
             if let Ok(mut storage) = body_query.get_mut(chunk.body) {
                 let entry = storage.0.entry(index).or_default();
                 entry.cells = Some(cells);
+                if mesh.primitive_topology() == PrimitiveTopology::LineList {
+                    commands.entity(chunk_entity).insert(LineChunk);
+                }
                 entry.mesh_handle = Some(meshes.add(mesh));
                 commands.entity(chunk_entity).insert(ChunkCells {
                     cells: entry.cells.clone().map(|i| i.into_iter().collect()),
@@ -391,12 +967,12 @@ fn poll_mesh_tasks(
 
 pub fn spawn_ready_chunks(
     mut commands: Commands,
-    mut body_query: Query<&mut ChunkStorage>,
+    mut body_query: Query<(&Body, &mut ChunkStorage, &Transform)>,
     chunk_query: Query<(Entity, &Chunk), (With<NeedsMesh>, Without<GeneratingMesh>)>,
     material: Res<HexsphereMaterial>,
 ) {
     for (chunk_entity, chunk) in chunk_query.iter() {
-        let Ok(mut storage) = body_query.get_mut(chunk.body) else {
+        let Ok((body, mut storage, body_transform)) = body_query.get_mut(chunk.body) else {
             continue;
         };
 
@@ -410,11 +986,14 @@ pub fn spawn_ready_chunks(
                     e.insert((
                         Mesh3d(mesh_handle.clone()),
                         MeshMaterial3d(material.0.clone()),
-                        Transform::from_scale(Vec3::splat(32.0)),
+                        *body_transform,
                         Wireframeable,
                         NeedsColoring,
                     ))
                     .remove::<NeedsMesh>();
+                    if let Some(bounds) = node_bounds(&body.octree, &chunk.index, body_transform) {
+                        e.insert(ChunkBounds(bounds));
+                    }
                 });
             }
             storage.0.remove(&chunk.index);
@@ -422,33 +1001,375 @@ pub fn spawn_ready_chunks(
     }
 }
 
+/// Fires [`BodyLoaded`] for bodies whose initially needed chunks all have
+/// meshes. Chunks despawned before getting one (because the camera moved on and
+/// their replacements were meshed first) count as done, so it always fires.
+fn check_bodies_loaded(
+    mut commands: Commands,
+    mut loaded: EventWriter<BodyLoaded>,
+    mut body_query: Query<(Entity, &ChunkRefs, &mut InitialLoad)>,
+    has_mesh: Query<Has<Mesh3d>>,
+) {
+    for (body_entity, chunk_refs, mut initial_load) in body_query.iter_mut() {
+        let InitialLoad { pending, satisfied } = &mut *initial_load;
+        let Some(pending) = pending else {
+            continue;
+        };
+
+        pending.retain(|index| {
+            let waiting = match chunk_refs.0.get(index) {
+                Some(ChunkRef::Active(entity) | ChunkRef::Cleanup(entity)) => {
+                    !has_mesh.get(*entity).unwrap_or(false)
+                }
+                None => false,
+            };
+            if !waiting {
+                *satisfied += 1;
+            }
+            waiting
+        });
+
+        if pending.is_empty() {
+            loaded.send(BodyLoaded { body: body_entity });
+            commands.entity(body_entity).remove::<InitialLoad>();
+        }
+    }
+}
+
+/// Logs each [`BodyLoaded`], i.e. when a body is done popping in
+fn log_loaded_bodies(mut loaded: EventReader<BodyLoaded>, names: Query<&Name>) {
+    for BodyLoaded { body } in loaded.read() {
+        match names.get(*body) {
+            Ok(name) => info!("{name} loaded"),
+            Err(_) => info!("Body {body} loaded"),
+        }
+    }
+}
+
+/// The world space bounds of the octree node at `index`, under `transform`
+fn node_bounds(octree: &Octree, index: &[u8], transform: &Transform) -> Option<Aabb3d> {
+    let center = octree.center_for_index(index)?;
+    let half_size = octree.bounds_for_index(index)?;
+
+    let mut min = Vec3::splat(f32::INFINITY);
+    let mut max = Vec3::splat(f32::NEG_INFINITY);
+    for corner in 0..8 {
+        let offset = Vec3::new(
+            if corner & 1 == 0 { -1.0 } else { 1.0 },
+            if corner & 2 == 0 { -1.0 } else { 1.0 },
+            if corner & 4 == 0 { -1.0 } else { 1.0 },
+        );
+        let point = transform.transform_point(center + offset * half_size);
+        min = min.min(point);
+        max = max.max(point);
+    }
+
+    Some(Aabb3d {
+        min: min.into(),
+        max: max.into(),
+    })
+}
+
+/// A body whose geometry is still being loaded (or built) in the background,
+/// drawn as a plain sphere until then
+#[derive(Component)]
+pub struct LoadingBody {
+    task: Task<LoadedBody>,
+    placeholder: Entity,
+}
+
+/// A body, its colors and its terrain if it was built from a preset
+type LoadedBody = (Body, HexColors, Option<Terrain>);
+
+/// Spawns the planet. Its geometry is loaded from the cache (see
+/// [`crate::geometry_cache`]) and terrained from a preset off the main thread
+/// so the window comes up straight away. Without a task pool (in tests) it is
+/// loaded right here.
 fn setup_bodies(
     mut commands: Commands,
-    mut flat_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let preset = PlanetPreset::default();
+    let params = GeometryParams {
+        subdivisions: preset.subdivisions,
+        octree: OctreeConfig::default(),
+    };
+    // The octree is built over the undisplaced cells, which terrain only
+    // nudges, so the cached one still fits
+    let load_planet = move || {
+        let (geometry, octree) = load_or_build(params, Path::new(GEOMETRY_CACHE_PATH));
+        let (geometry, colors, terrain) = geometry.with_preset(&preset);
+        (Body::from_parts(geometry, octree), colors, Some(terrain))
+    };
+    let planet = commands
+        .spawn((Name::new("Planet"), CameraTarget { radius: 32.0 }))
+        .id();
+    let transform = Transform::from_scale(Vec3::splat(32.));
+    load_body(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        planet,
+        transform,
+        load_planet,
+    );
+}
+
+/// Gives `entity` everything a body at `transform` needs, with the body and
+/// its colors from `load`, which is run on the async compute pool if there is one
+fn load_body(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    entity: Entity,
+    transform: Transform,
+    load: impl FnOnce() -> LoadedBody + Send + 'static,
 ) {
-    let geom = crate::geometry_data::GeometryData::icosahedron()
-        .subdivide_n(8)
-        .slerp()
-        .recell()
-        .dual();
-
-    let body = Body::new(geom);
-
-    commands.spawn((
-        HexColors {
-            colors: vec![Color::srgba(1.0, 0.0, 0.0, 1.0); body.geometry.cells.len()],
-            ..Default::default()
-        },
-        body,
+    commands.entity(entity).insert((
         ChunkStorage::default(),
         ChunkRefs::default(),
-        Name::new("Planet"),
-        Transform::default()
-            .with_translation(Vec3::ZERO)
-            .with_scale(Vec3::splat(32.)),
-        CameraTarget { radius: 32.0 },
+        InitialLoad::default(),
+        transform,
     ));
 
-    let material = create_material(&mut flat_materials);
-    commands.insert_resource(HexsphereMaterial(material));
+    match AsyncComputeTaskPool::try_get() {
+        Some(thread_pool) => {
+            let placeholder = commands
+                .spawn((
+                    Mesh3d(meshes.add(Sphere::new(1.0).mesh().ico(5).expect("5 is few enough"))),
+                    MeshMaterial3d(materials.add(Color::srgb(0.3, 0.3, 0.3))),
+                    transform,
+                    Name::new("Body placeholder"),
+                ))
+                .id();
+            commands.entity(entity).insert(LoadingBody {
+                task: thread_pool.spawn(async move { load() }),
+                placeholder,
+            });
+        }
+        None => insert_loaded(commands.entity(entity), load()),
+    }
+}
+
+fn insert_loaded(mut entity: EntityCommands, (body, colors, terrain): LoadedBody) {
+    entity.insert((body, colors));
+    if let Some(terrain) = terrain {
+        entity.insert(terrain);
+    }
+}
+
+/// Hands bodies their geometry once it has loaded, replacing the placeholder.
+/// The pov is forgotten so the new body is chunked straight away, even if the
+/// camera hasn't moved since.
+fn finish_loading_bodies(
+    mut commands: Commands,
+    mut loading: Query<(Entity, &mut LoadingBody)>,
+    mut povs: Query<&mut POV>,
+) {
+    for (entity, mut loading) in loading.iter_mut() {
+        let Some(loaded) = block_on(future::poll_once(&mut loading.task)) else {
+            continue;
+        };
+        commands.entity(loading.placeholder).despawn_recursive();
+        commands.entity(entity).remove::<LoadingBody>();
+        insert_loaded(commands.entity(entity), loaded);
+        for mut pov in povs.iter_mut() {
+            *pov = POV::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn chunks_are_meshed_without_a_task_pool() {
+        // No `TaskPoolPlugin`, so `generate_meshes` has to build chunks inline
+        let mut app = App::new();
+        app.init_resource::<ChunkMeshConfig>()
+            .init_resource::<ChunkBufferPool>()
+            .init_resource::<MaxActiveChunks>()
+            .init_resource::<ForcedLod>()
+            .init_resource::<Assets<Mesh>>()
+            .insert_resource(HexsphereMaterial(Handle::default()))
+            .add_systems(
+                Update,
+                (
+                    calculate_povs,
+                    generate_meshes,
+                    poll_mesh_tasks,
+                    spawn_ready_chunks,
+                )
+                    .chain(),
+            );
+
+        let geometry = GeometryData::icosahedron()
+            .subdivide_n(2)
+            .unwrap()
+            .slerp()
+            .recell()
+            .dual()
+            .unwrap();
+        app.world_mut().spawn((
+            Body::new(geometry),
+            ChunkStorage::default(),
+            ChunkRefs::default(),
+            Transform::IDENTITY,
+        ));
+        app.world_mut().spawn((
+            Transform::from_xyz(0.0, 0.0, 3.0),
+            Projection::default(),
+            POV::default(),
+        ));
+
+        let mut meshed = 0;
+        for _ in 0..5 {
+            app.update();
+            meshed = app
+                .world_mut()
+                .query_filtered::<(), (With<Chunk>, With<Mesh3d>)>()
+                .iter(app.world())
+                .count();
+            if meshed > 0 {
+                break;
+            }
+        }
+        assert!(meshed > 0);
+    }
+
+    #[test]
+    fn deferred_chunks_wait_for_room() {
+        let mut app = App::new();
+        app.insert_resource(MaxActiveChunks(2))
+            .init_resource::<ForcedLod>()
+            .add_systems(Update, calculate_povs);
+
+        let geometry = GeometryData::icosahedron()
+            .subdivide_n(2)
+            .unwrap()
+            .slerp()
+            .recell()
+            .dual()
+            .unwrap();
+        app.world_mut().spawn((
+            Body::new(geometry),
+            ChunkRefs::default(),
+            Transform::IDENTITY,
+        ));
+        let camera = Transform::from_xyz(0.0, 0.0, 3.0);
+        app.world_mut()
+            .spawn((camera, Projection::default(), POV::default()));
+
+        let mut chunks = app.world_mut().query_filtered::<Entity, With<Chunk>>();
+        for _ in 0..3 {
+            app.update();
+            assert_eq!(chunks.iter(app.world()).count(), 2);
+            // Hitting the cap leaves the pov alone, so nothing reruns
+            let pov = app.world_mut().query::<&POV>().single(app.world()).0;
+            assert_eq!(pov, camera.translation);
+        }
+
+        // Clean up one chunk the way `despawn_chunks` would
+        let first = chunks.iter(app.world()).next().unwrap();
+        let index = app.world().get::<Chunk>(first).unwrap().index.clone();
+        let mut refs = app.world_mut().query::<&mut ChunkRefs>();
+        refs.single_mut(app.world_mut()).0.remove(&index);
+        app.world_mut().despawn(first);
+        assert_eq!(chunks.iter(app.world()).count(), 1);
+        app.update();
+        assert_eq!(chunks.iter(app.world()).count(), 2);
+    }
+
+    #[test]
+    fn chunk_index_navigation() {
+        let root: &[u8] = &[];
+        let index: &[u8] = &[0, 3, 1];
+
+        assert_eq!(index.parent(), Some(vec![0, 3]));
+        assert_eq!(root.parent(), None);
+        assert_eq!(
+            index.ancestors().collect::<Vec<_>>(),
+            vec![vec![0, 3], vec![0], vec![]]
+        );
+        assert_eq!(root.ancestors().count(), 0);
+
+        assert!(root.is_ancestor_of(index));
+        assert!([0, 3].is_ancestor_of(index));
+        assert!(!index.is_ancestor_of(&[0, 3]));
+        assert!(![0, 4].is_ancestor_of(index));
+        // Nothing is its own ancestor, not even the root
+        assert!(!index.is_ancestor_of(index));
+        assert!(!root.is_ancestor_of(root));
+
+        assert_eq!(common_prefix(index, &[0, 3, 7]), vec![0, 3]);
+        assert_eq!(common_prefix(index, &[0]), vec![0]);
+        assert_eq!(common_prefix(index, index), index.to_vec());
+        // Different top level children only have the root in common
+        assert_eq!(common_prefix(index, &[5, 3, 1]), ChunkIndex::new());
+        assert_eq!(common_prefix(root, index), ChunkIndex::new());
+    }
+
+    #[test]
+    fn chunk_index_strings_round_trip() {
+        assert_eq!(chunk_index_to_string(&[]), "root");
+        assert_eq!(chunk_index_to_string(&[0, 3, 1]), "root/0/3/1");
+        for index in [vec![], vec![0], vec![0, 3, 1], vec![7; 12]] {
+            let path = chunk_index_to_string(&index);
+            assert_eq!(parse_chunk_index(&path), Some(index));
+        }
+        for path in ["", "root/", "root/8", "root/1/x", "root//1", "tree/1"] {
+            assert_eq!(parse_chunk_index(path), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn chunk_at_direction_picks_the_most_detailed_chunk() {
+        let mut world = World::new();
+        let transform = Transform::from_xyz(5.0, 0.0, 0.0).with_scale(Vec3::splat(2.0));
+        let body = world.spawn(transform).id();
+        let other = world.spawn(Transform::default()).id();
+
+        let mut spawn_chunk = |body, index: ChunkIndex, min: Vec3, max: Vec3| {
+            world
+                .spawn((
+                    Chunk { body, index },
+                    ChunkBounds(Aabb3d::new((min + max) / 2.0, (max - min) / 2.0)),
+                ))
+                .id()
+        };
+        let whole = spawn_chunk(
+            body,
+            vec![],
+            Vec3::new(3.0, -2.0, -2.0),
+            Vec3::new(7.0, 2.0, 2.0),
+        );
+        // The +x half, still there while the LOD changes
+        let east = spawn_chunk(
+            body,
+            vec![1],
+            Vec3::new(5.0, -2.0, -2.0),
+            Vec3::new(7.0, 2.0, 2.0),
+        );
+        // Another body that happens to overlap
+        spawn_chunk(
+            other,
+            vec![1, 1],
+            Vec3::new(3.0, -2.0, -2.0),
+            Vec3::new(7.0, 2.0, 2.0),
+        );
+
+        let mut at = |dir: Vec3| {
+            world
+                .run_system_once(move |chunks: Query<(Entity, &Chunk, &ChunkBounds)>| {
+                    chunk_at_direction(&chunks, body, &transform, dir)
+                })
+                .unwrap()
+        };
+        assert_eq!(at(Vec3::X), Some(east));
+        assert_eq!(at(Vec3::NEG_X), Some(whole));
+        assert_eq!(at(Vec3::new(-1.0, 1.0, 0.0)), Some(whole));
+    }
 }
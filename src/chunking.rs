@@ -1,4 +1,3 @@
-use bevy::pbr::{ExtendedMaterial, OpaqueRendererMethod};
 use bevy::prelude::*;
 use bevy::time::common_conditions::on_timer;
 use bevy::utils::HashMap;
@@ -8,14 +7,16 @@ use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
-use crate::camera::CameraTarget;
-use crate::flatnormal::FlatNormalMaterial;
+use crate::camera::{effective_fov, CameraTarget};
+use crate::chunk_storage::chunk_index_to_string;
+use crate::flatnormal::HexsphereMaterial;
 use crate::geometry_data::GeometryData;
-use crate::octree::{Octree, Point};
+use crate::octree::{Octree, OctreeConfig};
 
 pub(crate) type ChunkIndex = Vec<u8>;
 
 const NUM_WORKERS: usize = 16;
+const SIMPLIFY_THRESHOLD: usize = 256;
 
 #[derive(Debug)]
 struct ChunkRequest {
@@ -49,7 +50,10 @@ pub struct ChunkManager {
     /// Contains all chunk-specific data.
     pub chunk_data: BTreeMap<ChunkIndex, ChunkData>,
 
+    /// Unit direction from the body center towards the camera
     pub pov: Vec3,
+    /// Vertical field of view of the camera, narrower when zoomed in
+    pub fov: f32,
 
     /// Indices for which we have requested geometry and not yet received a response.
     pub active_requests: BTreeSet<ChunkIndex>,
@@ -101,15 +105,12 @@ impl ChunkManager {
                     // Build chunk geometry
                     // 1) get which cells belong to that chunk
                     if let Some(cells) = octree.get_cells_for_index(&index) {
-                        // 2) build geometry data
-                        let (local_geometry, cell_map) = geometry.sub_geometry(&cells);
+                        // 2) build geometry data and its mesh
+                        let (cells, _, _, mesh) =
+                            geometry.build_chunk_mesh(&cells, SIMPLIFY_THRESHOLD);
 
                         // 3) send back
-                        let _ = response_sender.send(ChunkResponse {
-                            index,
-                            mesh: local_geometry.mesh(),
-                            cells,
-                        });
+                        let _ = response_sender.send(ChunkResponse { index, mesh, cells });
                     }
                 }
             });
@@ -121,17 +122,19 @@ impl ChunkManager {
     }
 
     pub fn new(geometry: GeometryData) -> Self {
-        let capacity = 128;
-        let bounds = 1.0;
-        let center = Vec3::ZERO;
-        let mut octree = Octree::new(capacity, center, bounds, 0, vec![]);
-
-        for (cell_index, &position) in geometry.cell_normals.iter().enumerate() {
-            octree.insert(Point {
-                position,
-                value: cell_index,
-            });
-        }
+        Self::new_with_config(
+            geometry,
+            OctreeConfig {
+                capacity: 128,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Like [`Self::new`], with the octree (and so the chunks) built as
+    /// `config` says
+    pub fn new_with_config(geometry: GeometryData, config: OctreeConfig) -> Self {
+        let octree = config.build(&geometry.cell_normals);
 
         let geometry = Arc::new(geometry);
         let octree = Arc::new(octree);
@@ -156,6 +159,7 @@ impl ChunkManager {
             receiver: (response_sender, response_recv),
             workers,
             pov: Vec3::ZERO,
+            fov: 0.0,
             active_chunks: BTreeSet::new(),
         }
     }
@@ -180,18 +184,25 @@ impl ChunkManager {
         self.workers = still_alive;
     }
 
-    pub fn update_pov(&mut self, new_pov: Vec3) {
-        if self.pov == new_pov {
+    /// Requests the chunks needed when looking at the body from `direction`
+    /// (a unit vector from its center) through a camera with vertical field of
+    /// view `fov`. Chunks nearest `direction` get the most detail, and narrowing
+    /// `fov` (zooming in) only ever adds detail.
+    pub fn update_pov(&mut self, direction: Vec3, fov: f32) {
+        if self.pov == direction && self.fov == fov {
             return;
         }
-        self.pov = new_pov;
+        self.pov = direction;
+        self.fov = fov;
 
         // 1) Octree to find chunk indices near new POV
-        let needed_indices = self.octree.get_chunk_indices(1, new_pov, 1.0);
+        let needed_indices =
+            self.octree
+                .get_chunk_indices(self.geometry.cells.len(), direction, fov.sqrt());
 
         // Create requests for newly needed
         for idx in &needed_indices {
-            dbg!(&idx);
+            debug!("Chunk {} is needed", chunk_index_to_string(idx));
             let have_mesh = self
                 .chunk_data
                 .get(idx)
@@ -210,22 +221,30 @@ impl ChunkManager {
     }
 }
 
-pub fn cleanup_old_handles(mut query: Query<&mut ChunkManager>) {
+/// Forgets chunks that are neither wanted nor spawned. Dropping their
+/// `ChunkData` drops the last strong mesh handle, so the mesh asset is freed
+/// and `Assets<Mesh>` stays bounded no matter how long the camera wanders.
+pub fn cleanup_old_handles(mut query: Query<&mut ChunkManager>, meshes: Res<Assets<Mesh>>) {
     let Ok(mut manager) = query.get_single_mut() else {
         return;
     };
-    println!("\n\n");
-    manager.print_self();
-
-    manager
-        .chunk_data
-        .retain(|index, chunk_data| !(chunk_data.entity.is_none()));
 
-    // manager.chunk_data.shrink_to_fit();
-    // manager.active_chunks.shrink_to_fit();
-    // manager.active_requests.shrink_to_fit();
-
-    manager.print_self();
+    let ChunkManager {
+        chunk_data,
+        active_chunks,
+        ..
+    } = &mut *manager;
+
+    let before = chunk_data.len();
+    chunk_data
+        .retain(|index, chunk_data| chunk_data.entity.is_some() || active_chunks.contains(index));
+
+    debug!(
+        "Cleaned up {} chunk handles, {} remain ({} meshes loaded)",
+        before - chunk_data.len(),
+        chunk_data.len(),
+        meshes.len()
+    );
 }
 
 pub fn process_chunk_responses_system(
@@ -261,34 +280,17 @@ pub fn process_chunk_responses_system(
     }
 }
 
-fn create_material(
-    flat_materials: &mut ResMut<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>,
-) -> Handle<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>> {
-    let extended_material = ExtendedMaterial {
-        base: StandardMaterial {
-            opaque_render_method: OpaqueRendererMethod::Auto,
-            ..Default::default()
-        },
-        extension: FlatNormalMaterial {},
-    };
-    flat_materials.add(extended_material)
-}
-
-#[derive(Resource)]
-pub struct HexsphereMaterial(pub Handle<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>);
-
 pub(crate) fn update_chunk_pov_system(
     mut query: Query<&mut ChunkManager>,
     camera_query: Query<(&Transform, &Projection), With<Camera>>,
 ) {
     if let Ok((camera_transform, projection)) = camera_query.get_single() {
         if let Ok(mut manager) = query.get_single_mut() {
-            if let Projection::Perspective(projection) = projection {
-                manager.update_pov(
-                    camera_transform.translation.normalize()
-                        + camera_transform.translation.normalize() * projection.fov / 5.0,
-                );
-            }
+            let translation = camera_transform.translation;
+            manager.update_pov(
+                translation.normalize(),
+                effective_fov(projection, translation.length()),
+            );
         }
     }
 }
@@ -307,6 +309,7 @@ pub fn process_chunk_backlog_system(
         octree,
         chunk_data,
         pov,
+        fov: _,
         active_requests,
         sender,
         receiver,
@@ -345,7 +348,7 @@ pub fn process_chunk_backlog_system(
                     Mesh3d(data.mesh_handle.clone().unwrap()),
                     MeshMaterial3d(material.0.clone()),
                     Transform::from_scale(Vec3::splat(32.0)),
-                    Name::new(format!("Chunk {:?}", idx)),
+                    Name::new(format!("Chunk {}", chunk_index_to_string(idx))),
                 ));
             }
         }
@@ -360,24 +363,20 @@ pub fn check_workers_system(mut query: Query<&mut ChunkManager>) {
     }
 }
 
-pub fn setup_demo_chunk_manager(
-    mut commands: Commands,
-    mut flat_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>,
-) {
+pub fn setup_demo_chunk_manager(mut commands: Commands) {
     let geom = crate::geometry_data::GeometryData::icosahedron()
         .subdivide_n(8)
+        .expect("the icosahedron is well formed")
         .slerp()
         .recell()
         .dual()
+        .expect("recell puts a cell around every vertex")
         .duplicate();
 
     let manager = ChunkManager::new(geom);
 
     commands.spawn((manager, Name::new("ChunkManager")));
     commands.spawn((Transform::IDENTITY, CameraTarget { radius: 32.0 }));
-
-    let material = create_material(&mut flat_materials);
-    commands.insert_resource(HexsphereMaterial(material));
 }
 
 pub struct ChunkManagerDemoPlugin;
@@ -391,8 +390,83 @@ impl Plugin for ChunkManagerDemoPlugin {
                     process_chunk_responses_system.after(update_chunk_pov_system),
                     process_chunk_backlog_system.after(process_chunk_responses_system),
                     check_workers_system,
-                    // cleanup_old_handles.run_if(on_timer(Duration::from_secs(10))),
+                    cleanup_old_handles.run_if(on_timer(Duration::from_secs(10))),
                 ),
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::io::AssetSourceBuilders;
+    use bevy::asset::AssetServerMode;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::render::mesh::PrimitiveTopology;
+    use bevy::render::render_asset::RenderAssetUsages;
+
+    #[test]
+    fn cleanup_keeps_loaded_meshes_bounded() {
+        let mut sources = AssetSourceBuilders::default();
+        sources.init_default_source("assets", None);
+        let mut world = World::new();
+        world.insert_resource(AssetServer::new(
+            sources.build_sources(false, false),
+            AssetServerMode::Unprocessed,
+            false,
+        ));
+        world.init_resource::<Assets<Mesh>>();
+        let manager = world
+            .spawn(ChunkManager::new(GeometryData::subdivided_to_cells(162)))
+            .id();
+
+        for i in 0..64u8 {
+            let index = vec![i % 8, i / 8];
+            let mesh_handle = world.resource_mut::<Assets<Mesh>>().add(Mesh::new(
+                PrimitiveTopology::TriangleList,
+                RenderAssetUsages::default(),
+            ));
+
+            let mut chunk_manager = world.get_mut::<ChunkManager>(manager).unwrap();
+            chunk_manager.chunk_data.insert(
+                index.clone(),
+                ChunkData {
+                    mesh_handle: Some(mesh_handle),
+                    entity: None,
+                    cells: None,
+                },
+            );
+            chunk_manager.active_chunks = BTreeSet::from([index]);
+
+            world.run_system_once(cleanup_old_handles).unwrap();
+            world.run_system_once(Assets::<Mesh>::track_assets).unwrap();
+
+            assert_eq!(
+                world.get::<ChunkManager>(manager).unwrap().chunk_data.len(),
+                1
+            );
+            assert!(world.resource::<Assets<Mesh>>().len() <= 1);
+        }
+    }
+
+    #[test]
+    fn zooming_in_never_requests_less_detail() {
+        let mut manager = ChunkManager::new(GeometryData::subdivided_to_cells(2562));
+        let direction = Vec3::new(0.3, 0.8, -0.5).normalize();
+
+        manager.update_pov(direction, std::f32::consts::FRAC_PI_2);
+        let widest = manager.active_chunks.len();
+        let mut previous = widest;
+        for step in 1..16 {
+            let fov = std::f32::consts::FRAC_PI_2 * 0.8f32.powi(step);
+            manager.update_pov(direction, fov);
+            let count = manager.active_chunks.len();
+            assert!(
+                count >= previous,
+                "fov {fov} wants {count} chunks, down from {previous}"
+            );
+            previous = count;
+        }
+        assert!(previous > widest);
+    }
+}
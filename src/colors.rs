@@ -4,29 +4,251 @@ use std::{
 };
 
 use bevy::{
-    pbr::ExtendedMaterial, prelude::*, render::mesh::VertexAttributeValues,
+    math::Affine3A,
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::{
+        mesh::VertexAttributeValues,
+        primitives::{Aabb, Frustum},
+    },
     utils::tracing::instrument::WithSubscriber,
 };
+use image::{DynamicImage, GenericImageView};
 use rand::{random_range, seq::index};
 
 use crate::{
-    chunk_storage::{Body, Chunk, ChunkCells},
-    flatnormal::FlatNormalMaterial,
+    chunk_storage::{Body, Chunk, ChunkBounds, ChunkCells, LineChunk, POV},
+    flatnormal::{FlatNormalMaterial, ATTRIBUTE_EMISSIVE},
+    geometry_data::GeometryData,
+    preset::Terrain,
 };
 
 /// Represents a planets hex colours
-#[derive(Component, Default)]
-pub(crate) struct HexColors {
-    // The color of each cell
-    pub(crate) colors: Vec<Color>,
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct HexColors {
+    // The color of each cell. Any color space will do, as `update_mesh_colors`
+    // converts to linear when writing the mesh, but colors that are mixed
+    // together should be mixed as `LinearRgba` so the blend matches light.
+    pub colors: Vec<Color>,
     // A list of indices into changed cells
     pub(crate) changed: BTreeSet<usize>,
+    // Optional per cell ambient occlusion (see `GeometryData::bake_ao`),
+    // darkening each cell by (1 - ao). Empty means no occlusion.
+    pub(crate) occlusion: Vec<f32>,
+    // Optional per cell fog of war, see `CellVisibility`. Empty means every
+    // cell is visible.
+    pub(crate) visibility: Vec<CellVisibility>,
+    // A list of indices into cells whose visibility or glow changed, whose chunks
+    // repaint straight away rather than waiting for enough changes to pile up
+    pub(crate) visibility_changed: BTreeSet<usize>,
+    // Optional per cell glow (lava and the like), with the strength in alpha.
+    // Empty means nothing glows.
+    pub(crate) emissive: Vec<LinearRgba>,
+}
+
+/// How much of a cell the player can see. Cells only ever move up from
+/// `Hidden`, though `Visible` ones drop back to `Explored` once out of sight.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Reflect)]
+#[repr(u8)]
+pub(crate) enum CellVisibility {
+    /// Never seen, drawn nearly black
+    #[default]
+    Hidden,
+    /// Seen before but not right now, drawn dim and washed out
+    Explored,
+    /// In sight, drawn as is
+    Visible,
+}
+
+impl HexColors {
+    /// Colors each cell by sampling an equirectangular image at the cells
+    /// latitude/longitude. The top row of the image is the north (+Y) pole.
+    pub fn from_image(img: &DynamicImage, geom: &GeometryData) -> HexColors {
+        let (width, height) = img.dimensions();
+
+        let colors: Vec<Color> = geom
+            .cell_normals
+            .iter()
+            .map(|normal| {
+                let n = normal.normalize();
+                let u = 0.5 + n.z.atan2(n.x) / std::f32::consts::TAU;
+                let v = 0.5 - n.y.clamp(-1.0, 1.0).asin() / std::f32::consts::PI;
+
+                let x = ((u * width as f32) as u32).min(width.saturating_sub(1));
+                let y = ((v * height as f32) as u32).min(height.saturating_sub(1));
+                let [r, g, b, a] = img.get_pixel(x, y).0;
+                Color::srgba_u8(r, g, b, a)
+            })
+            .collect();
+
+        HexColors {
+            // Everything is new, so every chunk should pick it up
+            changed: (0..colors.len()).collect(),
+            colors,
+            ..Default::default()
+        }
+    }
+}
+
+impl HexColors {
+    /// The fog of war state of `cell`
+    pub(crate) fn visibility(&self, cell: usize) -> CellVisibility {
+        if self.visibility.is_empty() {
+            CellVisibility::Visible
+        } else {
+            self.visibility[cell]
+        }
+    }
+
+    /// Moves `cell` to `state`, repainting it if that changes anything. Cells
+    /// can't be hidden again once seen, so asking for `Hidden` does nothing.
+    /// The first call turns on fog of war for the whole body.
+    pub(crate) fn set_visibility(&mut self, cell: usize, state: CellVisibility) {
+        if self.visibility.is_empty() {
+            self.visibility = vec![CellVisibility::Hidden; self.colors.len()];
+            self.changed.extend(0..self.colors.len());
+            self.visibility_changed.extend(0..self.colors.len());
+        }
+
+        let current = &mut self.visibility[cell];
+        let state = match state {
+            CellVisibility::Hidden => *current,
+            state => state,
+        };
+        if *current != state {
+            *current = state;
+            self.changed.insert(cell);
+            self.visibility_changed.insert(cell);
+        }
+    }
+
+    /// Makes every cell of `body` within `radius` of `position` (in the bodies
+    /// local space, where it is a unit sphere) visible, e.g. around a unit
+    pub(crate) fn reveal_within(&mut self, body: &Body, position: Vec3, radius: f32) {
+        for cell in body.octree.within_distance(position, radius) {
+            self.set_visibility(cell, CellVisibility::Visible);
+        }
+    }
+
+    /// Makes `cell` glow with `color`, the alpha being the strength. A strength
+    /// of 0 stops it glowing.
+    pub(crate) fn set_emissive(&mut self, cell: usize, color: LinearRgba) {
+        if self.emissive.is_empty() {
+            self.emissive = vec![LinearRgba::NONE; self.colors.len()];
+        }
+
+        if self.emissive[cell] != color {
+            self.emissive[cell] = color;
+            self.changed.insert(cell);
+            self.visibility_changed.insert(cell);
+        }
+    }
+
+    /// Drops every visible cell back to explored, so the next round of
+    /// `reveal_within` calls can decide what is still in sight
+    pub(crate) fn fade_visible(&mut self) {
+        for cell in 0..self.visibility.len() {
+            if self.visibility[cell] == CellVisibility::Visible {
+                self.set_visibility(cell, CellVisibility::Explored);
+            }
+        }
+    }
+
+    /// Turns fog of war off again, showing every cell as it is
+    pub(crate) fn clear_visibility(&mut self) {
+        if !self.visibility.is_empty() {
+            self.visibility.clear();
+            self.changed.extend(0..self.colors.len());
+            self.visibility_changed.extend(0..self.colors.len());
+        }
+    }
+}
+
+/// Whether fog of war is on, see [`reveal_under_camera`]
+#[derive(Resource, Default, Clone, Copy)]
+pub(crate) struct FogOfWar(pub(crate) bool);
+
+/// How far around the point under the camera cells are revealed, in body radii
+const REVEAL_RADIUS: f32 = 0.35;
+
+/// Turns fog of war on and off
+pub(crate) fn toggle_fog_of_war(
+    mut fog: ResMut<FogOfWar>,
+    mut hexes: Query<&mut HexColors>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if !input.just_pressed(KeyCode::KeyH) {
+        return;
+    }
+
+    fog.0 = !fog.0;
+    if !fog.0 {
+        for mut colors in hexes.iter_mut() {
+            colors.clear_visibility();
+        }
+    }
+}
+
+/// Reveals the cells of each body under the camera while fog of war is on,
+/// standing in for the units that would in a game. Cells the camera has moved
+/// on from stay explored.
+pub(crate) fn reveal_under_camera(
+    fog: Res<FogOfWar>,
+    camera: Query<&Transform, With<POV>>,
+    mut bodies: Query<(&mut HexColors, &Body, &Transform)>,
+) {
+    if !fog.0 {
+        return;
+    }
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    for (mut colors, body, transform) in bodies.iter_mut() {
+        let local = transform
+            .compute_affine()
+            .inverse()
+            .transform_point3(camera.translation);
+        let Some(dir) = local.try_normalize() else {
+            continue;
+        };
+        colors.fade_visible();
+        colors.reveal_within(body, dir, REVEAL_RADIUS);
+    }
+}
+
+/// The linear color a cell should be drawn with, after occlusion and fog of war
+fn shaded_color(
+    colors: &[Color],
+    occlusion: &[f32],
+    visibility: &[CellVisibility],
+    cell: usize,
+) -> [f32; 4] {
+    let [r, g, b, a] = colors[cell].to_linear().to_f32_array();
+    let light = 1.0 - occlusion.get(cell).copied().unwrap_or(0.0);
+    let [r, g, b] = [r * light, g * light, b * light];
+
+    match visibility
+        .get(cell)
+        .copied()
+        .unwrap_or(CellVisibility::Visible)
+    {
+        CellVisibility::Hidden => [r * 0.05, g * 0.05, b * 0.05, a],
+        CellVisibility::Explored => {
+            // Halfway to grey, at half the brightness
+            let grey = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            [(r + grey) * 0.25, (g + grey) * 0.25, (b + grey) * 0.25, a]
+        }
+        CellVisibility::Visible => [r, g, b, a],
+    }
 }
 
 #[derive(Component)]
 pub(crate) struct NeedsColoring;
 
-pub(crate) fn randomize_colors(mut hexes: Query<(&mut HexColors)>) {
+/// Recolors random cells of the bodies not colored by their terrain
+pub(crate) fn randomize_colors(mut hexes: Query<(&mut HexColors), Without<Terrain>>) {
     // Pick a handful of random hexes
     // add them to the changed list, and update the color to be random
 
@@ -36,51 +258,94 @@ pub(crate) fn randomize_colors(mut hexes: Query<(&mut HexColors)>) {
     // let dark = [1.0, 0.0, 0.0, 1.0];
     // let bright = [0.0, 1.0, 0.0, 1.0];
 
-    let dark = [1.0, 0.0, 1.0, 1.0];
-    let bright = [0.0, 1.0, 1.0, 1.0];
-
     let mut rng = rand::rng();
     for mut colors in hexes.iter_mut() {
-        let samples = index::sample(&mut rng, colors.colors.len(), 10000);
+        let count = colors.colors.len().min(10000);
+        let samples = index::sample(&mut rng, colors.colors.len(), count);
 
         for sample in samples {
             let t = random_range(0.0..=1.0f32).powi(2);
 
             // let t = (noisy_bevy::simplex_noise_3d(Vec3::from(*hex) * 4.0) + 1.0) / 2.0;
 
-            // Linear interpolation for each channel:
-            let mut r = dark[0] + t * (bright[0] - dark[0]);
-            let mut g = dark[1] + t * (bright[1] - dark[1]);
-            let mut b = dark[2] + t * (bright[2] - dark[2]);
-            let a = 1.0; // Keep alpha at 1.0
-
-            // if t < 0.4 {
-            //     r = 0.0;
-            //     g = 0.0;
-            //     b = 1.0;
-            // } else if t < 0.8 {
-            //     r = 0.0;
-            //     g = 0.5;
-            //     b = 0.0;
-            // } else {
-            //     r = 0.9;
-            //     g = 0.9;
-            //     b = 0.91;
-            // }
-
-            colors.colors[sample] = Color::from(LinearRgba::from_f32_array([r, g, b, a]));
+            colors.colors[sample] = random_color_at(t);
             colors.changed.insert(sample);
         }
     }
 }
 
+/// The color `randomize_colors` gives a cell at `t`, from 0 to 1. The ends
+/// are picked as sRGB, like any color from a color picker, and mixed in linear
+/// space, as mixing sRGB values directly gives dark, muddy midpoints.
+fn random_color_at(t: f32) -> Color {
+    let dark = LinearRgba::from(Srgba::rgb(1.0, 0.0, 1.0));
+    let bright = LinearRgba::from(Srgba::rgb(0.0, 1.0, 1.0));
+    Color::from(dark.mix(&bright, t))
+}
+
+/// Sets a handful of random cells glowing like lava, to show off emissive cells
+pub(crate) fn ignite_lava(mut hexes: Query<&mut HexColors>, input: Res<ButtonInput<KeyCode>>) {
+    if !input.just_pressed(KeyCode::KeyV) {
+        return;
+    }
+
+    let mut rng = rand::rng();
+    for mut colors in hexes.iter_mut() {
+        let count = colors.colors.len().min(20);
+        for cell in index::sample(&mut rng, colors.colors.len(), count) {
+            let strength = random_range(2.0..=6.0);
+            colors.set_emissive(cell, LinearRgba::new(1.0, 0.25, 0.02, strength));
+        }
+    }
+}
+
+/// Flags the chunks holding cells whose fog of war or glow changed for repainting
+pub(crate) fn mark_visibility_changes(
+    mut commands: Commands,
+    mut hexes: Query<&mut HexColors>,
+    chunks: Query<(Entity, &Chunk, &ChunkCells)>,
+) {
+    for (entity, chunk, chunk_cells) in chunks.iter() {
+        let Ok(hex_colors) = hexes.get(chunk.body) else {
+            continue;
+        };
+        if chunk_cells.cells.as_ref().is_some_and(|cells| {
+            hex_colors
+                .visibility_changed
+                .iter()
+                .any(|cell| cells.contains(cell))
+        }) {
+            commands.entity(entity).insert(NeedsColoring);
+        }
+    }
+
+    for mut hex_colors in hexes.iter_mut() {
+        if !hex_colors.visibility_changed.is_empty() {
+            hex_colors.visibility_changed.clear();
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct ColorCooldown(Timer);
 
+/// The least time between color updates of any one chunk. Zero updates chunks
+/// every frame, as far as the time budget allows.
+#[derive(Resource, Clone, Copy)]
+pub struct ColorUpdateCooldown(pub Duration);
+
+impl Default for ColorUpdateCooldown {
+    fn default() -> Self {
+        Self(Duration::from_millis(1000))
+    }
+}
+
 pub(crate) fn update_mesh_colors(
     mut commands: Commands,
     // mut materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    clock: Res<Time>,
+    cooldown: Res<ColorUpdateCooldown>,
     mut hexes: Query<(&mut HexColors, &Body)>,
     mut chunks: Query<(
         Entity,
@@ -89,24 +354,61 @@ pub(crate) fn update_mesh_colors(
         &ChunkCells,
         Option<&NeedsColoring>,
         Option<&mut ColorCooldown>,
+        Option<&LineChunk>,
+        Option<&ChunkBounds>,
     )>,
+    frustums: Query<&Frustum, With<POV>>,
 ) {
     let time = Instant::now();
-    for (entity, chunk, mesh3d, chunk_cells, needs_coloring, mut color_cooldown) in
-        chunks.iter_mut()
-    {
-        if let Some(timer) = &mut color_cooldown {
-            if !timer.0.finished() {
-                continue;
+
+    // Chunks in view go first, so what is on screen refreshes before the budget runs out
+    let frustum = frustums.get_single().ok();
+    let mut order: Vec<(bool, Entity)> = chunks
+        .iter()
+        .map(|(entity, .., bounds)| {
+            let visible = match (frustum, bounds) {
+                (Some(frustum), Some(ChunkBounds(bounds))) => frustum.intersects_obb(
+                    &Aabb::from_min_max(bounds.min.into(), bounds.max.into()),
+                    &Affine3A::IDENTITY,
+                    true,
+                    true,
+                ),
+                _ => false,
+            };
+            (!visible, entity)
+        })
+        .collect();
+    order.sort_by_key(|&(hidden, _)| hidden);
+
+    for (_, entity) in order {
+        let Ok((
+            entity,
+            chunk,
+            mesh3d,
+            chunk_cells,
+            needs_coloring,
+            mut color_cooldown,
+            line_chunk,
+            _,
+        )) = chunks.get_mut(entity)
+        else {
+            continue;
+        };
+        if !cooldown.0.is_zero() {
+            if let Some(timer) = &mut color_cooldown {
+                timer.0.tick(clock.delta());
+                if !timer.0.finished() {
+                    continue;
+                } else {
+                    timer.0.set_duration(cooldown.0);
+                    timer.0.reset();
+                    timer.0.unpause();
+                }
             } else {
-                timer.0.reset();
-                timer.0.unpause();
+                commands
+                    .entity(entity)
+                    .insert(ColorCooldown(Timer::new(cooldown.0, TimerMode::Once)));
             }
-        } else {
-            commands.entity(entity).insert(ColorCooldown(Timer::new(
-                Duration::from_millis(1000),
-                TimerMode::Once,
-            )));
         }
 
         if Instant::now().duration_since(time) > Duration::from_millis(3) {
@@ -125,7 +427,14 @@ pub(crate) fn update_mesh_colors(
         };
 
         // TODO cache this instead of recalcing for each chunk pls
-        let HexColors { colors, changed } = hex_colors.into_inner();
+        let HexColors {
+            colors,
+            changed,
+            occlusion,
+            visibility,
+            emissive,
+            ..
+        } = hex_colors.into_inner();
         let intersection: Vec<usize> = changed.intersection(cells).into_iter().copied().collect();
 
         if (intersection.len() as f32) < (0.75 * local_geometry.cells.len() as f32)
@@ -138,24 +447,42 @@ pub(crate) fn update_mesh_colors(
             continue;
         };
 
-        // Gather the colors of the chunk
+        // Reversed so the first cell wins in simplified chunks, where every cell maps to one
+        let mut local_to_cell = vec![0; local_geometry.cells.len()];
+        for (&cell, &local_cell) in cells_to_local.iter().rev() {
+            local_to_cell[local_cell] = cell;
+        }
+
+        // Gather the colors and glow of the chunk
+        let glow = |cell: usize| {
+            emissive
+                .get(cell)
+                .copied()
+                .unwrap_or(LinearRgba::NONE)
+                .to_f32_array()
+        };
         let mut new_colors = Vec::new();
-        let mut seen = BTreeSet::new();
-        for cell in cells {
-            if !seen.insert(cells_to_local[cell]) {
-                continue;
+        let mut new_emissive = Vec::new();
+        if line_chunk.is_some() {
+            // Border meshes are laid out in local cell order, two vertices per edge
+            for (local_cell, cell) in local_to_cell.into_iter().enumerate() {
+                let color = shaded_color(colors, occlusion, visibility, cell);
+                let edges = local_geometry.cell_border_edges(local_cell).len();
+                new_colors.extend(std::iter::repeat_n(color, edges * 2));
+                new_emissive.extend(std::iter::repeat_n(glow(cell), edges * 2));
             }
-            let color = colors[*cell].to_linear().to_f32_array();
-            let local_cell = cells_to_local[cell];
-            let faces = &local_geometry.cells[local_cell];
-
-            let mut seen_verts = BTreeSet::new();
-            for f in faces {
-                for v in local_geometry.faces[*f] {
-                    if !seen_verts.insert(v) {
-                        continue;
-                    };
-                    new_colors.push(color);
+        } else {
+            // Works for both duplicated and welded vertices, though welded ones
+            // on a cell border just take the color of whichever cell is last
+            new_colors = vec![[0.0; 4]; local_geometry.vertices.len()];
+            new_emissive = vec![[0.0; 4]; local_geometry.vertices.len()];
+            for (local_cell, cell) in local_to_cell.into_iter().enumerate() {
+                let color = shaded_color(colors, occlusion, visibility, cell);
+                for &f in &local_geometry.cells[local_cell] {
+                    for v in local_geometry.faces[f] {
+                        new_colors[v] = color;
+                        new_emissive[v] = glow(cell);
+                    }
                 }
             }
         }
@@ -164,6 +491,10 @@ pub(crate) fn update_mesh_colors(
             Mesh::ATTRIBUTE_COLOR,
             VertexAttributeValues::Float32x4(new_colors),
         );
+        mesh.insert_attribute(
+            ATTRIBUTE_EMISSIVE,
+            VertexAttributeValues::Float32x4(new_emissive),
+        );
 
         for i in intersection {
             changed.remove(&i);
@@ -225,3 +556,85 @@ pub(crate) fn update_mesh_colors(
     //     }
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revealing_cells_only_moves_them_up() {
+        let body = Body::new(GeometryData::subdivided_to_cells(162));
+        let cells = body.geometry.cells.len();
+        let mut colors = HexColors {
+            colors: vec![Color::WHITE; cells],
+            ..Default::default()
+        };
+        assert_eq!(colors.visibility(0), CellVisibility::Visible);
+
+        // The first change turns fog of war on, hiding everything
+        colors.set_visibility(0, CellVisibility::Hidden);
+        assert!((0..cells).all(|cell| colors.visibility(cell) == CellVisibility::Hidden));
+
+        let mut last = colors.visibility.clone();
+        for radius in [0.2, 0.5, 1.0, 2.5] {
+            colors.reveal_within(&body, Vec3::Y, radius);
+            assert!(colors
+                .visibility
+                .iter()
+                .zip(&last)
+                .all(|(now, then)| now >= then));
+            last = colors.visibility.clone();
+        }
+        assert!(last.iter().all(|&state| state == CellVisibility::Visible));
+
+        // Out of sight cells are only ever explored, never hidden again
+        colors.fade_visible();
+        colors.set_visibility(0, CellVisibility::Hidden);
+        assert!(colors
+            .visibility
+            .iter()
+            .all(|&state| state == CellVisibility::Explored));
+    }
+
+    #[test]
+    fn image_columns_map_to_hemispheres() {
+        // One red and one blue pixel, split at z = 0
+        let mut img = image::RgbaImage::new(2, 1);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+        let geom = GeometryData::subdivided_to_cells(642);
+        let colors = HexColors::from_image(&DynamicImage::ImageRgba8(img), &geom);
+
+        let red = Color::srgba_u8(255, 0, 0, 255);
+        let blue = Color::srgba_u8(0, 0, 255, 255);
+        assert_eq!(colors.colors.len(), geom.cells.len());
+        for (normal, color) in geom.cell_normals.iter().zip(&colors.colors) {
+            let z = normal.normalize().z;
+            if z < -0.05 {
+                assert_eq!(*color, red);
+            } else if z > 0.05 {
+                assert_eq!(*color, blue);
+            }
+        }
+        assert!(colors.colors.contains(&red) && colors.colors.contains(&blue));
+    }
+
+    #[test]
+    fn random_colors_mix_in_linear_space() {
+        assert_eq!(
+            random_color_at(0.0).to_srgba().to_u8_array(),
+            [255, 0, 255, 255]
+        );
+        assert_eq!(
+            random_color_at(1.0).to_srgba().to_u8_array(),
+            [0, 255, 255, 255]
+        );
+
+        // Halfway in linear space is brighter than halfway between the sRGB values
+        let linear = random_color_at(0.5).to_srgba();
+        let srgb = Srgba::rgb(1.0, 0.0, 1.0).mix(&Srgba::rgb(0.0, 1.0, 1.0), 0.5);
+        assert!((srgb.red - 0.5).abs() < 1e-6);
+        assert!(linear.red > 0.7 && linear.green > 0.7, "{linear:?}");
+        assert!(Color::from(linear).luminance() > Color::from(srgb).luminance());
+    }
+}
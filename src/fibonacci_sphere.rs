@@ -1,6 +1,8 @@
 use bevy::prelude::*;
 
-pub(crate) fn fibonacci_sphere_point(i: u32, n: u32) -> Vec3 {
+use crate::octree::{Octree, Point};
+
+pub fn fibonacci_sphere_point(i: u32, n: u32) -> Vec3 {
     let phi = std::f32::consts::PI * (5.0f32.sqrt() - 1.0);
 
     let y = 1.0 - (i as f32 / (n as f32 - 1.0)) * 2.0;
@@ -14,6 +16,75 @@ pub(crate) fn fibonacci_sphere_point(i: u32, n: u32) -> Vec3 {
     Vec3::new(x, y, z)
 }
 
-pub(crate) fn fibonacci_sphere(n: u32) -> Vec<Vec3> {
+pub fn fibonacci_sphere(n: u32) -> Vec<Vec3> {
     (0..n).map(|i| fibonacci_sphere_point(i, n)).collect()
 }
+
+/// The approximate spherical voronoi area of each point of `fibonacci_sphere(n)`,
+/// for normalizing per point quantities. The areas sum to 4π.
+///
+/// Estimated by scattering a much denser (and so close to equal area) fibonacci
+/// sphere over the surface, and giving each of its samples to the nearest point.
+pub fn fibonacci_sphere_areas(n: u32) -> Vec<f32> {
+    const SAMPLES_PER_POINT: u32 = 32;
+    let sphere_area = 4.0 * std::f32::consts::PI;
+
+    if n < 2 {
+        // The spiral is undefined for a single point, which covers everything
+        return vec![sphere_area; n as usize];
+    }
+
+    let points = fibonacci_sphere(n);
+    let mut octree = Octree::new(16, Vec3::ZERO, 1.0, 0, vec![]);
+    for (value, &position) in points.iter().enumerate() {
+        octree.insert(Point { position, value });
+    }
+
+    let samples = n * SAMPLES_PER_POINT;
+    let sample_area = sphere_area / samples as f32;
+    // About twice the spacing between points, so the nearest is almost always in range
+    let mut radius = 2.0 * (sphere_area / n as f32).sqrt();
+
+    let mut areas = vec![0.0; n as usize];
+    for i in 0..samples {
+        let sample = fibonacci_sphere_point(i, samples);
+        let nearest = loop {
+            let nearest = octree
+                .within_distance(sample, radius)
+                .into_iter()
+                .min_by(|&a, &b| {
+                    let a = points[a].distance_squared(sample);
+                    let b = points[b].distance_squared(sample);
+                    a.total_cmp(&b)
+                });
+            match nearest {
+                Some(nearest) => break nearest,
+                None => radius *= 2.0,
+            }
+        };
+        areas[nearest] += sample_area;
+    }
+
+    areas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn areas_cover_the_sphere() {
+        for n in [1, 2, 50, 500] {
+            let areas = fibonacci_sphere_areas(n);
+            assert_eq!(areas.len(), n as usize);
+            assert!(areas.iter().all(|&area| area > 0.0));
+            let total = areas.iter().sum::<f32>();
+            assert!((total - 4.0 * std::f32::consts::PI).abs() < 1e-3, "{total}");
+        }
+
+        // Nearly equal for the spiral, which spreads its points evenly
+        let areas = fibonacci_sphere_areas(500);
+        let mean = 4.0 * std::f32::consts::PI / 500.0;
+        assert!(areas.iter().all(|&area| (area / mean - 1.0).abs() < 0.5));
+    }
+}
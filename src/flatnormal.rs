@@ -6,7 +6,7 @@ use bevy::{
     color::LinearRgba,
     pbr::{
         ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline,
-        MaterialPlugin, StandardMaterial,
+        MaterialPlugin, OpaqueRendererMethod, StandardMaterial,
     },
     prelude::*,
     reflect::TypePath,
@@ -21,13 +21,61 @@ use bevy::{
 };
 
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
-pub(crate) struct FlatNormalMaterial {}
+pub(crate) struct FlatNormalMaterial {
+    /// Scales the alpha of every vertex color. Only visible when the base
+    /// material blends, which is how chunks fade out (see `ChunkFade`).
+    #[uniform(100)]
+    pub(crate) alpha: f32,
+    /// How far vertices are moved towards their [`ATTRIBUTE_MORPH_TARGET`],
+    /// from 0 (where they are) to 1 (all the way)
+    #[uniform(100)]
+    pub(crate) morph: f32,
+}
+
+impl Default for FlatNormalMaterial {
+    fn default() -> Self {
+        Self {
+            alpha: 1.0,
+            morph: 0.0,
+        }
+    }
+}
+
+impl FlatNormalMaterial {
+    /// Adds the material used by every hexsphere chunk, returning its handle
+    pub(crate) fn hexsphere_handle(
+        assets: &mut Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>,
+    ) -> Handle<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>> {
+        assets.add(ExtendedMaterial {
+            base: StandardMaterial {
+                opaque_render_method: OpaqueRendererMethod::Auto,
+                ..Default::default()
+            },
+            extension: FlatNormalMaterial::default(),
+        })
+    }
+}
+
+/// The shared material for hexsphere chunks, inserted once by [`FlatNormalMaterialPlugin`]
+#[derive(Resource)]
+pub struct HexsphereMaterial(pub Handle<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>);
 
 // https://github.com/bevyengine/bevy/blob/v0.14.2/examples/shader/extended_material.rs
 
 pub const ATTRIBUTE_BLEND_COLOR: MeshVertexAttribute =
     MeshVertexAttribute::new("BlendColor", 988540917, VertexFormat::Float32x4);
 
+/// How much each vertex glows, as a color with the strength in alpha. Zero for
+/// ordinary cells, set per cell from [`crate::colors::HexColors::emissive`]
+pub const ATTRIBUTE_EMISSIVE: MeshVertexAttribute =
+    MeshVertexAttribute::new("Emissive", 988540918, VertexFormat::Float32x4);
+
+/// Where each vertex of a chunk sits on the coarser mesh it replaced, for
+/// morphing between the two. The same as the position where there is nothing
+/// to morph from.
+pub const ATTRIBUTE_MORPH_TARGET: MeshVertexAttribute =
+    MeshVertexAttribute::new("MorphTarget", 988540919, VertexFormat::Float32x3);
+
 impl MaterialExtension for FlatNormalMaterial {
     fn fragment_shader() -> ShaderRef {
         "flat_normal_material.wgsl".into()
@@ -48,6 +96,8 @@ impl MaterialExtension for FlatNormalMaterial {
             Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
             Mesh::ATTRIBUTE_COLOR.at_shader_location(5),
             ATTRIBUTE_BLEND_COLOR.at_shader_location(10),
+            ATTRIBUTE_EMISSIVE.at_shader_location(11),
+            ATTRIBUTE_MORPH_TARGET.at_shader_location(12),
         ])?;
         descriptor.vertex.buffers = vec![vertex_layout];
         Ok(())
@@ -60,6 +110,35 @@ impl Plugin for FlatNormalMaterialPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugins(MaterialPlugin::<
             ExtendedMaterial<StandardMaterial, FlatNormalMaterial>,
-        >::default());
+        >::default())
+            .add_systems(Startup, setup_hexsphere_material);
+    }
+}
+
+fn setup_hexsphere_material(
+    mut commands: Commands,
+    mut flat_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>,
+) {
+    let material = FlatNormalMaterial::hexsphere_handle(&mut flat_materials);
+    commands.insert_resource(HexsphereMaterial(material));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn hexsphere_material_is_inserted_once() {
+        let mut world = World::new();
+        world.init_resource::<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>();
+
+        world.run_system_once(setup_hexsphere_material).unwrap();
+
+        let materials =
+            world.resource::<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>();
+        assert_eq!(materials.len(), 1);
+        let material = world.resource::<HexsphereMaterial>();
+        assert!(materials.contains(&material.0));
     }
 }
@@ -0,0 +1,456 @@
+//! Caches built body geometry and its octree on disk, as building a large
+//! sphere is by far the slowest part of starting up.
+//!
+//! The file is a small header (magic, format version and a hash of the
+//! [`GeometryParams`] it was built from) followed by the geometry and then the
+//! octree, depth first, in little endian, each list prefixed with its length.
+//! Anything that doesn't match is treated as a miss and rebuilt.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    geometry_data::GeometryData,
+    octree::{Octree, OctreeConfig, Point},
+};
+
+/// Where `setup_bodies` keeps its geometry between runs
+pub(crate) const GEOMETRY_CACHE_PATH: &str = "cache/geometry.bin";
+
+const MAGIC: &[u8; 4] = b"HEXG";
+
+/// Bumped whenever the file layout or the way geometry is built changes, so
+/// old caches stop matching
+const CACHE_VERSION: u32 = 2;
+
+/// Octrees nest no deeper than this in a cache, well past the deepest an
+/// octree gets, so a corrupt one can't overflow the stack
+const MAX_CACHED_DEPTH: usize = 64;
+
+/// Everything that decides what a bodies geometry and octree look like
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct GeometryParams {
+    /// How many times the icosahedron is subdivided
+    pub(crate) subdivisions: usize,
+    /// How the octree over the cells is built
+    pub(crate) octree: OctreeConfig,
+}
+
+impl GeometryParams {
+    /// Builds the geometry and its octree from scratch
+    pub(crate) fn build(self) -> (GeometryData, Octree) {
+        let geometry = GeometryData::icosahedron()
+            .subdivide_n(self.subdivisions)
+            .expect("the icosahedron is well formed")
+            .slerp()
+            .recell()
+            .dual()
+            .expect("recell puts a cell around every vertex");
+        let octree = self.octree.build(&geometry.cell_normals);
+        (geometry, octree)
+    }
+
+    /// FNV-1a over the params and the cache version. Unlike `DefaultHasher`
+    /// it is the same on every build, so caches survive recompiling.
+    pub(crate) fn hash(self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let OctreeConfig {
+            capacity,
+            bounds,
+            center,
+        } = self.octree;
+        let bytes = [
+            CACHE_VERSION as u64,
+            self.subdivisions as u64,
+            capacity as u64,
+            bounds.to_bits() as u64,
+            center.x.to_bits() as u64,
+            center.y.to_bits() as u64,
+            center.z.to_bits() as u64,
+        ];
+        for byte in bytes.iter().flat_map(|value| value.to_le_bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+}
+
+/// Reads the geometry and octree cached at `path`, or builds them and writes
+/// them there when there is no cache or it was built from different params.
+/// Failing to write the cache only costs the next start, so it is just logged.
+pub(crate) fn load_or_build(params: GeometryParams, path: &Path) -> (GeometryData, Octree) {
+    match read_cache(path, params) {
+        Ok(cached) => return cached,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => warn!("Rebuilding geometry, cache at {path:?} unusable: {error}"),
+    }
+
+    let (geometry, octree) = params.build();
+    if let Err(error) = write_cache(path, params, &geometry, &octree) {
+        warn!("Couldn't cache geometry at {path:?}: {error}");
+    }
+    (geometry, octree)
+}
+
+/// Writes `geometry` and `octree` to `path`, via a temporary file so a crash
+/// part way through never leaves a truncated cache behind
+pub(crate) fn write_cache(
+    path: &Path,
+    params: GeometryParams,
+    geometry: &GeometryData,
+    octree: &Octree,
+) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temporary = path.with_extension("tmp");
+
+    let mut out = BufWriter::new(fs::File::create(&temporary)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&CACHE_VERSION.to_le_bytes())?;
+    out.write_all(&params.hash().to_le_bytes())?;
+
+    write_vec3s(&mut out, &geometry.vertices)?;
+    write_len(&mut out, geometry.faces.len())?;
+    for face in &geometry.faces {
+        for &vertex in face {
+            write_index(&mut out, vertex)?;
+        }
+    }
+    write_lists(&mut out, geometry.cells.iter().map(|cell| cell.iter()))?;
+    write_lists(
+        &mut out,
+        geometry
+            .cell_neighbors
+            .iter()
+            .map(|neighbors| neighbors.iter()),
+    )?;
+    write_vec3s(&mut out, &geometry.cell_normals)?;
+    write_octree(&mut out, octree)?;
+
+    out.into_inner()?.sync_all()?;
+    fs::rename(temporary, path)
+}
+
+/// Reads the geometry and octree cached at `path`, failing with `InvalidData`
+/// if they were built from other params or are malformed
+pub(crate) fn read_cache(
+    path: &Path,
+    params: GeometryParams,
+) -> io::Result<(GeometryData, Octree)> {
+    let bytes = fs::read(path)?;
+    let mut reader = Reader(&bytes);
+
+    if reader.take(4)? != MAGIC {
+        return Err(invalid("not a geometry cache"));
+    }
+    if reader.u32()? != CACHE_VERSION {
+        return Err(invalid("written by another version"));
+    }
+    if reader.u64()? != params.hash() {
+        return Err(invalid("built from other params"));
+    }
+
+    let mut geometry = GeometryData::default();
+    geometry.vertices = reader.vec3s()?;
+    let faces = reader.len()?;
+    geometry.faces = (0..faces)
+        .map(|_| Ok([reader.index()?, reader.index()?, reader.index()?]))
+        .collect::<io::Result<_>>()?;
+    geometry.cells = reader.lists()?;
+    geometry.cell_neighbors = reader
+        .lists()?
+        .into_iter()
+        .map(BTreeSet::from_iter)
+        .collect();
+    geometry.cell_normals = reader.vec3s()?;
+    let octree = reader.octree(0)?;
+
+    if !reader.0.is_empty() {
+        return Err(invalid("trailing data"));
+    }
+    let cells = geometry.cells.len();
+    if geometry.cell_neighbors.len() != cells || geometry.cell_normals.len() != cells {
+        return Err(invalid("cell lists differ in length"));
+    }
+    if geometry
+        .faces
+        .iter()
+        .flatten()
+        .any(|&v| v >= geometry.vertices.len())
+        || geometry
+            .cells
+            .iter()
+            .flatten()
+            .any(|&f| f >= geometry.faces.len())
+        || geometry
+            .cell_neighbors
+            .iter()
+            .flatten()
+            .any(|&c| c >= cells)
+        || octree.cells().iter().any(|&c| c >= cells)
+    {
+        return Err(invalid("index out of range"));
+    }
+
+    Ok((geometry, octree))
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn write_len(out: &mut impl Write, len: usize) -> io::Result<()> {
+    out.write_all(&(len as u64).to_le_bytes())
+}
+
+/// Indices are stored as `u32`, which halves the file and fits any sphere
+/// that would fit in memory
+fn write_index(out: &mut impl Write, index: usize) -> io::Result<()> {
+    let index = u32::try_from(index).map_err(|_| invalid("index too large to cache"))?;
+    out.write_all(&index.to_le_bytes())
+}
+
+fn write_vec3(out: &mut impl Write, point: Vec3) -> io::Result<()> {
+    for component in point.to_array() {
+        out.write_all(&component.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_vec3s(out: &mut impl Write, points: &[Vec3]) -> io::Result<()> {
+    write_len(out, points.len())?;
+    for &point in points {
+        write_vec3(out, point)?;
+    }
+    Ok(())
+}
+
+/// Writes `node` and then, for leaves, its points, otherwise a bit per child
+/// saying which exist followed by those children
+fn write_octree(out: &mut impl Write, node: &Octree) -> io::Result<()> {
+    write_vec3(out, node.center)?;
+    out.write_all(&node.bounds.to_le_bytes())?;
+    for value in [node.capacity, node.height, node.depth, node.cell_count] {
+        write_len(out, value)?;
+    }
+    write_len(out, node.octree_index.len())?;
+    out.write_all(&node.octree_index)?;
+
+    match &node.points {
+        Some(points) => {
+            out.write_all(&[1])?;
+            write_len(out, points.len())?;
+            for point in points {
+                write_vec3(out, point.position)?;
+                write_index(out, point.value)?;
+            }
+        }
+        None => {
+            out.write_all(&[0])?;
+            let present = node
+                .children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| child.is_some())
+                .fold(0u8, |mask, (i, _)| mask | 1 << i);
+            out.write_all(&[present])?;
+            for child in node.children.iter().flatten() {
+                write_octree(out, child)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_lists<'a>(
+    out: &mut impl Write,
+    lists: impl ExactSizeIterator<Item = impl ExactSizeIterator<Item = &'a usize>>,
+) -> io::Result<()> {
+    write_len(out, lists.len())?;
+    for list in lists {
+        write_len(out, list.len())?;
+        for &index in list {
+            write_index(out, index)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads values off the front of a byte slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// A length, checked against what is left so a corrupt one can't make us
+    /// allocate everything
+    fn len(&mut self) -> io::Result<usize> {
+        let len = self.u64()?;
+        if len > self.0.len() as u64 {
+            return Err(invalid("length past the end of the file"));
+        }
+        Ok(len as usize)
+    }
+
+    fn index(&mut self) -> io::Result<usize> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn vec3(&mut self) -> io::Result<Vec3> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn vec3s(&mut self) -> io::Result<Vec<Vec3>> {
+        let len = self.len()?;
+        (0..len).map(|_| self.vec3()).collect()
+    }
+
+    /// An octree node `depth` deep in the file, and everything under it
+    fn octree(&mut self, depth: usize) -> io::Result<Octree> {
+        if depth > MAX_CACHED_DEPTH {
+            return Err(invalid("octree too deep"));
+        }
+
+        let center = self.vec3()?;
+        let bounds = self.f32()?;
+        let capacity = self.u64()? as usize;
+        let height = self.u64()? as usize;
+        let node_depth = self.u64()? as usize;
+        let cell_count = self.u64()? as usize;
+        let index_len = self.len()?;
+        let octree_index = self.take(index_len)?.to_vec();
+
+        let mut children = Box::new([const { None }; 8]);
+        let points = match self.array::<1>()? {
+            [1] => {
+                let len = self.len()?;
+                let points = (0..len)
+                    .map(|_| {
+                        Ok(Point {
+                            position: self.vec3()?,
+                            value: self.index()?,
+                        })
+                    })
+                    .collect::<io::Result<_>>()?;
+                Some(points)
+            }
+            [0] => {
+                let [present] = self.array()?;
+                for (i, child) in children.iter_mut().enumerate() {
+                    if present & 1 << i != 0 {
+                        *child = Some(self.octree(depth + 1)?);
+                    }
+                }
+                None
+            }
+            _ => return Err(invalid("unknown octree node")),
+        };
+
+        Ok(Octree {
+            children,
+            center,
+            points,
+            capacity,
+            bounds,
+            height,
+            depth: node_depth,
+            octree_index,
+            cell_count,
+        })
+    }
+
+    fn lists(&mut self) -> io::Result<Vec<Vec<usize>>> {
+        let len = self.len()?;
+        (0..len)
+            .map(|_| {
+                let inner = self.len()?;
+                (0..inner).map(|_| self.index()).collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_round_trips_and_rebuilds_for_other_params() {
+        let path =
+            std::env::temp_dir().join(format!("planets-geometry-cache-{}.bin", std::process::id()));
+        let params = GeometryParams {
+            subdivisions: 2,
+            octree: OctreeConfig {
+                capacity: 8,
+                ..Default::default()
+            },
+        };
+        let (geometry, octree) = params.build();
+
+        write_cache(&path, params, &geometry, &octree).unwrap();
+        let (read, read_octree) = read_cache(&path, params).unwrap();
+        assert_eq!(read.vertices, geometry.vertices);
+        assert_eq!(read.faces, geometry.faces);
+        assert_eq!(read.cells, geometry.cells);
+        assert_eq!(read.cell_neighbors, geometry.cell_neighbors);
+        assert_eq!(read.cell_normals, geometry.cell_normals);
+        // Debug prints floats exactly, so this compares every node
+        assert_eq!(format!("{read_octree:?}"), format!("{octree:?}"));
+        assert!(read_octree.children.iter().any(Option::is_some));
+
+        // A cache built from other params is a miss, so it is rebuilt and replaced
+        let other = GeometryParams {
+            subdivisions: 1,
+            ..params
+        };
+        let error = read_cache(&path, other).err().map(|error| error.kind());
+        assert_eq!(error, Some(io::ErrorKind::InvalidData));
+        let (rebuilt, rebuilt_octree) = load_or_build(other, &path);
+        let (built, built_octree) = other.build();
+        assert_eq!(rebuilt.vertices, built.vertices);
+        assert_eq!(rebuilt.cells.len(), built.cells.len());
+        assert_eq!(format!("{rebuilt_octree:?}"), format!("{built_octree:?}"));
+        assert!(read_cache(&path, other).is_ok());
+        assert!(read_cache(&path, params).is_err());
+
+        // As is one whose octree was built differently
+        let coarser = GeometryParams {
+            octree: OctreeConfig::default(),
+            ..other
+        };
+        assert!(read_cache(&path, coarser).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,17 +1,35 @@
-use bevy::pbr::ExtendedMaterial;
-use bevy::render::mesh::{Indices, PrimitiveTopology::TriangleList};
+use bevy::math::{DMat4, DVec3};
+use bevy::render::mesh::{
+    Indices,
+    PrimitiveTopology::{LineList, TriangleList},
+};
 use bevy::{asset::RenderAssetUsages, prelude::*};
-use rand::{random, random_range};
-use std::collections::{BTreeMap, BTreeSet};
+use noisy_bevy::simplex_noise_3d;
+use rand::{random, rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
 
+use crate::borders::BorderStyle;
 use crate::camera::CameraTarget;
+use crate::chunk_storage::ChunkShading;
 use crate::chunking::ChunkManager;
-use crate::flatnormal::FlatNormalMaterial;
-use crate::helpers::{self, sort_poly_vertices};
-use crate::octree::{Octree, Point};
+use crate::fibonacci_sphere::fibonacci_sphere_point;
+use crate::flatnormal::{
+    HexsphereMaterial, ATTRIBUTE_BLEND_COLOR, ATTRIBUTE_EMISSIVE, ATTRIBUTE_MORPH_TARGET,
+};
+use crate::helpers;
+use crate::kdtree::KdTree;
+use crate::octree::{Octree, OctreeConfig, Point};
+
+/// How many times [`GeometryData::direction_id`] splits the base triangles,
+/// as many as fit in a `u64` after the 5 bits picking the face. The triangles
+/// are a few meters across on an earth sized planet by the last level.
+pub const CELL_ID_LEVELS: u32 = 29;
 
 #[derive(Default, Clone)]
-pub(crate) struct GeometryData {
+pub struct GeometryData {
     /// Stores the position of vertex i at index i
     pub vertices: Vec<Vec3>,
     /// Stores the faces in the geometry
@@ -22,10 +40,102 @@ pub(crate) struct GeometryData {
     pub cell_neighbors: Vec<BTreeSet<usize>>,
     /// Stores the normals (and by extension cell positions)
     pub cell_normals: Vec<Vec3>,
+    /// Memoized result of `cell_centroids`. Cleared by every method that moves
+    /// vertices or regroups faces, anything else mutating the public fields
+    /// directly must call `invalidate_centroids`.
+    centroid_cache: OnceLock<Vec<Vec3>>,
+}
+
+/// Where `subdivide` places the vertex splitting each edge
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum SubdivideMode {
+    /// Halfway along the straight edge, leaving it to a later `slerp` to push
+    /// the vertex out onto the sphere
+    #[default]
+    Linear,
+    /// Halfway along the great circle through the edge ends, so every level of
+    /// subdivision stays on the sphere and the triangles stay closer in size
+    Spherical,
+}
+
+/// What can go wrong building geometry from faces and cells that don't fit
+/// together, e.g. imported or edited by hand
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeometryError {
+    /// A face refers to a vertex that doesn't exist
+    MissingVertex { face: usize, vertex: usize },
+    /// A cell refers to a face that doesn't exist
+    MissingFace { cell: usize, face: usize },
+    /// A cell has no faces, so there is nothing to take the dual of
+    EmptyCell { cell: usize },
+    /// `dual` needs exactly one cell around each vertex, as made by `recell`
+    CellsNotAroundVertices { cells: usize, vertices: usize },
+    /// The edge from vertex `from` to `to` of a face isn't shared with exactly
+    /// one other face, leaving a hole (`faces` is 1) or a fin (more than 2)
+    NonManifoldEdge {
+        face: usize,
+        from: usize,
+        to: usize,
+        faces: usize,
+    },
+    /// A face winds inward, or is too thin to wind either way
+    InvertedFace { face: usize },
+    /// Values given one per vertex or one per cell don't match their count
+    AttributeLength { expected: usize, found: usize },
+}
+
+impl std::fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingVertex { face, vertex } => {
+                write!(f, "face {face} refers to missing vertex {vertex}")
+            }
+            Self::MissingFace { cell, face } => {
+                write!(f, "cell {cell} refers to missing face {face}")
+            }
+            Self::EmptyCell { cell } => write!(f, "cell {cell} has no faces"),
+            Self::CellsNotAroundVertices { cells, vertices } => write!(
+                f,
+                "expected a cell around each of the {vertices} vertices, found {cells} cells"
+            ),
+            Self::NonManifoldEdge {
+                face,
+                from,
+                to,
+                faces,
+            } => write!(
+                f,
+                "edge {from}-{to} of face {face} is shared by {faces} faces, not 2"
+            ),
+            Self::InvertedFace { face } => write!(f, "face {face} doesn't wind outward"),
+            Self::AttributeLength { expected, found } => {
+                write!(f, "expected {expected} attribute values, found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GeometryError {}
+
+/// A read only view of one cell of a [`GeometryData`]
+#[derive(Clone, Copy, Debug)]
+pub struct CellView<'a> {
+    pub index: usize,
+    pub normal: Vec3,
+    pub neighbors: &'a BTreeSet<usize>,
 }
 
 impl GeometryData {
-    pub(crate) fn dual(mut self) -> Self {
+    pub fn dual(mut self) -> Result<Self, GeometryError> {
+        self.check_faces()?;
+        self.check_cells()?;
+        if self.cells.len() != self.vertices.len() {
+            return Err(GeometryError::CellsNotAroundVertices {
+                cells: self.cells.len(),
+                vertices: self.vertices.len(),
+            });
+        }
+
         let mut dual_vertices = Vec::new();
         let mut dual_faces = Vec::new();
         let mut dual_cells = Vec::new();
@@ -51,9 +161,8 @@ impl GeometryData {
                 }));
             }
 
-            sorted = sort_poly_vertices(&dual_vertices, sorted);
-
-            // Utilizing the list of sorted vertices, construct faces
+            // The faces are already in ring order (see `recell`), so the
+            // centroids are too, and can be fanned into faces directly
             let o = sorted[0];
             for d in sorted[1..].windows(2) {
                 dual_faces.push([o, d[0], d[1]]);
@@ -74,12 +183,7 @@ impl GeometryData {
         // And as a final precaution against back-face culling,
         // flip any faces order that is not clockwise
         for face in &mut dual_faces {
-            let [a, b, c] = (0..3)
-                .map(|i| dual_vertices[face[i]])
-                .collect::<Vec<Vec3>>()[..3]
-            else {
-                panic!("Impossible!!")
-            };
+            let [a, b, c] = face.map(|v| dual_vertices[v]);
 
             // dot the normal with a vector and see if its <0
             if (b - a).cross(c - a).dot(a) < 0. {
@@ -92,42 +196,167 @@ impl GeometryData {
         std::mem::swap(&mut self.faces, &mut dual_faces);
         std::mem::swap(&mut self.cells, &mut dual_cells);
         std::mem::swap(&mut self.cell_neighbors, &mut dual_cell_neighbors);
+        self.invalidate_centroids();
 
-        self
+        Ok(self)
     }
 
-    /// Duplicates vertices (necessary for proper normals)
-    pub(crate) fn duplicate(mut self) -> Self {
-        let mut new_vertices = Vec::with_capacity(self.faces.len() * 3);
-        let mut new_faces = Vec::with_capacity(self.faces.len());
+    /// Checks that every face refers to existing vertices
+    fn check_faces(&self) -> Result<(), GeometryError> {
+        for (face, vertices) in self.faces.iter().enumerate() {
+            if let Some(&vertex) = vertices.iter().find(|&&v| v >= self.vertices.len()) {
+                return Err(GeometryError::MissingVertex { face, vertex });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that there are as many values as the vertices or cells they go with
+    fn check_attribute_length(expected: usize, found: usize) -> Result<(), GeometryError> {
+        if expected == found {
+            Ok(())
+        } else {
+            Err(GeometryError::AttributeLength { expected, found })
+        }
+    }
+
+    /// Checks the faces make a closed surface around the origin: every index
+    /// is in range, every edge is shared by exactly two faces and every face
+    /// winds outward (its normal points away from the origin). Edges are
+    /// matched by the positions of their ends, so duplicated vertices are
+    /// fine. The error names the first face at fault.
+    pub fn validate(&self) -> Result<(), GeometryError> {
+        self.check_faces()?;
+
+        let key = |v: usize| self.vertices[v].to_array().map(f32::to_bits);
+        let edge = |a: usize, b: usize| {
+            let (a, b) = (key(a), key(b));
+            if a < b {
+                (a, b)
+            } else {
+                (b, a)
+            }
+        };
+        let mut edge_faces = HashMap::new();
+        for &[a, b, c] in &self.faces {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                *edge_faces.entry(edge(u, v)).or_insert(0) += 1;
+            }
+        }
+
+        for (face, &[a, b, c]) in self.faces.iter().enumerate() {
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                let faces = edge_faces[&edge(from, to)];
+                if faces != 2 {
+                    return Err(GeometryError::NonManifoldEdge {
+                        face,
+                        from,
+                        to,
+                        faces,
+                    });
+                }
+            }
+
+            let [va, vb, vc] = [a, b, c].map(|v| self.vertices[v]);
+            if (vb - va).cross(vc - va).dot(va) <= 0.0 {
+                return Err(GeometryError::InvertedFace { face });
+            }
+        }
 
-        for [i0, i1, i2] in self.faces {
-            let v0 = self.vertices[i0];
-            let v1 = self.vertices[i1];
-            let v2 = self.vertices[i2];
+        Ok(())
+    }
 
-            let start_index = new_vertices.len();
-            new_vertices.push(v0);
-            new_vertices.push(v1);
-            new_vertices.push(v2);
+    /// Checks that every cell has faces, and that they exist
+    fn check_cells(&self) -> Result<(), GeometryError> {
+        for (cell, faces) in self.cells.iter().enumerate() {
+            if faces.is_empty() {
+                return Err(GeometryError::EmptyCell { cell });
+            }
+            if let Some(&face) = faces.iter().find(|&&f| f >= self.faces.len()) {
+                return Err(GeometryError::MissingFace { cell, face });
+            }
+        }
+        Ok(())
+    }
 
-            new_faces.push([start_index, start_index + 1, start_index + 2]);
+    /// Finds the faces not claimed by exactly one cell, with the cells claiming
+    /// each (none for faces no cell has). Empty means the cells partition the
+    /// faces.
+    ///
+    /// That only holds after `dual`, where each cell is a fan of its own faces.
+    /// The cells `recell` builds around vertices overlap by design, every
+    /// triangle being in the cell of each of its corners. `sub_geometry` and the
+    /// per cell coloring copy faces cell by cell without deduplicating them, so
+    /// they expect the dual: given overlapping cells they would draw shared
+    /// faces once per cell, in whichever color comes last.
+    pub fn validate_cells(&self) -> BTreeMap<usize, Vec<usize>> {
+        let mut claims = vec![Vec::new(); self.faces.len()];
+        for (cell, faces) in self.cells.iter().enumerate() {
+            for &f in faces {
+                if let Some(claimed_by) = claims.get_mut(f) {
+                    claimed_by.push(cell);
+                }
+            }
         }
 
-        self.vertices = new_vertices;
-        self.faces = new_faces;
+        claims
+            .into_iter()
+            .enumerate()
+            .filter(|(_, cells)| cells.len() != 1)
+            .collect()
+    }
+
+    /// Duplicates vertices (necessary for proper normals)
+    pub fn duplicate(mut self) -> Self {
+        (self.vertices, self.faces) = duplicate_faces(&self.vertices, &self.faces);
+        self.invalidate_centroids();
 
         self
     }
 
-    pub(crate) fn subdivide_n(mut self, n: usize) -> Self {
+    pub fn subdivide_n(mut self, n: usize) -> Result<Self, GeometryError> {
         for _ in 0..n {
-            self = self.subdivide();
+            self = self.subdivide()?;
         }
-        self
+        Ok(self)
+    }
+
+    pub fn subdivide(self) -> Result<Self, GeometryError> {
+        self.subdivide_in_mode(SubdivideMode::Linear)
+    }
+
+    /// Like [`Self::subdivide`], but carries a value per vertex along with it:
+    /// `attrs` (one per vertex, e.g. heights) is extended in step with the new
+    /// vertices, each midpoint getting the average of the two ends of the edge
+    /// it splits. Fields that vary linearly over the vertices stay linear.
+    pub fn subdivide_with(self, attrs: &mut Vec<f32>) -> Result<Self, GeometryError> {
+        Self::check_attribute_length(self.vertices.len(), attrs.len())?;
+        attrs.reserve_exact(self.split_vertex_count());
+        self.split_edges(SubdivideMode::Linear, |u, v| {
+            attrs.push((attrs[u] + attrs[v]) / 2.0)
+        })
+    }
+
+    /// Like [`Self::subdivide`], with the new midpoint vertices placed by `mode`
+    pub fn subdivide_in_mode(self, mode: SubdivideMode) -> Result<Self, GeometryError> {
+        self.split_edges(mode, |_, _| {})
     }
 
-    pub(crate) fn subdivide(mut self) -> Self {
+    /// How many vertices subdividing adds to a closed mesh. Each edge is shared
+    /// by two faces, so splitting them adds one and a half per face.
+    fn split_vertex_count(&self) -> usize {
+        self.faces.len() * 3 / 2
+    }
+
+    /// Subdivides once, calling `on_split` with the ends of each edge as its
+    /// midpoint vertex is added, in the order they are added
+    fn split_edges(
+        mut self,
+        mode: SubdivideMode,
+        mut on_split: impl FnMut(usize, usize),
+    ) -> Result<Self, GeometryError> {
+        self.check_faces()?;
+
         // Subdivides self once
         // For each face:
         // 1) Split each edge with a new vertex in the middle.
@@ -138,81 +367,548 @@ impl GeometryData {
         // 2) After splitting the three edges of a face, create 4 new faces for each subtriangle.
         // 3) Add those faces to the new face vector.
         let mut btree: BTreeMap<(usize, usize), usize> = BTreeMap::new();
-        let mut new_faces = Vec::<[usize; 3]>::new();
+        // Every face becomes 4
+        let mut new_faces = Vec::<[usize; 3]>::with_capacity(self.faces.len() * 4);
+        self.vertices.reserve_exact(self.split_vertex_count());
 
         for &[i, j, k] in &self.faces {
             // Splits i,j, j,k and k,i into 3 new vertices:
-            let mut splits = Vec::new();
-            for (u, v) in [(i, j), (j, k), (k, i)] {
-                let index = *btree
+            let mut splits = [0; 3];
+            for (split, (u, v)) in splits.iter_mut().zip([(i, j), (j, k), (k, i)]) {
+                *split = *btree
                     .entry(helpers::ordered_2tuple(u, v))
                     .or_insert_with(|| {
                         // New vertex, tell it its parent is i
+                        on_split(u, v);
                         self.vertices.push({
                             let x = self.vertices[u];
                             let y = self.vertices[v];
-                            (x + y) / 2.
+                            match mode {
+                                SubdivideMode::Linear => (x + y) / 2.,
+                                SubdivideMode::Spherical => {
+                                    let radius = (x.length() + y.length()) / 2.;
+                                    helpers::slerp_points(x.normalize(), y.normalize(), 0.5)
+                                        * radius
+                                }
+                            }
                         });
                         self.vertices.len() - 1
                     });
-                splits.push(index);
             }
-            let [ij, jk, ki] = splits[0..3] else {
-                panic!("This should be impossible")
-            };
+            let [ij, jk, ki] = splits;
             new_faces.extend([[i, ij, ki], [ij, j, jk], [ki, jk, k], [ij, jk, ki]]);
         }
 
         std::mem::swap(&mut self.faces, &mut new_faces);
+        self.invalidate_centroids();
 
-        self
+        Ok(self)
     }
 
-    pub(crate) fn slerp(mut self) -> Self {
+    /// Pushes every vertex out onto the unit sphere. The cells move with
+    /// their vertices, so `cell_normals` are taken from the new centroids
+    /// (and put on the sphere too).
+    pub fn slerp(mut self) -> Self {
         for vertex in self.vertices.iter_mut() {
-            std::mem::swap(vertex, &mut vertex.normalize());
+            *vertex = vertex.normalize();
+        }
+        self.refresh_cell_normals();
+        self
+    }
+
+    /// Moves the geometry by `offset`. `cell_normals` are recomputed, so they
+    /// point from the origin through the moved cells.
+    pub fn translate(&mut self, offset: Vec3) {
+        for v in self.vertices.iter_mut() {
+            *v += offset;
+        }
+        self.refresh_cell_normals();
+    }
+
+    /// Scales the geometry about the origin by `factor`
+    pub fn scale(&mut self, factor: f32) {
+        for v in self.vertices.iter_mut() {
+            *v *= factor;
+        }
+        self.refresh_cell_normals();
+    }
+
+    /// Rotates the geometry about the origin by `quat`
+    pub fn rotate(&mut self, quat: Quat) {
+        for v in self.vertices.iter_mut() {
+            *v = quat * *v;
+        }
+        self.refresh_cell_normals();
+    }
+
+    /// Sets `cell_normals` to the direction of each cell's centroid, after the
+    /// vertices have moved
+    fn refresh_cell_normals(&mut self) {
+        self.invalidate_centroids();
+        self.cell_normals = self
+            .cell_centroids()
+            .iter()
+            .map(|centroid| centroid.normalize_or_zero())
+            .collect();
+    }
+
+    /// Breaks up the icosahedral symmetry by moving every vertex a random
+    /// distance across the surface of the sphere, up to `magnitude` times the
+    /// shortest edge leaving it. Much past 0.2 the thin triangles of the cell
+    /// fans start getting turned inside out. The same `seed` always gives the
+    /// same jitter.
+    pub fn deregulate(mut self, magnitude: f32, seed: u64) -> Self {
+        let mut shortest = vec![f32::INFINITY; self.vertices.len()];
+        for face in &self.faces {
+            for i in 0..3 {
+                let (a, b) = (face[i], face[(i + 1) % 3]);
+                let length = self.vertices[a].distance(self.vertices[b]);
+                shortest[a] = shortest[a].min(length);
+                shortest[b] = shortest[b].min(length);
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        for (vertex, shortest) in self.vertices.iter_mut().zip(shortest) {
+            // Drawn for every vertex, so a lone vertex doesn't shift the rest
+            let (angle, distance) = (
+                rng.random_range(0.0..std::f32::consts::TAU),
+                rng.random_range(0.0..=1.0f32).sqrt(),
+            );
+            if !shortest.is_finite() {
+                continue;
+            }
+            let radius = vertex.length();
+            let Some(up) = vertex.try_normalize() else {
+                continue;
+            };
+            let (east, north) = up.any_orthonormal_pair();
+            let offset = (east * angle.cos() + north * angle.sin()) * distance;
+            *vertex = (*vertex + offset * magnitude * shortest).normalize() * radius;
         }
+
+        self.refresh_cell_normals();
         self
     }
 
-    pub(crate) fn deregulate(self) -> Self {
+    /// Raises each cell by its entry in `heights` (negative lowers it), moving
+    /// its vertices out along `cell_normals`. A vertex on the border of
+    /// several cells is moved by the average of their heights along the
+    /// average of their normals, so the cells stay joined. Vertices are matched
+    /// by position, so this holds after `duplicate` too.
+    pub fn displace(&mut self, heights: &[f32]) -> Result<(), GeometryError> {
+        Self::check_attribute_length(self.cells.len(), heights.len())?;
+        let key = |v: Vec3| v.to_array().map(f32::to_bits);
+
+        // Summed height and normal of the cells around each position, and how
+        // many there were
+        let mut incident = HashMap::<[u32; 3], (f32, Vec3, f32)>::new();
+        for (cell, faces) in self.cells.iter().enumerate() {
+            let vertices: BTreeSet<usize> = faces.iter().flat_map(|&f| self.faces[f]).collect();
+            let normal = self.cell_normals[cell].normalize_or_zero();
+            for v in vertices {
+                let (height, normals, count) = incident.entry(key(self.vertices[v])).or_default();
+                *height += heights[cell];
+                *normals += normal;
+                *count += 1.0;
+            }
+        }
+
+        for vertex in self.vertices.iter_mut() {
+            if let Some(&(height, normals, count)) = incident.get(&key(*vertex)) {
+                *vertex += normals.normalize_or_zero() * height / count;
+            }
+        }
+
+        self.refresh_cell_normals();
+        Ok(())
+    }
+
+    /// [`Self::displace`]s every cell by simplex noise sampled at its center,
+    /// from -`amplitude` to `amplitude`. Higher `frequency` makes for smaller
+    /// features, and each `seed` samples a different part of the noise.
+    pub fn displace_noise(
+        &mut self,
+        seed: u64,
+        amplitude: f32,
+        frequency: f32,
+    ) -> Result<(), GeometryError> {
+        // Moves the noise somewhere else for every seed
+        let mut rng = StdRng::seed_from_u64(seed);
+        let offset = Vec3::new(
+            rng.random_range(-1000.0..1000.0),
+            rng.random_range(-1000.0..1000.0),
+            rng.random_range(-1000.0..1000.0),
+        );
+
+        let heights: Vec<f32> = self
+            .cell_normals
+            .iter()
+            .map(|normal| {
+                simplex_noise_3d(normal.normalize_or_zero() * frequency + offset) * amplitude
+            })
+            .collect();
+        self.displace(&heights)
+    }
+
+    pub fn relax(self) -> Self {
         self
     }
 
-    pub(crate) fn relax(self) -> Self {
+    pub fn recell(mut self) -> Self {
+        self.cells = Self::cells_around_vertices(&self.vertices, &self.faces);
+        self.cell_neighbors = Self::neighbors_from_shared_faces(&self.cells, self.faces.len());
+        self.invalidate_centroids();
+        self.cell_normals = self.cell_centroids().to_vec();
         self
     }
 
-    pub(crate) fn recell(mut self) -> Self {
+    /// Groups the faces touching each vertex into a cell, ordered counter-clockwise
+    /// around the vertex when viewed from outside, so consecutive faces share an edge
+    fn cells_around_vertices(vertices: &[Vec3], faces: &[[usize; 3]]) -> Vec<Vec<usize>> {
         let mut cells = BTreeMap::new();
-        for (i, face) in self.faces.iter().enumerate() {
+        for (i, face) in faces.iter().enumerate() {
             for &v in face {
-                cells.entry(v).or_insert_with(BTreeSet::new).insert(i);
+                cells.entry(v).or_insert_with(Vec::new).push(i);
             }
         }
 
-        let mut cell_neighbors = vec![BTreeSet::default(); cells.len()];
+        let face_centers: Vec<Vec3> = faces
+            .iter()
+            .map(|face| face.iter().map(|&v| vertices[v]).sum::<Vec3>() / 3.0)
+            .collect();
+        cells
+            .into_iter()
+            .map(|(v, cell)| helpers::sort_around(vertices[v], &face_centers, cell))
+            .collect()
+    }
+
+    /// Makes freshly imported triangles usable: welds coincident vertices,
+    /// turns every face outward and derives the cells with `recell`
+    pub fn normalize(self) -> Self {
+        self.weld().wind_outward().recell()
+    }
+
+    /// Merges vertices within 1e-5 of an earlier one into it, dropping any
+    /// faces that collapse as a result
+    pub fn weld(mut self) -> Self {
+        const TOLERANCE: f32 = 1e-5;
+        let key = |v: Vec3| (v / TOLERANCE).floor().as_ivec3();
+
+        // The kept vertices in each cube of a grid the size of the tolerance.
+        // A vertex close enough to one is in the same cube or one next to it,
+        // even when the two straddle a cube boundary.
+        let mut grid = HashMap::<IVec3, Vec<usize>>::new();
+        let mut vertices: Vec<Vec3> = Vec::new();
+        let remap: Vec<usize> = self
+            .vertices
+            .iter()
+            .map(|&v| {
+                let cube = key(v);
+                let near = (-1..=1)
+                    .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| (x, y, z))))
+                    .filter_map(|(x, y, z)| grid.get(&(cube + IVec3::new(x, y, z))))
+                    .flatten()
+                    .copied()
+                    .find(|&w| vertices[w].distance(v) <= TOLERANCE);
+                near.unwrap_or_else(|| {
+                    vertices.push(v);
+                    grid.entry(cube).or_default().push(vertices.len() - 1);
+                    vertices.len() - 1
+                })
+            })
+            .collect();
+
+        self.vertices = vertices;
+        self.faces = self
+            .faces
+            .iter()
+            .map(|face| face.map(|v| remap[v]))
+            .filter(|[a, b, c]| a != b && b != c && c != a)
+            .collect();
+        self.invalidate_centroids();
+
+        self
+    }
+
+    /// Flips any face that points back towards the origin, as `dual` does.
+    /// Only meaningful for shapes wrapped around the origin, like planets.
+    pub fn wind_outward(mut self) -> Self {
+        for face in &mut self.faces {
+            let [a, b, c] = face.map(|v| self.vertices[v]);
+            if (b - a).cross(c - a).dot(a + b + c) < 0. {
+                face.reverse();
+            }
+        }
+
+        self
+    }
+
+    /// Appends `other` to the geometry, e.g. a ring system or a separately built
+    /// cap. The two stay separate components even where they touch, see
+    /// [`Self::merge_linked`] to join them up there. Vertices are not merged,
+    /// `weld` (or `normalize`) does that.
+    pub fn merge(mut self, other: Self) -> Self {
+        let vertex_offset = self.vertices.len();
+        let face_offset = self.faces.len();
+        let cell_offset = self.cells.len();
+
+        self.vertices.extend(other.vertices);
+        self.faces.extend(
+            other
+                .faces
+                .into_iter()
+                .map(|face| face.map(|v| v + vertex_offset)),
+        );
+        self.cells.extend(
+            other
+                .cells
+                .into_iter()
+                .map(|cell| cell.into_iter().map(|f| f + face_offset).collect()),
+        );
+        self.cell_neighbors.extend(
+            other
+                .cell_neighbors
+                .into_iter()
+                .map(|neighbors| neighbors.into_iter().map(|n| n + cell_offset).collect()),
+        );
+        self.cell_normals.extend(other.cell_normals);
+        self.invalidate_centroids();
+
+        self
+    }
+
+    /// Like [`Self::merge`], but cells either side of a border edge lying in
+    /// the same place in both geometries become neighbors, so a cap built to
+    /// fit a hole joins the rest of the planet
+    pub fn merge_linked(self, other: Self) -> Self {
+        let cell_offset = self.cells.len();
+
+        // Border edges by position, so they can be matched up across the two
+        let key = |v: Vec3| (v / 1e-5).round().as_ivec3().to_array();
+        let edge_key = |geometry: &Self, [u, v]: [usize; 2]| {
+            helpers::ordered_2tuple(key(geometry.vertices[u]), key(geometry.vertices[v]))
+        };
+        let mut edge_cells = BTreeMap::<_, Vec<usize>>::new();
+        for cell in 0..self.cells.len() {
+            for edge in self.cell_border_edges(cell) {
+                edge_cells
+                    .entry(edge_key(&self, edge))
+                    .or_default()
+                    .push(cell);
+            }
+        }
+        let mut shared = Vec::new();
+        for cell in 0..other.cells.len() {
+            for edge in other.cell_border_edges(cell) {
+                for &ours in edge_cells
+                    .get(&edge_key(&other, edge))
+                    .into_iter()
+                    .flatten()
+                {
+                    shared.push((ours, cell + cell_offset));
+                }
+            }
+        }
+
+        let mut merged = self.merge(other);
+        for (a, b) in shared {
+            merged.cell_neighbors[a].insert(b);
+            merged.cell_neighbors[b].insert(a);
+        }
+        merged
+    }
+
+    /// Simplifies the triangles down to about `target_faces` by repeatedly
+    /// collapsing the edge whose removal moves the surface least (by the
+    /// quadric error of the planes around it), e.g. for low poly distant moons.
+    /// Collapses that would tear the surface or flip a face are skipped, so a
+    /// closed mesh stays closed, and if no more are possible it stops early.
+    /// Cells are rebuilt around the remaining vertices with `recell`, so this
+    /// wants the triangles from before `dual`, which can then be applied again.
+    pub fn decimate_to_target(mut self, target_faces: usize) -> Self {
+        let face_plane = |[a, b, c]: [DVec3; 3]| {
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            normal.extend(-normal.dot(a))
+        };
+        let error = |quadric: &DMat4, v: DVec3| {
+            let v = v.extend(1.0);
+            v.dot(*quadric * v).max(0.0)
+        };
+
+        let mut positions: Vec<DVec3> = self.vertices.iter().map(|v| v.as_dvec3()).collect();
+        let mut faces: Vec<Option<[usize; 3]>> = self.faces.iter().copied().map(Some).collect();
+        let mut vertex_faces = vec![BTreeSet::new(); positions.len()];
+        let mut quadrics = vec![DMat4::ZERO; positions.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            let plane = face_plane(face.map(|v| positions[v]));
+            let quadric = DMat4::from_cols(
+                plane * plane.x,
+                plane * plane.y,
+                plane * plane.z,
+                plane * plane.w,
+            );
+            for &v in face {
+                vertex_faces[v].insert(f);
+                quadrics[v] += quadric;
+            }
+        }
+
+        let neighbors =
+            |vertex_faces: &[BTreeSet<usize>], faces: &[Option<[usize; 3]>], v: usize| {
+                vertex_faces[v]
+                    .iter()
+                    .filter_map(|&f| faces[f])
+                    .flatten()
+                    .filter(|&n| n != v)
+                    .collect::<BTreeSet<usize>>()
+            };
+
+        // Where an edge collapses to and what that costs. Besides the two ends
+        // the midpoint is tried, lifted back out to their distance from the origin
+        // so the sphere doesn't shrink.
+        let collapse = |positions: &[DVec3], quadrics: &[DMat4], a: usize, b: usize| {
+            let quadric = quadrics[a] + quadrics[b];
+            let (pa, pb) = (positions[a], positions[b]);
+            let mid = ((pa + pb) / 2.0).normalize_or_zero() * (pa.length() + pb.length()) / 2.0;
+            [pa, pb, mid]
+                .into_iter()
+                .map(|p| (error(&quadric, p), p))
+                .min_by(|x, y| x.0.total_cmp(&y.0))
+                .expect("there are three candidates")
+        };
+
+        // Edges are queued with the versions of their ends, so edges whose ends
+        // have moved since are skipped when popped. Errors are never negative,
+        // so their bits order the same as the floats themselves.
+        let mut versions = vec![0usize; positions.len()];
+        let mut queue = BinaryHeap::new();
         for face in &self.faces {
-            cell_neighbors[face[0]].insert(face[1]);
-            cell_neighbors[face[1]].insert(face[2]);
-            cell_neighbors[face[2]].insert(face[0]);
+            for i in 0..3 {
+                let (a, b) = helpers::ordered_2tuple(face[i], face[(i + 1) % 3]);
+                let (cost, _) = collapse(&positions, &quadrics, a, b);
+                queue.push(Reverse((cost.to_bits(), a, b, 0, 0)));
+            }
+        }
+
+        let mut face_count = self.faces.len();
+        while face_count > target_faces.max(4) {
+            let Some(Reverse((_, a, b, version_a, version_b))) = queue.pop() else {
+                break;
+            };
+            if versions[a] != version_a || versions[b] != version_b {
+                continue;
+            }
+
+            // The ends may only share the two vertices opposite the edge,
+            // otherwise collapsing it pinches the surface
+            let neighbors_a = neighbors(&vertex_faces, &faces, a);
+            let neighbors_b = neighbors(&vertex_faces, &faces, b);
+            if !neighbors_a.contains(&b) || neighbors_a.intersection(&neighbors_b).count() != 2 {
+                continue;
+            }
+
+            let (_, target) = collapse(&positions, &quadrics, a, b);
+            let moved = |v: usize| {
+                if v == a || v == b {
+                    target
+                } else {
+                    positions[v]
+                }
+            };
+            let flips = vertex_faces[a]
+                .iter()
+                .chain(&vertex_faces[b])
+                .filter_map(|&f| faces[f])
+                .filter(|face| !(face.contains(&a) && face.contains(&b)))
+                .any(|face| {
+                    let before = face_plane(face.map(|v| positions[v])).truncate();
+                    let after = face_plane(face.map(moved)).truncate();
+                    before.dot(after) <= 0.0
+                });
+            if flips {
+                continue;
+            }
+
+            // Everything on `b` moves over to `a`
+            positions[a] = target;
+            quadrics[a] = quadrics[a] + quadrics[b];
+            for f in std::mem::take(&mut vertex_faces[b]) {
+                let Some(face) = &mut faces[f] else {
+                    continue;
+                };
+                if face.contains(&a) {
+                    for v in *face {
+                        vertex_faces[v].remove(&f);
+                    }
+                    faces[f] = None;
+                    face_count -= 1;
+                } else {
+                    face.iter_mut().filter(|v| **v == b).for_each(|v| *v = a);
+                    vertex_faces[a].insert(f);
+                }
+            }
+
+            versions[a] += 1;
+            versions[b] += 1;
+            for n in neighbors(&vertex_faces, &faces, a) {
+                let (x, y) = helpers::ordered_2tuple(a, n);
+                let (cost, _) = collapse(&positions, &quadrics, x, y);
+                queue.push(Reverse((cost.to_bits(), x, y, versions[x], versions[y])));
+            }
         }
 
-        self.cells = cells
-            .into_values()
-            .map(|f| f.into_iter().collect())
+        // Drop the vertices that were collapsed away
+        let mut remap = vec![usize::MAX; positions.len()];
+        self.vertices = Vec::new();
+        for (v, faces) in vertex_faces.iter().enumerate() {
+            if !faces.is_empty() {
+                remap[v] = self.vertices.len();
+                self.vertices.push(positions[v].as_vec3());
+            }
+        }
+        self.faces = faces
+            .into_iter()
+            .flatten()
+            .map(|face| face.map(|v| remap[v]))
             .collect();
-        self.cell_neighbors = cell_neighbors;
-        self.cell_normals = self.cell_centroids();
-        self
+
+        self.recell()
+    }
+
+    /// Two cells are neighbors when they share a face, i.e. the fans around
+    /// both ends of a triangle edge overlap on the triangles of that edge.
+    fn neighbors_from_shared_faces(
+        cells: &[Vec<usize>],
+        face_count: usize,
+    ) -> Vec<BTreeSet<usize>> {
+        let mut face_cells = vec![Vec::new(); face_count];
+        for (ci, cell) in cells.iter().enumerate() {
+            for &f in cell {
+                face_cells[f].push(ci);
+            }
+        }
+
+        let mut cell_neighbors = vec![BTreeSet::default(); cells.len()];
+        for owners in face_cells {
+            for &a in &owners {
+                for &b in &owners {
+                    if a != b {
+                        cell_neighbors[a].insert(b);
+                    }
+                }
+            }
+        }
+        cell_neighbors
     }
 
-    pub(crate) fn icosahedron() -> Self {
+    /// The corners of the unit icosahedron every sphere is built from
+    fn icosahedron_vertices() -> [Vec3; 12] {
         let phi = (1.0 + 5.0_f32.sqrt()) / 2.0;
         let du = 1.0 / (phi * phi + 1.0).sqrt();
         let dv = phi * du;
 
-        let vertices = vec![
+        [
             Vec3::new(0.0, dv, du),
             Vec3::new(0.0, dv, -du),
             Vec3::new(0.0, -dv, du),
@@ -225,7 +921,11 @@ impl GeometryData {
             Vec3::new(dv, -du, 0.0),
             Vec3::new(-dv, du, 0.0),
             Vec3::new(-dv, -du, 0.0),
-        ];
+        ]
+    }
+
+    pub fn icosahedron() -> Self {
+        let vertices = Self::icosahedron_vertices().to_vec();
 
         let faces: Vec<[usize; 3]> = vec![
             [0, 1, 8],
@@ -256,96 +956,805 @@ impl GeometryData {
         })
         .collect();
 
-        let mut cells = BTreeMap::new();
-        for (i, face) in faces.iter().enumerate() {
-            for &v in face {
-                cells.entry(v).or_insert_with(BTreeSet::new).insert(i);
+        let cells = Self::cells_around_vertices(&vertices, &faces);
+        let cell_neighbors = Self::neighbors_from_shared_faces(&cells, faces.len());
+
+        let mut geo = GeometryData {
+            vertices,
+            faces,
+            cells,
+            cell_neighbors,
+            cell_normals: Vec::new(),
+            ..Default::default()
+        };
+        geo.cell_normals = geo.cell_centroids().to_vec();
+        geo
+    }
+
+    /// A cube with each side split into a `divisions` by `divisions` grid of
+    /// quads (two triangles each), pushed out onto the unit sphere. Like
+    /// [`Self::icosahedron`] it has a cell around every vertex, ready for
+    /// `dual`. Every vertex joins 6 triangles except 4 of the corners, which
+    /// join only 3 and so end up as triangular cells.
+    pub fn cube_sphere(divisions: usize) -> Self {
+        let n = divisions.max(1) as i32;
+
+        // Grid points are kept as integers (the cube spanning -n to n) until
+        // the end, so the points along the edges and corners that sides share
+        // match exactly
+        let mut indices = HashMap::<IVec3, usize>::new();
+        let mut points = Vec::new();
+        let mut faces = Vec::new();
+        for normal in [IVec3::X, IVec3::Y, IVec3::Z] {
+            for normal in [normal, -normal] {
+                let u = IVec3::new(normal.z.abs(), normal.x.abs(), normal.y.abs());
+                let v = normal.cross(u);
+                let mut index = |i: i32, j: i32| {
+                    let point = normal * n + u * (2 * i - n) + v * (2 * j - n);
+                    *indices.entry(point).or_insert_with(|| {
+                        points.push(point);
+                        points.len() - 1
+                    })
+                };
+
+                for i in 0..n {
+                    for j in 0..n {
+                        let [a, b, c, d] = [
+                            index(i, j),
+                            index(i + 1, j),
+                            index(i + 1, j + 1),
+                            index(i, j + 1),
+                        ];
+                        // u, v, normal is right handed, so these wind outward
+                        faces.push([a, b, c]);
+                        faces.push([a, c, d]);
+                    }
+                }
             }
         }
-        let cells: Vec<_> = cells
-            .into_values()
-            .map(|f| f.into_iter().collect())
-            .collect();
 
-        let mut cell_neighbors = vec![BTreeSet::default(); cells.len()];
-        for face in &faces {
-            cell_neighbors[face[0]].insert(face[1]);
-            cell_neighbors[face[1]].insert(face[2]);
-            cell_neighbors[face[2]].insert(face[0]);
-        }
+        let vertices: Vec<Vec3> = points.iter().map(|p| p.as_vec3().normalize()).collect();
+        let cells = Self::cells_around_vertices(&vertices, &faces);
+        let cell_neighbors = Self::neighbors_from_shared_faces(&cells, faces.len());
 
         let mut geo = GeometryData {
             vertices,
             faces,
             cells,
             cell_neighbors,
-            cell_normals: Vec::new(),
+            ..Default::default()
         };
-        geo.cell_normals = geo.cell_centroids();
+        geo.cell_normals = geo.cell_centroids().to_vec();
         geo
     }
 
-    // Returns the centroid of each cell
-    pub(crate) fn cell_centroids(&self) -> Vec<Vec3> {
+    /// The directions of the 12 pentagons of a hex sphere, which sit where the
+    /// corners of the icosahedron were whatever the subdivision. They come
+    /// straight from those corners, so they make stable landmarks (for poles,
+    /// capitals and the like) without having to find the pentagons. Only
+    /// meaningful for geometry built from [`Self::icosahedron`].
+    pub fn pentagon_anchors(&self) -> [Vec3; 12] {
+        Self::icosahedron_vertices()
+    }
+
+    /// A hex sphere with about `target_cells` cells. Each subdivision of the
+    /// icosahedron quadruples its faces, leaving `10 * 4^n + 2` vertices and so
+    /// as many cells in the dual, so this picks the `n` closest to the target
+    /// (by ratio rather than difference) and builds that.
+    pub fn subdivided_to_cells(target_cells: usize) -> Self {
+        let cells = |n: u32| 10 * 4usize.pow(n) + 2;
+        let off_by = |n: u32| (cells(n) as f32 / target_cells.max(1) as f32).ln().abs();
+        let subdivisions = (0..12)
+            .min_by(|&a, &b| off_by(a).total_cmp(&off_by(b)))
+            .expect("there are subdivision levels to choose from");
+
+        GeometryData::icosahedron()
+            .subdivide_n(subdivisions as usize)
+            .expect("the icosahedron is well formed")
+            .slerp()
+            .recell()
+            .dual()
+            .expect("recell puts a cell around every vertex")
+    }
+
+    // Returns the centroid of each cell, computed once and cached until
+    // the geometry next changes
+    pub fn cell_centroids(&self) -> &[Vec3] {
+        self.centroid_cache.get_or_init(|| {
+            self.cells
+                .iter()
+                .map(|fs| {
+                    let mut cent = Vec3::ZERO;
+                    for f in fs {
+                        let mut avg = Vec3::ZERO;
+                        for v in self.faces[*f] {
+                            avg += self.vertices[v];
+                        }
+                        cent += avg / 3.0;
+                    }
+                    cent
+                })
+                .collect()
+        })
+    }
+
+    /// Forgets the cached centroids, for after vertices, faces or cells change
+    pub fn invalidate_centroids(&mut self) {
+        self.centroid_cache.take();
+    }
+
+    /// The area of each cell projected onto the unit sphere (so its solid
+    /// angle), summed over the spherical triangles its faces project to. Every
+    /// cell is counted however many faces it has, so the areas of a closed
+    /// sphere add up to 4π. Multiply by the radius squared for a real area.
+    pub fn cell_areas(&self) -> Vec<f32> {
         self.cells
             .iter()
-            .map(|fs| {
-                let mut cent = Vec3::ZERO;
-                for f in fs {
-                    let mut avg = Vec3::ZERO;
-                    for v in self.faces[*f] {
-                        avg += self.vertices[v];
-                    }
-                    cent += avg / 3.0;
-                }
-                cent
+            .map(|faces| {
+                faces
+                    .iter()
+                    .map(|&f| {
+                        // Van Oosterom and Strackee's solid angle of a triangle
+                        let [a, b, c] = self.faces[f].map(|v| self.vertices[v].normalize());
+                        let numerator = a.dot(b.cross(c));
+                        let denominator = 1.0 + a.dot(b) + b.dot(c) + c.dot(a);
+                        2.0 * numerator.atan2(denominator).abs()
+                    })
+                    .sum()
             })
             .collect()
     }
 
-    // Returns the normal for each vertex
-    // assumes that vertex duplication has been done otherwise results are wierd
-    pub(crate) fn flat_normals(&self) -> Vec<Vec3> {
-        let centroids = &self.cell_normals;
-        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
-        for (ci, cell) in self.cells.iter().enumerate() {
-            let r = -0.0..=0.0;
-            let x = random_range(r.clone());
-            let y = random_range(r.clone());
-            let z = random_range(r);
-            for face in cell.iter().map(|c| self.faces[*c]) {
-                for v in face {
-                    normals[v] = (centroids[ci] + Vec3::new(x, y, z)).normalize();
+    /// The shortest walk from `start` to `goal` through neighboring cells, both
+    /// ends included, by A* over the great circle distance between cell
+    /// normals. `None` if no walk joins them.
+    pub fn path_between(&self, start: usize, goal: usize) -> Option<Vec<usize>> {
+        let distance =
+            |a: usize, b: usize| self.cell_normals[a].angle_between(self.cell_normals[b]);
+
+        let mut best = vec![f32::INFINITY; self.cells.len()];
+        let mut came_from = vec![usize::MAX; self.cells.len()];
+        // Costs are never negative, so their bits order the same as they do
+        let mut queue = BinaryHeap::new();
+        best[start] = 0.0;
+        queue.push(Reverse((distance(start, goal).to_bits(), start)));
+
+        while let Some(Reverse((_, cell))) = queue.pop() {
+            if cell == goal {
+                let mut path = vec![goal];
+                while let Some(&last) = path.last().filter(|&&last| last != start) {
+                    path.push(came_from[last]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for &next in &self.cell_neighbors[cell] {
+                let cost = best[cell] + distance(cell, next);
+                if cost < best[next] {
+                    best[next] = cost;
+                    came_from[next] = cell;
+                    queue.push(Reverse(((cost + distance(next, goal)).to_bits(), next)));
                 }
             }
         }
-        normals
+
+        None
     }
 
-    pub(crate) fn mesh(&self) -> Mesh {
-        let len = self.vertices.len();
-        Mesh::new(
-            TriangleList,
-            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-        )
-        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.vertices.clone())
-        .with_inserted_indices(Indices::U32(
-            self.faces.iter().flatten().map(|&f| f as u32).collect(),
+    /// Bakes an ambient occlusion factor per cell, from 0 (open sky) to 1 (fully
+    /// occluded). Casts `samples` rays over the hemisphere around each cell normal
+    /// and counts, cosine weighted, how many hit the surrounding cells' faces
+    /// within a few cells distance. A perfect sphere is never occluded.
+    pub fn bake_ao(&self, samples: usize) -> Vec<f32> {
+        // How far (in cell widths) geometry can be and still occlude a cell
+        const REACH: f32 = 3.0;
+
+        if samples == 0 {
+            return vec![0.0; self.cells.len()];
+        }
+
+        let centers: Vec<Vec3> = self
+            .cell_centroids()
+            .iter()
+            .zip(&self.cells)
+            .map(|(&c, faces)| c / faces.len() as f32)
+            .collect();
+
+        // The cell centers may have been displaced off the unit sphere
+        let bounds = centers
+            .iter()
+            .map(|c| c.abs().max_element())
+            .fold(1.0, f32::max);
+        let mut octree = Octree::new(32, Vec3::ZERO, bounds, 0, vec![]);
+        for (value, &position) in centers.iter().enumerate() {
+            octree.insert(Point { position, value });
+        }
+
+        // Deterministic, evenly spread directions over the +Y hemisphere
+        let n = 2 * samples as u32;
+        let hemisphere: Vec<Vec3> = (0..samples as u32)
+            .map(|i| fibonacci_sphere_point(i, n))
+            .collect();
+
+        (0..self.cells.len())
+            .into_par_iter()
+            .map(|cell| {
+                let normal = self.cell_normals[cell].normalize();
+                let center = centers[cell];
+
+                let neighbors = &self.cell_neighbors[cell];
+                let spacing = neighbors
+                    .iter()
+                    .map(|&n| centers[n].distance(center))
+                    .sum::<f32>()
+                    / neighbors.len().max(1) as f32;
+                let reach = REACH * spacing;
+                let origin = center + normal * spacing * 1e-3;
+
+                // Faces of a cell can stick out a cell width past its center
+                let candidates = octree.within_distance(origin, reach + 2.0 * spacing);
+
+                let rotation = Quat::from_rotation_arc(Vec3::Y, normal);
+                let mut occluded = 0.0;
+                let mut total = 0.0;
+                for &dir in &hemisphere {
+                    let dir = rotation * dir;
+                    let weight = dir.dot(normal);
+                    total += weight;
+
+                    let hit = candidates
+                        .iter()
+                        .filter(|&&c| c != cell)
+                        .flat_map(|&c| &self.cells[c])
+                        .any(|&f| {
+                            let tri = self.faces[f].map(|v| self.vertices[v]);
+                            helpers::ray_triangle(origin, dir, tri).is_some_and(|t| t < reach)
+                        });
+                    if hit {
+                        occluded += weight;
+                    }
+                }
+
+                if total > 0.0 {
+                    occluded / total
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// A prevailing wind/current direction for each cell, tangent to the sphere.
+    ///
+    /// Air flows along the meridians within the three circulation bands of each
+    /// hemisphere (towards the equator below 30 degrees and above 60, poleward in
+    /// between), fading to nothing at the band edges. That flow is then deflected
+    /// by the coriolis effect, right in the north and left in the south, turning
+    /// it into the trade winds, westerlies and polar easterlies.
+    pub fn compute_currents(&self) -> Vec<Vec3> {
+        // How far the flow is turned at the poles, easing to none at the equator
+        const MAX_DEFLECTION: f32 = 1.2;
+
+        self.cell_normals
+            .iter()
+            .map(|normal| {
+                let n = normal.normalize();
+                // The planet spins about +Y
+                let Some(east) = Vec3::Y.cross(n).try_normalize() else {
+                    // Right on a pole there is no north to speak of
+                    return Vec3::ZERO;
+                };
+                let north = n.cross(east);
+
+                let latitude = n.y.clamp(-1.0, 1.0).asin();
+                let meridional = north * -(6.0 * latitude).sin();
+
+                Quat::from_axis_angle(n, -MAX_DEFLECTION * n.y) * meridional
+            })
+            .collect()
+    }
+
+    /// Smooths a per cell `field` (e.g. height or temperature) by replacing each
+    /// cell with the average of it and its neighbors, `iterations` times over.
+    /// Going by `cell_neighbors` rather than positions means nothing smears
+    /// across the poles or wherever longitude wraps.
+    pub fn smooth_field(&self, field: &[f32], iterations: usize) -> Vec<f32> {
+        let mut field = field.to_vec();
+        for _ in 0..iterations {
+            field = self
+                .cell_neighbors
+                .par_iter()
+                .enumerate()
+                .map(|(cell, neighbors)| {
+                    let sum = field[cell] + neighbors.iter().map(|&n| field[n]).sum::<f32>();
+                    sum / (neighbors.len() + 1) as f32
+                })
+                .collect();
+        }
+        field
+    }
+
+    // Returns the normal for each vertex
+    // assumes that vertex duplication has been done otherwise results are wierd
+    //
+    // Each cell normal is nudged by up to `jitter` along every axis, so cells
+    // catch the light a little differently. The nudge is picked from `seed`
+    // and where the cell is, so a cell looks the same in every chunk and on
+    // every run with the same seed.
+    pub fn flat_normals(&self, jitter: f32, seed: u64) -> Vec<Vec3> {
+        let centroids = &self.cell_normals;
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+        for (ci, cell) in self.cells.iter().enumerate() {
+            let [x, y, z] = centroids[ci].to_array().map(|c| c.to_bits() as u64);
+            let mut rng = StdRng::seed_from_u64(seed ^ x ^ y.rotate_left(21) ^ z.rotate_left(42));
+            let r = -jitter.abs()..=jitter.abs();
+            let nudge = Vec3::new(
+                rng.random_range(r.clone()),
+                rng.random_range(r.clone()),
+                rng.random_range(r),
+            );
+            for face in cell.iter().map(|c| self.faces[*c]) {
+                for v in face {
+                    normals[v] = (centroids[ci] + nudge).normalize();
+                }
+            }
+        }
+        normals
+    }
+
+    // Returns the geometric normal of each face, following its winding.
+    // Unlike `flat_normals` this ignores the cells entirely. Degenerate
+    // faces get a zero normal.
+    pub fn face_normals(&self) -> Vec<Vec3> {
+        self.faces
+            .iter()
+            .map(|&[a, b, c]| {
+                let (va, vb, vc) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+                (vb - va).cross(vc - va).normalize_or_zero()
+            })
+            .collect()
+    }
+
+    // Returns a smooth normal for each vertex, averaging the (area weighted)
+    // normals of every face touching that position. Works on duplicated
+    // vertices too since vertices are matched by position not index.
+    pub fn smooth_normals(&self) -> Vec<Vec3> {
+        let key = |v: Vec3| v.to_array().map(f32::to_bits);
+
+        let mut accumulated = BTreeMap::<[u32; 3], Vec3>::new();
+        for &[a, b, c] in &self.faces {
+            let (va, vb, vc) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+            let normal = (vb - va).cross(vc - va);
+            for v in [va, vb, vc] {
+                *accumulated.entry(key(v)).or_default() += normal;
+            }
+        }
+
+        self.vertices
+            .iter()
+            .map(|&v| {
+                accumulated
+                    .get(&key(v))
+                    .and_then(|n| n.try_normalize())
+                    .unwrap_or_else(|| v.normalize_or_zero())
+            })
+            .collect()
+    }
+
+    /// The geometry as a flat shaded mesh, see `flat_normals` for `jitter`
+    /// and `seed`
+    pub fn mesh(&self, jitter: f32, seed: u64) -> Mesh {
+        self.mesh_with_normals(self.flat_normals(jitter, seed))
+    }
+
+    /// Like [`Self::mesh`], but with each cell colored by `cell_colors`
+    /// rather than a random color. Vertices shared between cells take the
+    /// color of the last, so `duplicate` first to keep the cells apart.
+    pub fn mesh_with_colors(&self, cell_colors: &[Color]) -> Result<Mesh, GeometryError> {
+        Ok(self
+            .mesh(0.0, 0)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.vertex_colors(cell_colors)?))
+    }
+
+    /// The linear color of each vertex, from the color of its cell. Vertices
+    /// in no cell are white.
+    pub fn vertex_colors(&self, cell_colors: &[Color]) -> Result<Vec<[f32; 4]>, GeometryError> {
+        Self::check_attribute_length(self.cells.len(), cell_colors.len())?;
+        let mut colors = vec![[1.0; 4]; self.vertices.len()];
+        for (faces, color) in self.cells.iter().zip(cell_colors) {
+            let color = color.to_linear().to_f32_array();
+            for &f in faces {
+                for v in self.faces[f] {
+                    colors[v] = color;
+                }
+            }
+        }
+        Ok(colors)
+    }
+
+    /// Like [`Self::mesh`], but shaded smoothly across cells with
+    /// `smooth_normals`, which suits displaced terrain better than facets.
+    /// Duplicated vertices are matched by position, so this works either side
+    /// of `duplicate`.
+    pub fn mesh_smooth(&self) -> Mesh {
+        self.mesh_with_normals(self.smooth_normals())
+    }
+
+    fn mesh_with_normals(&self, normals: Vec<Vec3>) -> Mesh {
+        let len = self.vertices.len();
+        Mesh::new(
+            TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.vertices.clone())
+        .with_inserted_indices(Indices::U32(
+            self.faces.iter().flatten().map(|&f| f as u32).collect(),
         ))
         .with_inserted_attribute(
             Mesh::ATTRIBUTE_COLOR,
             vec![[random(), random(), random(), 1.0]; len],
         )
-        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.flat_normals())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    }
+
+    /// Borrows each cell in turn, along with its normal and neighbors
+    pub fn iter_cells(&self) -> impl Iterator<Item = CellView<'_>> {
+        self.cell_normals
+            .iter()
+            .zip(&self.cell_neighbors)
+            .enumerate()
+            .map(|(index, (&normal, neighbors))| CellView {
+                index,
+                normal,
+                neighbors,
+            })
+    }
+
+    /// Every cell whose normal is within `half_angle` radians of `axis`, e.g. the
+    /// cells facing the camera. A half angle of π or more gives every cell.
+    pub fn cells_in_cone(&self, axis: Vec3, half_angle: f32) -> Vec<usize> {
+        let axis = axis.normalize();
+        let min_cos = half_angle.min(std::f32::consts::PI).cos();
+
+        self.cell_normals
+            .iter()
+            .enumerate()
+            .filter(|(_, normal)| normal.normalize().dot(axis) >= min_cos)
+            .map(|(cell, _)| cell)
+            .collect()
+    }
+
+    /// Labels each cell satisfying `predicate` with the id (counting up from 0)
+    /// of the group of such cells it is connected to through its neighbors, e.g.
+    /// the continent it is on. Cells not satisfying `predicate` get `usize::MAX`.
+    pub fn connected_components(&self, predicate: impl Fn(usize) -> bool) -> Vec<usize> {
+        let included: Vec<bool> = (0..self.cells.len()).map(&predicate).collect();
+        let mut components = vec![usize::MAX; self.cells.len()];
+        let mut next_id = 0;
+
+        for start in 0..self.cells.len() {
+            if !included[start] || components[start] != usize::MAX {
+                continue;
+            }
+
+            components[start] = next_id;
+            let mut stack = vec![start];
+            while let Some(cell) = stack.pop() {
+                for &neighbor in &self.cell_neighbors[cell] {
+                    if included[neighbor] && components[neighbor] == usize::MAX {
+                        components[neighbor] = next_id;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            next_id += 1;
+        }
+
+        components
+    }
+
+    /// The neighbors of `cell` in ring order, counter-clockwise around it
+    /// when viewed from outside. Hexagons have 6, pentagons 5.
+    pub fn ordered_neighbors(&self, cell: usize) -> Vec<usize> {
+        helpers::sort_around(
+            self.cell_normals[cell],
+            &self.cell_normals,
+            self.cell_neighbors[cell].iter().copied().collect(),
+        )
+    }
+
+    /// The unique vertices of `cell`, counter-clockwise around it when viewed
+    /// from outside. After `dual` hexagons have 6 and pentagons 5.
+    pub fn cell_vertices(&self, cell: usize) -> Vec<usize> {
+        let vertices: Vec<usize> = self.cells[cell]
+            .iter()
+            .flat_map(|&f| self.faces[f])
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let center =
+            vertices.iter().map(|&v| self.vertices[v]).sum::<Vec3>() / vertices.len() as f32;
+        helpers::sort_around(center, &self.vertices, vertices)
+    }
+
+    /// The positions of `cell_vertices`, in the same order
+    pub fn cell_vertex_positions(&self, cell: usize) -> Vec<Vec3> {
+        self.cell_vertices(cell)
+            .into_iter()
+            .map(|v| self.vertices[v])
+            .collect()
+    }
+
+    /// Whether the direction `dir` passes through `cell`, i.e. lies inside one
+    /// of its triangles seen from the center of the sphere. Unlike the nearest
+    /// cell center, this follows the actual borders, which matters where cells
+    /// differ in size such as around the pentagons. Points exactly on a border
+    /// are in both cells.
+    pub fn cell_contains(&self, cell: usize, dir: Vec3) -> bool {
+        let Some(dir) = dir.try_normalize() else {
+            return false;
+        };
+
+        self.cells[cell].iter().any(|&f| {
+            let [a, b, c] = self.faces[f].map(|v| self.vertices[v]);
+            // Inside the triangles cone when on the same side of all three
+            // planes through its edges as the triangle itself, whatever its
+            // winding, and not the mirror image cone on the far side
+            let winding = a.cross(b).dot(c).signum();
+            [(a, b), (b, c), (c, a)]
+                .into_iter()
+                .all(|(u, v)| u.cross(v).dot(dir) * winding >= 0.0)
+                && (a + b + c).dot(dir) > 0.0
+        })
+    }
+
+    /// Where the ray from the center of the sphere through `point` crosses the
+    /// surface, i.e. the first face whose cone it passes through. `None` if it
+    /// misses every face.
+    pub fn project_radially(&self, point: Vec3) -> Option<Vec3> {
+        let dir = point.try_normalize()?;
+        self.faces.iter().find_map(|face| {
+            let [a, b, c] = face.map(|v| self.vertices[v]);
+            let normal = (b - a).cross(c - a);
+            let winding = normal.dot(a).signum();
+            let inside = [(a, b), (b, c), (c, a)]
+                .into_iter()
+                .all(|(u, v)| u.cross(v).dot(dir) * winding >= 0.0);
+            let along = normal.dot(dir);
+            (inside && along.abs() > f32::EPSILON).then(|| dir * (normal.dot(a) / along))
+        })
+    }
+
+    /// Where each of `positions` (vertices of a chunk inside the cells
+    /// `parent_cells`) sits on the mesh of the parent chunk, simplified as
+    /// `build_chunk_mesh` does to chunks over its threshold. Morphing from
+    /// these to the real positions hides the switch between the two. Points
+    /// the parent doesn't cover stay put.
+    pub fn morph_targets(&self, parent_cells: &[usize], positions: &[Vec3]) -> Vec<Vec3> {
+        let parent = self.sub_geometry(parent_cells).0.simplified();
+        positions
+            .iter()
+            .map(|&p| parent.project_radially(p).unwrap_or(p))
+            .collect()
+    }
+
+    /// Returns the edges on the border of a cell as pairs of vertex indices,
+    /// i.e. the edges of its faces that no other face in the cell shares
+    pub fn cell_border_edges(&self, cell: usize) -> Vec<[usize; 2]> {
+        let mut counts = BTreeMap::<(usize, usize), usize>::new();
+        for &f in &self.cells[cell] {
+            let [a, b, c] = self.faces[f];
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                *counts.entry(helpers::ordered_2tuple(u, v)).or_default() += 1;
+            }
+        }
+
+        counts
+            .into_iter()
+            .filter(|&(_, count)| count == 1)
+            .map(|((u, v), _)| [u, v])
+            .collect()
+    }
+
+    /// The border edge cells `a` and `b` have in common, if they are adjacent.
+    /// Relies on neighboring cells sharing vertices, so it must be used before `duplicate`.
+    pub fn shared_edge(&self, a: usize, b: usize) -> Option<[usize; 2]> {
+        if a == b {
+            return None;
+        }
+        let b_edges: BTreeSet<[usize; 2]> = self.cell_border_edges(b).into_iter().collect();
+        self.cell_border_edges(a)
+            .into_iter()
+            .find(|edge| b_edges.contains(edge))
+    }
+
+    /// A line list mesh of every cells border, for cheap far away chunks.
+    /// Vertices are laid out cell by cell, two per border edge.
+    pub fn border_mesh(&self) -> Mesh {
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        for cell in 0..self.cells.len() {
+            let normal = self.cell_normals[cell].normalize();
+            for [u, v] in self.cell_border_edges(cell) {
+                positions.extend([self.vertices[u], self.vertices[v]]);
+                normals.extend([normal, normal]);
+            }
+        }
+
+        let len = positions.len();
+        Mesh::new(
+            LineList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            vec![[random(), random(), random(), 1.0]; len],
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
     }
 
-    /// Returns the new geometry, and a mapping from old cells to new cells
-    pub(crate) fn sub_geometry(&self, cells: &[usize]) -> (Self, BTreeMap<usize, usize>) {
-        let mut chunk_vertices = Vec::new();
-        let mut chunk_faces = Vec::new();
+    /// A mesh of every cell as a flat topped column, for a boardgame look. Each
+    /// cells top sits `cell_heights[cell]` above `base_radius` (from the center
+    /// of the sphere), and a wall runs down the side of a cell wherever its
+    /// neighbor is lower, to the top of that neighbor. Edges with no cell on
+    /// the other side, as on a patch of cells, drop all the way to
+    /// `base_radius`. Every top and wall gets its own vertices, so they are
+    /// flat shaded. Relies on neighboring cells sharing vertices, so it must be
+    /// used before `duplicate`.
+    pub fn prism_mesh(
+        &self,
+        cell_heights: &[f32],
+        base_radius: f32,
+    ) -> Result<Mesh, GeometryError> {
+        Self::check_attribute_length(self.cells.len(), cell_heights.len())?;
+        let top_radius = |cell: usize| base_radius + cell_heights[cell];
+
+        // The cells either side of each border edge
+        let mut edge_cells = BTreeMap::<(usize, usize), Vec<usize>>::new();
+        for cell in 0..self.cells.len() {
+            for [u, v] in self.cell_border_edges(cell) {
+                edge_cells
+                    .entry(helpers::ordered_2tuple(u, v))
+                    .or_default()
+                    .push(cell);
+            }
+        }
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut indices = Vec::new();
+        for cell in 0..self.cells.len() {
+            let up = self.cell_normals[cell].normalize();
+            let radius = top_radius(cell);
+            let ring = self.cell_vertices(cell);
+            let on_top = |v: usize| self.vertices[v].normalize() * radius;
+
+            // The top, fanned from the center
+            let start = positions.len() as u32;
+            positions.push(up * radius);
+            positions.extend(ring.iter().map(|&v| on_top(v)));
+            normals.extend(std::iter::repeat_n(up, ring.len() + 1));
+            for i in 0..ring.len() as u32 {
+                let next = (i + 1) % ring.len() as u32;
+                indices.extend([start, start + 1 + i, start + 1 + next]);
+            }
+
+            // The walls, wound to face out of the cell as the ring is
+            // counter-clockwise from outside
+            for (i, &a) in ring.iter().enumerate() {
+                let b = ring[(i + 1) % ring.len()];
+                let floor = match edge_cells
+                    .get(&helpers::ordered_2tuple(a, b))
+                    .and_then(|cells| cells.iter().find(|&&other| other != cell))
+                {
+                    Some(&neighbor) => top_radius(neighbor),
+                    None => base_radius,
+                };
+                if floor >= radius {
+                    continue;
+                }
+
+                let [a_top, b_top] = [on_top(a), on_top(b)];
+                let [a_bottom, b_bottom] = [a, b].map(|v| self.vertices[v].normalize() * floor);
+                let normal = (b_top - a_top).cross(up).normalize_or_zero();
+
+                let start = positions.len() as u32;
+                positions.extend([a_top, b_top, b_bottom, a_bottom]);
+                normals.extend([normal; 4]);
+                indices.extend([start, start + 3, start + 2, start, start + 2, start + 1]);
+            }
+        }
+
+        let len = positions.len();
+        Ok(Mesh::new(
+            TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_indices(Indices::U32(indices))
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, vec![[1.0; 4]; len])
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals))
+    }
+
+    /// A triangle mesh of flat strips along the borders of `cells`, each drawn as
+    /// `style` says for the pair of cells either side of it (or left out if it
+    /// gives `None`). Borders between two of `cells` are only drawn once, so the
+    /// ring of a pentagon comes out with 5 strips and a hexagons with 6.
+    /// Relies on neighboring cells sharing vertices, so it must be used before `duplicate`.
+    pub(crate) fn styled_border_mesh(
+        &self,
+        cells: &[usize],
+        style: impl Fn(usize, usize) -> Option<BorderStyle>,
+    ) -> Mesh {
+        let included: BTreeSet<usize> = cells.iter().copied().collect();
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::new();
+
+        for &cell in cells {
+            let edges = self.cell_border_edges(cell);
+            for &neighbor in &self.cell_neighbors[cell] {
+                if included.contains(&neighbor) && neighbor < cell {
+                    continue;
+                }
+                let Some(BorderStyle { width, color }) = style(cell, neighbor) else {
+                    continue;
+                };
+                let neighbor_edges = self.cell_border_edges(neighbor);
+                let Some(&[u, v]) = edges.iter().find(|edge| neighbor_edges.contains(edge)) else {
+                    continue;
+                };
+
+                // Lifted a touch off the surface so the strips dont z-fight it
+                let (p, q) = (self.vertices[u] * 1.002, self.vertices[v] * 1.002);
+                let normal = (p + q).normalize();
+                let side = (q - p).cross(normal).normalize_or_zero() * width / 2.0;
+
+                let base = positions.len() as u32;
+                positions.extend([p - side, p + side, q + side, q - side]);
+                normals.extend([normal; 4]);
+                colors.extend([color.to_linear().to_f32_array(); 4]);
+                // `side` is the edge turned clockwise (seen from outside), so
+                // going p - side, p + side, q + side winds counter-clockwise
+                indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+        }
+
+        Mesh::new(
+            TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+        .with_inserted_indices(Indices::U32(indices))
+    }
+
+    /// Returns the new geometry, and a mapping from old cells to new cells.
+    /// Faces are copied once per cell, so cells should not share them (see
+    /// `validate_cells`).
+    pub fn sub_geometry(&self, cells: &[usize]) -> (Self, BTreeMap<usize, usize>) {
+        self.sub_geometry_in(cells, &mut ChunkBuffers::default())
+    }
+
+    /// Like [`Self::sub_geometry`], but the vertices and faces are built in
+    /// (and moved out of) `buffers`, so their capacity can be reused.
+    fn sub_geometry_in(
+        &self,
+        cells: &[usize],
+        buffers: &mut ChunkBuffers,
+    ) -> (Self, BTreeMap<usize, usize>) {
+        let ChunkBuffers {
+            vertices: chunk_vertices,
+            faces: chunk_faces,
+            vert_map,
+        } = buffers;
         let mut chunk_cells = Vec::new();
         let mut chunk_cell_normals = Vec::new();
-        let mut vert_map = BTreeMap::<usize, usize>::new();
         let mut cell_map = BTreeMap::new();
 
         for &cell_id in cells {
@@ -381,125 +1790,419 @@ impl GeometryData {
 
         (
             GeometryData {
-                vertices: chunk_vertices,
-                faces: chunk_faces,
+                vertices: std::mem::take(chunk_vertices),
+                faces: std::mem::take(chunk_faces),
                 cells: chunk_cells,
                 cell_neighbors: chunk_cell_neighbors,
                 cell_normals: chunk_cell_normals,
+                ..Default::default()
             },
             cell_map,
         )
     }
 
-    pub(crate) fn create_octree(&self) -> Octree {
-        let capacity = 128;
-        let bounds = 1.0;
-        let center = Vec3::ZERO;
-        let mut octree = Octree::new(capacity, center, bounds, 0, vec![]);
-
-        for (cell_index, &position) in self.cell_normals.iter().enumerate() {
-            octree.insert(Point {
-                position,
-                value: cell_index,
-            });
-        }
-
-        octree
+    /// Builds everything a chunk made of `cells` needs to be rendered: the cells,
+    /// the chunks local geometry, a map from global to local cells and the mesh.
+    /// Chunks with more than `simplify_threshold` cells are simplified into one.
+    pub(crate) fn build_chunk_mesh(
+        &self,
+        cells: &[usize],
+        simplify_threshold: usize,
+    ) -> (Vec<usize>, GeometryData, BTreeMap<usize, usize>, Mesh) {
+        self.build_chunk_mesh_pooled(
+            cells,
+            simplify_threshold,
+            ChunkShading::Duplicated,
+            &ChunkBufferPool::default(),
+        )
     }
 
-    pub fn simplify(mut self) -> Self {
-        // Determine how many cells each vertex is part of.
-        let mut cell_count_per_vertex = vec![0; self.vertices.len()];
-        for cell in &self.cells {
-            let mut cell_vertices = BTreeSet::new();
-            for face in cell.iter().map(|c| self.faces[*c]) {
-                for v_idx in face {
-                    cell_vertices.insert(v_idx);
+    /// Like [`Self::build_chunk_mesh`], but the intermediate geometry is built in
+    /// buffers borrowed from `pool`, which are handed back afterwards, and the
+    /// vertices of unsimplified chunks are laid out according to `shading`.
+    pub(crate) fn build_chunk_mesh_pooled(
+        &self,
+        cells: &[usize],
+        simplify_threshold: usize,
+        shading: ChunkShading,
+        pool: &ChunkBufferPool,
+    ) -> (Vec<usize>, GeometryData, BTreeMap<usize, usize>, Mesh) {
+        let mut buffers = pool.take();
+        let (mut scratch, mut cell_map) = self.sub_geometry_in(cells, &mut buffers);
+
+        let local_geometry = if scratch.cells.len() > simplify_threshold {
+            for v in cell_map.values_mut() {
+                // all original cells point into the ONE simple cell
+                *v = 0;
+            }
+            scratch.simplified()
+        } else {
+            let (mut vertices, mut faces) = pool.take_output();
+            match shading {
+                ChunkShading::Duplicated => duplicate_faces_into(
+                    &scratch.vertices,
+                    &scratch.faces,
+                    &mut vertices,
+                    &mut faces,
+                ),
+                ChunkShading::Welded => {
+                    vertices.extend_from_slice(&scratch.vertices);
+                    faces.extend_from_slice(&scratch.faces);
                 }
             }
-            for v_idx in cell_vertices {
-                cell_count_per_vertex[v_idx] += 1;
+            GeometryData {
+                vertices,
+                faces,
+                cells: std::mem::take(&mut scratch.cells),
+                cell_neighbors: std::mem::take(&mut scratch.cell_neighbors),
+                cell_normals: std::mem::take(&mut scratch.cell_normals),
+                ..Default::default()
             }
+        };
+
+        buffers.vertices = std::mem::take(&mut scratch.vertices);
+        buffers.faces = std::mem::take(&mut scratch.faces);
+        pool.give(buffers);
+
+        let mut mesh = local_geometry.mesh(0.0, 0);
+        mesh.insert_attribute(
+            ATTRIBUTE_BLEND_COLOR,
+            vec![[1.0, 0.0, 0.0, 1.0]; local_geometry.vertices.len()],
+        );
+        mesh.insert_attribute(
+            ATTRIBUTE_EMISSIVE,
+            vec![[0.0; 4]; local_geometry.vertices.len()],
+        );
+        mesh.insert_attribute(ATTRIBUTE_MORPH_TARGET, local_geometry.vertices.clone());
+        if shading == ChunkShading::Welded {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, local_geometry.smooth_normals());
         }
 
-        // Decide which vertices are "internal" based on how many cells they belong to
-        let threshold = 3;
-        let mut is_internal = vec![false; self.vertices.len()];
-        for (v_idx, &count) in cell_count_per_vertex.iter().enumerate() {
-            if count >= threshold {
-                is_internal[v_idx] = true;
-            }
+        (cells.to_vec(), local_geometry, cell_map, mesh)
+    }
+
+    /// Like [`Self::build_chunk_mesh`], but the mesh is a line list of the cell borders
+    pub(crate) fn build_chunk_border_mesh(
+        &self,
+        cells: &[usize],
+    ) -> (Vec<usize>, GeometryData, BTreeMap<usize, usize>, Mesh) {
+        let (local_geometry, cell_map) = self.sub_geometry(cells);
+
+        let mut mesh = local_geometry.border_mesh();
+        let len = mesh.count_vertices();
+        mesh.insert_attribute(ATTRIBUTE_BLEND_COLOR, vec![[1.0, 0.0, 0.0, 1.0]; len]);
+        mesh.insert_attribute(ATTRIBUTE_EMISSIVE, vec![[0.0; 4]; len]);
+        // Border lines are already the coarsest tier, so there is nothing to morph from
+        if let Some(positions) = mesh.attribute(Mesh::ATTRIBUTE_POSITION).cloned() {
+            mesh.insert_attribute(ATTRIBUTE_MORPH_TARGET, positions);
         }
 
-        // Calculate the avg and slerp it
-        let mut avg = Vec3::ZERO;
-        for vert in &self.vertices {
-            avg += vert;
+        (cells.to_vec(), local_geometry, cell_map, mesh)
+    }
+
+    pub fn create_octree(&self) -> Octree {
+        OctreeConfig {
+            capacity: 128,
+            ..Default::default()
         }
-        avg /= self.vertices.len() as f32;
-        avg = avg.normalize();
+        .build(&self.cell_normals)
+    }
 
-        // Generate the new list of vertices and store a map
-        let mut map = BTreeMap::<usize, usize>::new();
-        let mut boundary_vertices = vec![avg];
-        for (v_idx, vertex) in self.vertices.iter().enumerate() {
-            if is_internal[v_idx] {
-                continue;
-            }
-            map.entry(v_idx).or_insert_with(|| {
-                boundary_vertices.push(*vertex);
-                boundary_vertices.len() - 1
-            });
+    /// A KD-tree over the cell centers, for finding the cell nearest a point.
+    /// Unlike the octree it can't be updated, but it stays balanced over the
+    /// sphere surface, so point queries can be faster.
+    pub fn build_kdtree(&self) -> KdTree {
+        KdTree::new(&self.cell_normals)
+    }
+
+    /// A stable id for `cell`, from the direction of its center alone, so the
+    /// same place on the planet gets the same id whatever the subdivision and
+    /// however the cells are ordered. See [`Self::direction_id`].
+    pub fn cell_id(&self, cell: usize) -> u64 {
+        Self::direction_id(self.cell_normals[cell])
+    }
+
+    /// The hierarchical id of the point in direction `dir`, like HTM but on the
+    /// icosahedron. The top bits pick one of its 20 faces, then every 2 bits
+    /// below pick which quarter of the triangle so far the point lies in,
+    /// splitting at the (normalized) edge midpoints, for [`CELL_ID_LEVELS`]
+    /// levels. Points in the same triangle at some level share the bits down
+    /// to it, so a fine cell shares a prefix with the coarse cell it lies in
+    /// and nearby cells usually have close ids.
+    pub fn direction_id(dir: Vec3) -> u64 {
+        static BASE_FACES: OnceLock<Vec<[DVec3; 3]>> = OnceLock::new();
+        let base_faces = BASE_FACES.get_or_init(|| {
+            let icosahedron = Self::icosahedron();
+            icosahedron
+                .faces
+                .iter()
+                .map(|face| {
+                    let [a, b, c] = face.map(|v| icosahedron.vertices[v].as_dvec3().normalize());
+                    // Wound counter-clockwise seen from outside, for the tests below
+                    if a.cross(b).dot(c) < 0.0 {
+                        [a, c, b]
+                    } else {
+                        [a, b, c]
+                    }
+                })
+                .collect()
+        });
+
+        let p = dir.as_dvec3().normalize();
+        // Which side of the great circle through `a` and `b` the point is on,
+        // positive to the left
+        let side = |a: DVec3, b: DVec3| a.cross(b).dot(p);
+
+        // Points on an edge could go either way, so take the face they are
+        // most inside of
+        let (face, &[mut a, mut b, mut c]) = base_faces
+            .iter()
+            .enumerate()
+            .max_by(|(_, x), (_, y)| {
+                let inside = |[a, b, c]: [DVec3; 3]| side(a, b).min(side(b, c)).min(side(c, a));
+                inside(**x).total_cmp(&inside(**y))
+            })
+            .expect("the icosahedron has faces");
+
+        let mut id = face as u64;
+        for _ in 0..CELL_ID_LEVELS {
+            let ab = (a + b).normalize();
+            let bc = (b + c).normalize();
+            let ca = (c + a).normalize();
+
+            let (child, triangle) = if side(ab, ca) >= 0.0 {
+                (0, [a, ab, ca])
+            } else if side(bc, ab) >= 0.0 {
+                (1, [ab, b, bc])
+            } else if side(ca, bc) >= 0.0 {
+                (2, [ca, bc, c])
+            } else {
+                (3, [ab, bc, ca])
+            };
+            id = (id << 2) | child;
+            [a, b, c] = triangle;
         }
 
-        // Figure out the edges we will keep
-        let mut boundary_edges = Vec::new();
-        for &face in &self.faces {
-            let [i0, i1, i2] = face;
-            if !(is_internal[i0] || is_internal[i1]) {
-                boundary_edges.push([map[&i0], map[&i1]]);
+        id
+    }
+
+    pub fn simplify(self) -> Self {
+        self.simplified()
+    }
+
+    /// The boundary of the geometry stitched into closed loops of vertex indices,
+    /// one per boundary (so a patch with a hole has two). Boundary edges are the
+    /// face edges no other face shares, and each loop follows the winding of its
+    /// faces, so outer boundaries run counter-clockwise when viewed from outside
+    /// and holes clockwise. Relies on faces sharing vertices, so it must be used
+    /// before `duplicate`.
+    pub fn boundary_loops(&self) -> Vec<Vec<usize>> {
+        let mut counts = BTreeMap::<(usize, usize), usize>::new();
+        for &[a, b, c] in &self.faces {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                *counts.entry(helpers::ordered_2tuple(u, v)).or_default() += 1;
             }
-            if !(is_internal[i1] || is_internal[i2]) {
-                boundary_edges.push([map[&i1], map[&i2]]);
+        }
+
+        // Where each boundary edge leads, in face winding order. A vertex can
+        // start more than one edge where two loops pinch together.
+        let mut next = BTreeMap::<usize, Vec<usize>>::new();
+        for &[a, b, c] in &self.faces {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                if counts[&helpers::ordered_2tuple(u, v)] == 1 {
+                    next.entry(u).or_default().push(v);
+                }
             }
-            if !(is_internal[i2] || is_internal[i0]) {
-                boundary_edges.push([map[&i2], map[&i0]]);
+        }
+
+        let mut loops = Vec::new();
+        while let Some((&start, _)) = next.iter().next() {
+            let mut boundary = vec![start];
+            let mut current = start;
+            // Stops early on an open boundary, which a closed set of faces never has
+            while let Some(outgoing) = next.get_mut(&current) {
+                let to = outgoing.pop().expect("empty entries are removed");
+                if outgoing.is_empty() {
+                    next.remove(&current);
+                }
+                if to == start {
+                    break;
+                }
+                boundary.push(to);
+                current = to;
             }
+            loops.push(boundary);
         }
 
-        // Fan triangulation the boundary edges into faces
+        loops
+    }
+
+    /// Collapses the geometry into a single cell by fanning each of its outer
+    /// boundary loops from their center. Holes are left out, so they end up
+    /// covered by the fan of the loop around them.
+    fn simplified(&self) -> Self {
+        let mut vertices = Vec::new();
         let mut faces = Vec::new();
-        for edge in boundary_edges {
-            if edge.contains(&0) {
+
+        for boundary in self.boundary_loops() {
+            if boundary.len() < 3 {
                 continue;
             }
-            faces.push([0, edge[0], edge[1]]);
+
+            let points: Vec<Vec3> = boundary.iter().map(|&v| self.vertices[v]).collect();
+            let center = (points.iter().sum::<Vec3>() / points.len() as f32).normalize();
+
+            // Holes wind the other way around their center
+            let winding: Vec3 = (0..points.len())
+                .map(|i| points[i].cross(points[(i + 1) % points.len()]))
+                .sum();
+            if winding.dot(center) < 0.0 {
+                continue;
+            }
+
+            let hub = vertices.len();
+            vertices.push(center);
+            vertices.extend(points);
+            let len = boundary.len();
+            for i in 0..len {
+                faces.push([hub, hub + 1 + i, hub + 1 + (i + 1) % len]);
+            }
         }
 
         // And then we just create one cell that stores all faces
         let cells: Vec<Vec<usize>> = vec![(0..faces.len()).collect()];
         let cell_neighbors = vec![BTreeSet::new()];
 
-        self.vertices = boundary_vertices;
-        self.faces = faces;
-        self.cells = cells;
-        self.cell_neighbors = cell_neighbors;
-        self.cell_normals = self.cell_centroids();
+        let mut simplified = GeometryData {
+            vertices,
+            faces,
+            cells,
+            cell_neighbors,
+            ..Default::default()
+        };
+        simplified.cell_normals = simplified.cell_centroids().to_vec();
 
-        self
+        simplified
+    }
+}
+
+/// Gives every face its own copy of its vertices (necessary for proper normals)
+fn duplicate_faces(vertices: &[Vec3], faces: &[[usize; 3]]) -> (Vec<Vec3>, Vec<[usize; 3]>) {
+    let mut new_vertices = Vec::new();
+    let mut new_faces = Vec::new();
+    duplicate_faces_into(vertices, faces, &mut new_vertices, &mut new_faces);
+
+    (new_vertices, new_faces)
+}
+
+/// Like [`duplicate_faces`], but appends to `new_vertices` and `new_faces` so
+/// their capacity can be reused
+fn duplicate_faces_into(
+    vertices: &[Vec3],
+    faces: &[[usize; 3]],
+    new_vertices: &mut Vec<Vec3>,
+    new_faces: &mut Vec<[usize; 3]>,
+) {
+    new_vertices.reserve(faces.len() * 3);
+    new_faces.reserve(faces.len());
+
+    for &[i0, i1, i2] in faces {
+        let v0 = vertices[i0];
+        let v1 = vertices[i1];
+        let v2 = vertices[i2];
+
+        let start_index = new_vertices.len();
+        new_vertices.push(v0);
+        new_vertices.push(v1);
+        new_vertices.push(v2);
+
+        new_faces.push([start_index, start_index + 1, start_index + 2]);
+    }
+}
+
+/// Scratch space for building a chunks intermediate geometry
+#[derive(Default)]
+pub(crate) struct ChunkBuffers {
+    vertices: Vec<Vec3>,
+    faces: Vec<[usize; 3]>,
+    vert_map: HashMap<usize, usize>,
+}
+
+/// At most this many chunks worth of recycled vertices and faces are kept
+const MAX_SPARE_OUTPUTS: usize = 64;
+
+/// The vertices and faces of a chunks local geometry, emptied for reuse
+type SpareOutput = (Vec<Vec3>, Vec<[usize; 3]>);
+
+/// A shared pool of [`ChunkBuffers`], so chunk mesh tasks reuse the same few
+/// allocations instead of making fresh ones for every chunk. The vertices and
+/// faces of the geometry handed out with each chunk come back through
+/// [`Self::recycle`] once the chunk is gone, to be refilled by the next one.
+#[derive(Resource, Clone, Default)]
+pub(crate) struct ChunkBufferPool {
+    buffers: Arc<Mutex<Vec<ChunkBuffers>>>,
+    outputs: Arc<Mutex<Vec<SpareOutput>>>,
+}
+
+impl ChunkBufferPool {
+    /// Borrows a set of buffers, or makes new ones if the pool is empty
+    pub(crate) fn take(&self) -> ChunkBuffers {
+        self.buffers
+            .lock()
+            .ok()
+            .and_then(|mut pool| pool.pop())
+            .unwrap_or_default()
+    }
+
+    /// Returns buffers to the pool, keeping their capacity
+    pub(crate) fn give(&self, mut buffers: ChunkBuffers) {
+        buffers.vertices.clear();
+        buffers.faces.clear();
+        buffers.vert_map.clear();
+        if let Ok(mut pool) = self.buffers.lock() {
+            pool.push(buffers);
+        }
+    }
+
+    /// Empty vectors for a chunks vertices and faces, recycled if there are any
+    fn take_output(&self) -> SpareOutput {
+        self.outputs
+            .lock()
+            .ok()
+            .and_then(|mut outputs| outputs.pop())
+            .unwrap_or_default()
+    }
+
+    /// Takes back the vertices and faces of a chunks geometry once the chunk
+    /// no longer needs it
+    pub(crate) fn recycle(&self, geometry: GeometryData) {
+        let GeometryData {
+            mut vertices,
+            mut faces,
+            ..
+        } = geometry;
+        vertices.clear();
+        faces.clear();
+        if let Ok(mut outputs) = self.outputs.lock() {
+            if outputs.len() < MAX_SPARE_OUTPUTS {
+                outputs.push((vertices, faces));
+            }
+        }
     }
 }
 
 pub(crate) fn setup_demo_sphere(
-    flat_materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, FlatNormalMaterial>>>,
+    material: Res<HexsphereMaterial>,
     meshes: ResMut<Assets<Mesh>>,
     mut commands: Commands,
 ) {
     let geom = GeometryData::icosahedron()
         .subdivide_n(9)
+        .expect("the icosahedron is well formed")
         .slerp()
         .recell()
         .dual()
+        .expect("recell puts a cell around every vertex")
         .duplicate();
 
     let chunker = ChunkManager::new(geom);
@@ -514,14 +2217,828 @@ pub(crate) fn setup_demo_sphere(
     //         Mesh3d(meshes.add(geom.mesh())),
     //         Transform::IDENTITY.with_scale(Vec3::new(32.0, 32.0, 32.0)),
     //         // .with_translation(Vec3::new(random(), random(), random())),
-    //         MeshMaterial3d(flat_materials.add(ExtendedMaterial {
-    //             base: StandardMaterial {
-    //                 opaque_render_method: OpaqueRendererMethod::Auto,
-    //                 ..Default::default()
-    //             },
-    //             extension: FlatNormalMaterial {},
-    //         })),
+    //         MeshMaterial3d(material.0.clone()),
     //     ));
     // }
     // commands.spawn(chunker);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    fn sphere(n: usize) -> GeometryData {
+        GeometryData::icosahedron()
+            .subdivide_n(n)
+            .unwrap()
+            .slerp()
+            .recell()
+            .dual()
+            .unwrap()
+    }
+
+    fn assert_normals_follow_centroids(geometry: &GeometryData) {
+        for (normal, centroid) in geometry.cell_normals.iter().zip(geometry.cell_centroids()) {
+            assert!((normal.length() - 1.0).abs() < 1e-5, "{normal}");
+            assert!(normal.dot(centroid.normalize()) > 1.0 - 1e-5);
+        }
+    }
+
+    fn radius(geometry: &GeometryData) -> f32 {
+        geometry
+            .vertices
+            .iter()
+            .map(|v| v.length())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn scaling_doubles_the_radius_and_keeps_counts() {
+        let original = sphere(2);
+        let mut scaled = original.clone();
+        scaled.scale(2.0);
+
+        assert!((radius(&scaled) - 2.0 * radius(&original)).abs() < 1e-5);
+        assert_eq!(scaled.faces.len(), original.faces.len());
+        assert_eq!(scaled.cells.len(), original.cells.len());
+        assert_normals_follow_centroids(&scaled);
+    }
+
+    #[test]
+    fn transforms_round_trip() {
+        let original = sphere(2);
+        let offset = Vec3::new(3.0, -1.0, 0.5);
+        let quat = Quat::from_axis_angle(Vec3::new(1.0, 2.0, 3.0).normalize(), 0.7);
+
+        let mut moved = original.clone();
+        moved.translate(offset);
+        assert_normals_follow_centroids(&moved);
+        moved.rotate(quat);
+        moved.scale(3.0);
+        moved.scale(1.0 / 3.0);
+        moved.rotate(quat.inverse());
+        moved.translate(-offset);
+
+        for (a, b) in moved.vertices.iter().zip(&original.vertices) {
+            assert!(a.distance(*b) < 1e-4);
+        }
+        for (a, b) in moved.cell_normals.iter().zip(original.cell_centroids()) {
+            assert!(a.distance(b.normalize()) < 1e-4);
+        }
+    }
+
+    #[test]
+    fn deregulate_is_seeded_and_stays_on_the_sphere() {
+        let a = sphere(2).deregulate(0.2, 7);
+        let b = sphere(2).deregulate(0.2, 7);
+        let c = sphere(2).deregulate(0.2, 8);
+
+        assert_eq!(a.vertices, b.vertices);
+        assert_ne!(a.vertices, c.vertices);
+        for (jittered, v) in a.vertices.iter().zip(&sphere(2).vertices) {
+            assert!((jittered.length() - v.length()).abs() < 1e-5);
+        }
+        assert_normals_follow_centroids(&a);
+    }
+
+    #[test]
+    fn displace_keeps_cells_joined() {
+        let flat = sphere(2);
+        let mut displaced = flat.clone().duplicate();
+        displaced.displace_noise(3, 0.2, 2.0).unwrap();
+
+        // Copies of a vertex made by `duplicate` all move to the same place
+        let mut moved_to = BTreeMap::new();
+        for (before, after) in flat
+            .clone()
+            .duplicate()
+            .vertices
+            .iter()
+            .zip(&displaced.vertices)
+        {
+            let key = before.to_array().map(f32::to_bits);
+            let first = *moved_to.entry(key).or_insert(*after);
+            assert!(first.distance(*after) < 1e-6);
+        }
+        assert_ne!(displaced.vertices, flat.duplicate().vertices);
+        assert_normals_follow_centroids(&displaced);
+    }
+
+    #[test]
+    fn slerp_leaves_unit_normals_through_the_centroids() {
+        let recelled = GeometryData::icosahedron()
+            .subdivide_n(2)
+            .unwrap()
+            .recell()
+            .slerp();
+        let dual = sphere(2).slerp();
+
+        for geometry in [recelled, dual] {
+            assert!(!geometry.cell_normals.is_empty());
+            assert_eq!(geometry.cell_normals.len(), geometry.cells.len());
+            assert_normals_follow_centroids(&geometry);
+        }
+    }
+
+    #[test]
+    fn convex_sphere_is_not_occluded() {
+        let ao = sphere(3).bake_ao(32);
+        assert!(ao.iter().all(|&ao| ao < 1e-3), "{ao:?}");
+    }
+
+    #[test]
+    fn pit_is_occluded() {
+        let mut geometry = sphere(3);
+        let mut heights = vec![0.0; geometry.cells.len()];
+        heights[0] = -0.2;
+        geometry.displace(&heights).unwrap();
+
+        let ao = geometry.bake_ao(32);
+        assert!(ao[0] > 0.1, "{}", ao[0]);
+    }
+
+    fn component_count(geometry: &GeometryData) -> usize {
+        geometry
+            .connected_components(|_| true)
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+
+    #[test]
+    fn merged_icosahedra_stay_apart() {
+        let one = GeometryData::icosahedron();
+        let merged = one.clone().merge(GeometryData::icosahedron());
+
+        assert_eq!(merged.vertices.len(), 2 * one.vertices.len());
+        assert_eq!(merged.faces.len(), 2 * one.faces.len());
+        assert_eq!(merged.cells.len(), 2 * one.cells.len());
+        assert_eq!(merged.cell_neighbors.len(), 2 * one.cell_neighbors.len());
+        assert_eq!(merged.cell_normals.len(), 2 * one.cell_normals.len());
+        assert_eq!(component_count(&one), 1);
+        assert_eq!(component_count(&merged), 2);
+        let offset = one.vertices.len();
+        assert_eq!(
+            merged.faces[one.faces.len()],
+            one.faces[0].map(|v| v + offset)
+        );
+    }
+
+    #[test]
+    fn merge_linked_joins_shared_borders() {
+        let planet = sphere(1);
+        // Split into two caps along the equator, then put back together
+        let (north, south): (Vec<usize>, Vec<usize>) =
+            (0..planet.cells.len()).partition(|&cell| planet.cell_normals[cell].y >= 0.0);
+        let (north, _) = planet.sub_geometry(&north);
+        let (south, _) = planet.sub_geometry(&south);
+
+        assert_eq!(component_count(&north.clone().merge(south.clone())), 2);
+        assert_eq!(component_count(&north.merge_linked(south)), 1);
+    }
+
+    #[test]
+    fn chunk_cell_map_covers_every_cell() {
+        let geometry = sphere(2);
+        let cells: Vec<usize> = (0..20).collect();
+        // Both as separate cells and simplified into one
+        for threshold in [256, 4] {
+            let (built, local, cell_map, mesh) = geometry.build_chunk_mesh(&cells, threshold);
+            assert_eq!(built, cells);
+            assert_eq!(cell_map.keys().copied().collect::<Vec<_>>(), cells);
+            assert!(cell_map.values().all(|&cell| cell < local.cells.len()));
+            assert_eq!(mesh.count_vertices(), local.vertices.len());
+        }
+    }
+
+    #[test]
+    fn subdivide_with_keeps_linear_fields_linear() {
+        let field = |v: Vec3| 2.0 * v.x + v.y - 0.5 * v.z + 3.0;
+        let mut geom = GeometryData::icosahedron();
+        let mut attrs: Vec<f32> = geom.vertices.iter().map(|&v| field(v)).collect();
+        for _ in 0..3 {
+            geom = geom.subdivide_with(&mut attrs).unwrap();
+        }
+        assert_eq!(attrs.len(), geom.vertices.len());
+        for (&v, &attr) in geom.vertices.iter().zip(&attrs) {
+            assert!((attr - field(v)).abs() < 1e-4);
+        }
+
+        attrs.pop();
+        assert_eq!(
+            geom.subdivide_with(&mut attrs).err(),
+            Some(GeometryError::AttributeLength {
+                expected: attrs.len() + 1,
+                found: attrs.len(),
+            })
+        );
+    }
+
+    #[test]
+    fn subdividing_never_reallocates() {
+        // Reserving exactly what each subdivision adds leaves no spare capacity,
+        // where growing one push at a time would have doubled it
+        let mut geom = GeometryData::icosahedron();
+        let mut attrs = vec![0.0; geom.vertices.len()];
+        for _ in 0..4 {
+            geom = geom.subdivide_with(&mut attrs).unwrap();
+            assert_eq!(geom.vertices.capacity(), geom.vertices.len());
+            assert_eq!(geom.faces.capacity(), geom.faces.len());
+            assert_eq!(attrs.capacity(), attrs.len());
+        }
+    }
+
+    #[test]
+    fn vertex_colors_come_from_their_cell() {
+        let geom = sphere(2).duplicate();
+        let cell_colors: Vec<Color> = (0..geom.cells.len())
+            .map(|cell| Color::srgb(cell as f32 / geom.cells.len() as f32, 0.5, 0.0))
+            .collect();
+        let colors = geom.vertex_colors(&cell_colors).unwrap();
+        for (faces, color) in geom.cells.iter().zip(&cell_colors) {
+            let color = color.to_linear().to_f32_array();
+            assert!(faces
+                .iter()
+                .flat_map(|&f| geom.faces[f])
+                .all(|v| colors[v] == color));
+        }
+
+        assert_eq!(
+            geom.vertex_colors(&cell_colors[1..]).err(),
+            Some(GeometryError::AttributeLength {
+                expected: geom.cells.len(),
+                found: geom.cells.len() - 1,
+            })
+        );
+    }
+
+    #[test]
+    fn prism_walls_only_where_heights_differ() {
+        let vertex_count = |geom: &GeometryData, heights: &[f32]| {
+            geom.prism_mesh(heights, 1.0).unwrap().count_vertices()
+        };
+        // A top of one vertex per corner plus its center for every cell
+        let top_vertices = |geom: &GeometryData| {
+            geom.cells.len()
+                + (0..geom.cells.len())
+                    .map(|cell| geom.cell_vertices(cell).len())
+                    .sum::<usize>()
+        };
+
+        for n in 1..3 {
+            let geom = sphere(n);
+            let level = vec![0.1; geom.cells.len()];
+            assert_eq!(vertex_count(&geom, &level), top_vertices(&geom));
+        }
+
+        // A raised cell gets a wall down every side, a sunken one gives each
+        // of its neighbors one wall down into it
+        let geom = sphere(2);
+        let sides = geom.cell_vertices(0).len();
+        for height in [0.2, 0.0] {
+            let mut heights = vec![0.1; geom.cells.len()];
+            heights[0] = height;
+            assert_eq!(
+                vertex_count(&geom, &heights),
+                top_vertices(&geom) + 4 * sides
+            );
+        }
+
+        assert!(geom.prism_mesh(&[0.1], 1.0).is_err());
+    }
+
+    #[test]
+    fn chunk_has_one_boundary_loop() {
+        let geom = sphere(3);
+        assert!(geom.boundary_loops().is_empty());
+
+        // A cap of cells around the pole, carved out as a chunk would be
+        let cap: Vec<usize> = (0..geom.cells.len())
+            .filter(|&cell| geom.cell_normals[cell].normalize().y > 0.8)
+            .collect();
+        let (chunk, _) = geom.sub_geometry(&cap);
+        let loops = chunk.boundary_loops();
+        assert_eq!(loops.len(), 1);
+
+        // It runs around the pole, every vertex of it on the rim of the cap
+        let ring = &loops[0];
+        assert!(ring.len() > 6);
+        assert_eq!(ring.iter().collect::<BTreeSet<_>>().len(), ring.len());
+        let lowest = chunk
+            .vertices
+            .iter()
+            .map(|v| v.normalize().y)
+            .fold(1.0, f32::min);
+        assert!(ring
+            .iter()
+            .all(|&v| chunk.vertices[v].normalize().y < lowest + 0.1));
+    }
+
+    #[test]
+    fn cell_ids_are_unique_and_local() {
+        let geom = sphere(3);
+        let ids: Vec<u64> = (0..geom.cells.len())
+            .map(|cell| geom.cell_id(cell))
+            .collect();
+        assert_eq!(ids.iter().collect::<BTreeSet<_>>().len(), ids.len());
+
+        // How many leading bits two ids agree on, on average
+        let shared_bits = |pairs: &[(usize, usize)]| {
+            let total: u32 = pairs
+                .iter()
+                .map(|&(a, b)| (ids[a] ^ ids[b]).leading_zeros())
+                .sum();
+            total as f32 / pairs.len() as f32
+        };
+        let neighbors: Vec<(usize, usize)> = geom
+            .cell_neighbors
+            .iter()
+            .enumerate()
+            .flat_map(|(cell, neighbors)| neighbors.iter().map(move |&n| (cell, n)))
+            .collect();
+        let far: Vec<(usize, usize)> = (0..geom.cells.len())
+            .map(|cell| (cell, (cell + geom.cells.len() / 2) % geom.cells.len()))
+            .filter(|&(a, b)| geom.cell_normals[a].angle_between(geom.cell_normals[b]) > 1.0)
+            .collect();
+        assert!(
+            shared_bits(&neighbors) > shared_bits(&far) + 4.0,
+            "{} {}",
+            shared_bits(&neighbors),
+            shared_bits(&far)
+        );
+    }
+
+    #[test]
+    fn decimate_reaches_its_target() {
+        let triangles = GeometryData::icosahedron().subdivide_n(3).unwrap().slerp();
+        assert_eq!(triangles.faces.len(), 1280);
+        for target in [800, 320, 80] {
+            let decimated = triangles.clone().decimate_to_target(target);
+            // Each collapse removes two faces, so it can land just under
+            let faces = decimated.faces.len();
+            assert!(
+                faces <= target && faces + 4 >= target,
+                "{faces} for {target}"
+            );
+            assert_eq!(decimated.validate(), Ok(()));
+            assert!(decimated.dual().is_ok());
+        }
+    }
+
+    #[test]
+    fn normalize_cleans_up_a_messy_mesh() {
+        let clean = GeometryData::icosahedron().subdivide_n(2).unwrap().slerp();
+
+        // Every face gets its own copies of its corners, nudged either side
+        // of where they should be (so some pairs straddle a 1e-5 grid
+        // boundary), and every third face is wound inward
+        let mut messy = GeometryData::default();
+        for (i, face) in clean.faces.iter().enumerate() {
+            let mut corners = face.map(|v| {
+                let nudge = if messy.vertices.len() % 2 == 0 {
+                    4e-6
+                } else {
+                    -4e-6
+                };
+                messy.vertices.push(clean.vertices[v] + Vec3::X * nudge);
+                messy.vertices.len() - 1
+            });
+            if i % 3 == 0 {
+                corners.reverse();
+            }
+            messy.faces.push(corners);
+        }
+        assert!(messy.validate().is_err());
+
+        let normalized = messy.normalize();
+        assert_eq!(normalized.vertices.len(), clean.vertices.len());
+        assert_eq!(normalized.faces.len(), clean.faces.len());
+        assert_eq!(normalized.validate(), Ok(()));
+        assert!(normalized.dual().is_ok());
+    }
+
+    #[test]
+    fn cached_centroids_match_a_fresh_computation() {
+        let fresh = |geometry: &GeometryData| {
+            let mut geometry = geometry.clone();
+            geometry.invalidate_centroids();
+            geometry.cell_centroids().to_vec()
+        };
+        let mut geometry = sphere(2);
+        assert_eq!(geometry.cell_centroids(), fresh(&geometry));
+
+        // Moved by a method, which forgets the cache itself
+        let heights: Vec<f32> = (0..geometry.cells.len())
+            .map(|cell| (cell % 3) as f32 * 0.1)
+            .collect();
+        geometry.displace(&heights).unwrap();
+        assert_eq!(geometry.cell_centroids(), fresh(&geometry));
+
+        // Moved by hand, which has to
+        for v in &mut geometry.vertices {
+            *v *= 2.0;
+        }
+        geometry.invalidate_centroids();
+        assert_eq!(geometry.cell_centroids(), fresh(&geometry));
+    }
+
+    #[test]
+    fn cells_contain_their_own_center() {
+        let geom = sphere(3);
+        for cell in 0..geom.cells.len() {
+            let center = geom.cell_normals[cell];
+            assert!(geom.cell_contains(cell, center));
+            assert!(!geom.cell_contains(cell, -center));
+            assert!(geom.cell_neighbors[cell]
+                .iter()
+                .all(|&neighbor| !geom.cell_contains(cell, geom.cell_normals[neighbor])));
+        }
+        assert!(!geom.cell_contains(0, Vec3::ZERO));
+    }
+
+    #[test]
+    fn pentagon_anchors_sit_on_the_pentagons() {
+        let geom = sphere(3);
+        let pentagons: Vec<Vec3> = (0..geom.cells.len())
+            .filter(|&cell| geom.cell_neighbors[cell].len() == 5)
+            .map(|cell| geom.cell_normals[cell].normalize())
+            .collect();
+        assert_eq!(pentagons.len(), 12);
+
+        let anchors = geom.pentagon_anchors();
+        for pentagon in pentagons {
+            let matching = anchors
+                .iter()
+                .filter(|anchor| anchor.normalize().distance(pentagon) < 1e-4)
+                .count();
+            assert_eq!(matching, 1);
+        }
+    }
+
+    #[test]
+    fn ordered_neighbors_go_around_the_cell() {
+        let geom = sphere(3);
+        for cell in 0..geom.cells.len() {
+            let ring = geom.ordered_neighbors(cell);
+            assert_eq!(ring.len(), geom.cell_neighbors[cell].len());
+            assert_eq!(
+                ring.iter().copied().collect::<BTreeSet<_>>(),
+                geom.cell_neighbors[cell]
+            );
+
+            // Each neighbor borders the next, turning counter-clockwise
+            let center = geom.cell_normals[cell];
+            for (i, &a) in ring.iter().enumerate() {
+                let b = ring[(i + 1) % ring.len()];
+                assert!(geom.cell_neighbors[a].contains(&b));
+                let [a, b] = [a, b].map(|neighbor| geom.cell_normals[neighbor] - center);
+                assert!(a.cross(b).dot(center) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn recell_neighbors_are_symmetric() {
+        let recelled = GeometryData::icosahedron()
+            .subdivide_n(3)
+            .unwrap()
+            .slerp()
+            .recell();
+        for geom in [recelled.clone(), recelled.dual().unwrap()] {
+            let mut degrees = BTreeMap::<usize, usize>::new();
+            for (cell, neighbors) in geom.cell_neighbors.iter().enumerate() {
+                assert!(!neighbors.contains(&cell));
+                assert!(neighbors
+                    .iter()
+                    .all(|&neighbor| geom.cell_neighbors[neighbor].contains(&cell)));
+                *degrees.entry(neighbors.len()).or_default() += 1;
+            }
+            assert_eq!(
+                degrees,
+                BTreeMap::from([(5, 12), (6, geom.cells.len() - 12)])
+            );
+        }
+    }
+
+    #[test]
+    fn dual_cells_have_a_vertex_per_neighbor() {
+        let geom = sphere(3);
+        let mut pentagons = 0;
+        for cell in 0..geom.cells.len() {
+            let vertices = geom.cell_vertices(cell);
+            assert_eq!(vertices.len(), geom.cell_neighbors[cell].len());
+            assert!(matches!(vertices.len(), 5 | 6));
+            if vertices.len() == 5 {
+                pentagons += 1;
+            }
+        }
+        assert_eq!(pentagons, 12);
+    }
+
+    #[test]
+    fn shared_edges_are_on_both_borders() {
+        let geom = sphere(2);
+        for (a, neighbors) in geom.cell_neighbors.iter().enumerate() {
+            for &b in neighbors {
+                let edge = geom.shared_edge(a, b).unwrap();
+                for cell in [a, b] {
+                    let ring = geom.cell_vertices(cell);
+                    assert!(edge.iter().all(|v| ring.contains(v)));
+                    assert!(geom.cell_border_edges(cell).contains(&edge));
+                }
+            }
+        }
+
+        assert_eq!(geom.shared_edge(0, 0), None);
+        let far = (0..geom.cells.len())
+            .find(|&cell| geom.cell_normals[cell].dot(geom.cell_normals[0]) < 0.0)
+            .unwrap();
+        assert_eq!(geom.shared_edge(0, far), None);
+    }
+
+    #[test]
+    fn recell_orders_faces_into_a_fan() {
+        let geom = GeometryData::icosahedron()
+            .subdivide_n(3)
+            .unwrap()
+            .slerp()
+            .recell();
+        let centroid = |face: usize| {
+            geom.faces[face]
+                .map(|v| geom.vertices[v])
+                .iter()
+                .sum::<Vec3>()
+                / 3.0
+        };
+        for (vertex, faces) in geom.cells.iter().enumerate() {
+            let center = geom.vertices[vertex];
+            for (i, &a) in faces.iter().enumerate() {
+                let b = faces[(i + 1) % faces.len()];
+                // Around the cells vertex, each face sharing an edge with the
+                // next and turning the same way
+                assert!(geom.faces[a].contains(&vertex));
+                let shared = geom.faces[a]
+                    .iter()
+                    .filter(|v| geom.faces[b].contains(v))
+                    .count();
+                assert_eq!(shared, 2);
+                assert!(
+                    (centroid(a) - center)
+                        .cross(centroid(b) - center)
+                        .dot(center)
+                        > 0.0
+                );
+            }
+        }
+        assert_eq!(geom.dual().unwrap().validate(), Ok(()));
+    }
+
+    #[test]
+    fn two_islands_are_two_components() {
+        let geom = sphere(3);
+        // A cap around each pole, with the tropics between them as sea
+        let land = |cell: usize| geom.cell_normals[cell].normalize().y.abs() > 0.8;
+        let components = geom.connected_components(land);
+
+        let north = components[geom.cells_in_cone(Vec3::Y, 0.1)[0]];
+        let south = components[geom.cells_in_cone(Vec3::NEG_Y, 0.1)[0]];
+        assert_eq!(BTreeSet::from([north, south]), BTreeSet::from([0, 1]));
+        for (cell, &id) in components.iter().enumerate() {
+            let expected = match land(cell) {
+                false => usize::MAX,
+                true if geom.cell_normals[cell].y > 0.0 => north,
+                true => south,
+            };
+            assert_eq!(id, expected);
+        }
+    }
+
+    #[test]
+    fn face_normals_point_outward() {
+        let geom = GeometryData::icosahedron().subdivide_n(3).unwrap().slerp();
+        for (face, normal) in geom.faces.iter().zip(geom.face_normals()) {
+            let center = face
+                .map(|v| geom.vertices[v])
+                .iter()
+                .sum::<Vec3>()
+                .normalize();
+            assert!((normal.length() - 1.0).abs() < 1e-5);
+            assert!(normal.dot(center) > 0.99, "{normal} {center}");
+        }
+    }
+
+    #[test]
+    fn cone_selects_cells_by_angle() {
+        let geom = sphere(3);
+        let axis = Vec3::new(1.0, 2.0, -0.5);
+        for half_angle in [std::f32::consts::PI, 4.0] {
+            assert_eq!(
+                geom.cells_in_cone(axis, half_angle),
+                (0..geom.cells.len()).collect::<Vec<_>>()
+            );
+        }
+
+        let narrow = geom.cells_in_cone(axis, 0.3);
+        assert!(!narrow.is_empty() && narrow.len() < geom.cells.len() / 20);
+        for cell in 0..geom.cells.len() {
+            let angle = geom.cell_normals[cell].angle_between(axis);
+            assert_eq!(narrow.contains(&cell), angle <= 0.3, "{angle}");
+        }
+    }
+
+    #[test]
+    fn currents_are_tangent() {
+        let geom = sphere(3);
+        let currents = geom.compute_currents();
+        assert_eq!(currents.len(), geom.cells.len());
+        for (current, normal) in currents.iter().zip(&geom.cell_normals) {
+            assert!(current.dot(normal.normalize()).abs() < 1e-5);
+        }
+        assert!(currents.iter().any(|current| current.length() > 0.5));
+    }
+
+    #[test]
+    fn smoothing_reduces_variance() {
+        let geom = sphere(3);
+        let step: Vec<f32> = geom
+            .cell_normals
+            .iter()
+            .map(|normal| if normal.y > 0.0 { 1.0 } else { 0.0 })
+            .collect();
+        let variance = |field: &[f32]| {
+            let mean = field.iter().sum::<f32>() / field.len() as f32;
+            field.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / field.len() as f32
+        };
+
+        let mut last = variance(&step);
+        for iterations in [1, 2, 4, 8] {
+            let smoothed = geom.smooth_field(&step, iterations);
+            assert!(smoothed.iter().all(|x| (0.0..=1.0).contains(x)));
+            assert!(variance(&smoothed) < last);
+            last = variance(&smoothed);
+        }
+        assert_eq!(geom.smooth_field(&step, 0), step);
+    }
+
+    #[test]
+    fn subdivided_to_cells_lands_near_the_target() {
+        assert_eq!(GeometryData::subdivided_to_cells(642).cells.len(), 642);
+        let cells = GeometryData::subdivided_to_cells(10000).cells.len();
+        assert!((7500..=12500).contains(&cells), "{cells}");
+    }
+
+    #[test]
+    fn spherical_subdivision_evens_out_edges() {
+        // How much edge lengths vary, relative to their mean
+        let spread = |geom: &GeometryData| {
+            let lengths: Vec<f32> = geom
+                .faces
+                .iter()
+                .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+                .map(|(u, v)| geom.vertices[u].distance(geom.vertices[v]))
+                .collect();
+            let mean = lengths.iter().sum::<f32>() / lengths.len() as f32;
+            let variance =
+                lengths.iter().map(|l| (l - mean).powi(2)).sum::<f32>() / lengths.len() as f32;
+            variance.sqrt() / mean
+        };
+        let subdivided = |mode| {
+            (0..4).fold(GeometryData::icosahedron(), |geom, _| {
+                geom.subdivide_in_mode(mode).unwrap()
+            })
+        };
+
+        let linear = subdivided(SubdivideMode::Linear).slerp();
+        let spherical = subdivided(SubdivideMode::Spherical);
+        assert!(spherical
+            .vertices
+            .iter()
+            .all(|v| (v.length() - 1.0).abs() < 1e-5));
+        assert_eq!(linear.vertices.len(), spherical.vertices.len());
+        assert!(
+            spread(&spherical) < spread(&linear),
+            "{} {}",
+            spread(&spherical),
+            spread(&linear)
+        );
+    }
+
+    #[test]
+    fn iter_cells_views_every_cell() {
+        let geom = sphere(2);
+        let views: Vec<CellView> = geom.iter_cells().collect();
+        assert_eq!(views.len(), geom.cells.len());
+        for (cell, view) in views.iter().enumerate() {
+            assert_eq!(view.index, cell);
+            assert_eq!(view.normal, geom.cell_normals[cell]);
+            assert_eq!(view.neighbors, &geom.cell_neighbors[cell]);
+        }
+
+        let pentagons = geom
+            .iter_cells()
+            .filter(|cell| cell.neighbors.len() == 5)
+            .count();
+        assert_eq!(pentagons, 12);
+    }
+
+    #[test]
+    fn chunk_buffers_are_reused() {
+        let geometry = sphere(3);
+        let cells: Vec<usize> = (0..40).collect();
+        let pool = ChunkBufferPool::default();
+        let pooled = |pool: &ChunkBufferPool| pool.buffers.lock().unwrap().len();
+
+        geometry.build_chunk_mesh_pooled(&cells, 256, ChunkShading::Duplicated, &pool);
+        assert_eq!(pooled(&pool), 1);
+        let allocation = pool.buffers.lock().unwrap()[0].vertices.as_ptr();
+
+        // Building the same chunk again takes the same buffers back out
+        for _ in 0..3 {
+            geometry.build_chunk_mesh_pooled(&cells, 256, ChunkShading::Duplicated, &pool);
+            assert_eq!(pooled(&pool), 1);
+        }
+        let buffers = pool.take();
+        assert_eq!(buffers.vertices.as_ptr(), allocation);
+        assert!(buffers.vertices.capacity() > 0);
+        assert!(
+            buffers.vertices.is_empty() && buffers.faces.is_empty() && buffers.vert_map.is_empty()
+        );
+
+        // Two chunks building at once need two sets
+        let other = pool.take();
+        pool.give(buffers);
+        pool.give(other);
+        assert_eq!(pooled(&pool), 2);
+    }
+
+    /// Counts the allocations made on each thread, so tests running alongside
+    /// don't show up in each others counts. With bevys `dynamic_linking`, code
+    /// inside the std dylib (e.g. growing a `Vec`) allocates without going
+    /// through here, so the counts are a lower bound.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count_allocation() {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count_allocation();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations_during(f: impl FnOnce()) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    #[test]
+    fn pooled_chunk_builds_allocate_less() {
+        let geometry = sphere(3);
+        let cells: Vec<usize> = (0..40).collect();
+
+        for shading in [ChunkShading::Duplicated, ChunkShading::Welded] {
+            // Warm the pool up, as the first chunks of a sweep would
+            let pool = ChunkBufferPool::default();
+            let (_, local_geometry, _, _) =
+                geometry.build_chunk_mesh_pooled(&cells, 256, shading, &pool);
+            let recycled = local_geometry.vertices.as_ptr();
+            pool.recycle(local_geometry);
+
+            let fresh = allocations_during(|| {
+                geometry.build_chunk_mesh_pooled(&cells, 256, shading, &ChunkBufferPool::default());
+            });
+            let pooled = allocations_during(|| {
+                let (_, local_geometry, _, _) =
+                    geometry.build_chunk_mesh_pooled(&cells, 256, shading, &pool);
+                // The next chunk is built in the last ones vertices
+                assert_eq!(local_geometry.vertices.as_ptr(), recycled);
+                pool.recycle(local_geometry);
+            });
+            assert!(
+                pooled < fresh,
+                "{shading:?}: {pooled} allocations pooled, {fresh} fresh"
+            );
+        }
+    }
+}
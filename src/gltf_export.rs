@@ -0,0 +1,289 @@
+//! Exporting a whole (unchunked) planet as binary glTF, for use in other tools.
+//! Only built with the `gltf_export` feature.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use gltf::json::{
+    self,
+    validation::{Checked::Valid, USize64},
+};
+
+use crate::{
+    chunk_storage::Body,
+    colors::HexColors,
+    geometry_data::{GeometryData, GeometryError},
+};
+
+/// Writes `body` to `path` as a .glb at its unit size, colored by `colors`
+pub(crate) fn export_body_glb(body: &Body, colors: &HexColors, path: &Path) -> io::Result<()> {
+    let glb = body
+        .geometry
+        .to_gltf_with_colors(1.0, &colors.colors)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    fs::write(path, glb)
+}
+
+/// Writes every body to `<name>.glb` in the working directory when E is pressed
+pub(crate) fn export_bodies(
+    bodies: Query<(&Name, &Body, &HexColors)>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if !input.just_pressed(KeyCode::KeyE) {
+        return;
+    }
+
+    for (name, body, colors) in &bodies {
+        let path = PathBuf::from(format!("{}.glb", name.as_str().to_lowercase()));
+        match export_body_glb(body, colors, &path) {
+            Ok(()) => info!("Exported {name} to {path:?}"),
+            Err(error) => warn!("Couldn't export {name} to {path:?}: {error}"),
+        }
+    }
+}
+
+impl GeometryData {
+    /// A binary glTF (.glb) of the geometry scaled by `scale`, with flat
+    /// normals and every vertex colored white
+    pub fn to_gltf(&self, scale: f32) -> Vec<u8> {
+        self.to_gltf_with_colors(scale, &vec![Color::WHITE; self.cells.len()])
+            .expect("there is a color for every cell")
+    }
+
+    /// Like [`Self::to_gltf`], but each cell is colored by `cell_colors`
+    pub fn to_gltf_with_colors(
+        &self,
+        scale: f32,
+        cell_colors: &[Color],
+    ) -> Result<Vec<u8>, GeometryError> {
+        // Vertices on the border of a cell are shared with its neighbors unless
+        // the geometry was already duplicated (as `mesh()` expects), and a
+        // shared vertex can only have one color and normal
+        let geometry = self.clone().duplicate();
+
+        let positions: Vec<Vec3> = geometry.vertices.iter().map(|&v| v * scale).collect();
+        let normals = geometry.flat_normals(0.0, 0);
+        let colors = geometry.vertex_colors(cell_colors)?;
+        let indices: Vec<u32> = geometry.faces.iter().flatten().map(|&i| i as u32).collect();
+
+        // Everything lives in one buffer, one view after another
+        use json::buffer::Target::{ArrayBuffer, ElementArrayBuffer};
+        let floats = |values: Vec<f32>| -> Vec<u8> {
+            values.into_iter().flat_map(f32::to_le_bytes).collect()
+        };
+        let sections = [
+            (
+                floats(positions.iter().flat_map(|v| v.to_array()).collect()),
+                ArrayBuffer,
+            ),
+            (
+                floats(normals.iter().flat_map(|v| v.to_array()).collect()),
+                ArrayBuffer,
+            ),
+            (floats(colors.into_iter().flatten().collect()), ArrayBuffer),
+            (
+                indices.iter().flat_map(|i| i.to_le_bytes()).collect(),
+                ElementArrayBuffer,
+            ),
+        ];
+        let mut bin = Vec::new();
+        let mut views = Vec::new();
+        for (bytes, target) in sections {
+            views.push((bin.len(), bytes.len(), target));
+            bin.extend(bytes);
+        }
+
+        let mut root = json::Root::default();
+        let buffer = root.push(json::Buffer {
+            byte_length: USize64::from(bin.len()),
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: None,
+            uri: None,
+        });
+        let views: Vec<_> = views
+            .into_iter()
+            .map(|(offset, length, target)| {
+                root.push(json::buffer::View {
+                    buffer,
+                    byte_length: USize64::from(length),
+                    byte_offset: Some(USize64::from(offset)),
+                    byte_stride: None,
+                    extensions: Default::default(),
+                    extras: Default::default(),
+                    name: None,
+                    target: Some(Valid(target)),
+                })
+            })
+            .collect();
+
+        let (min, max) = positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), &p| (min.min(p), max.max(p)),
+        );
+        let mut accessor = |view, count: usize, component, type_, bounds: Option<(Vec3, Vec3)>| {
+            root.push(json::Accessor {
+                buffer_view: Some(view),
+                byte_offset: None,
+                count: USize64::from(count),
+                component_type: Valid(json::accessor::GenericComponentType(component)),
+                extensions: Default::default(),
+                extras: Default::default(),
+                type_: Valid(type_),
+                min: bounds.map(|(min, _)| json::Value::from(min.to_array().to_vec())),
+                max: bounds.map(|(_, max)| json::Value::from(max.to_array().to_vec())),
+                name: None,
+                normalized: false,
+                sparse: None,
+            })
+        };
+        use json::accessor::{ComponentType, Type};
+        let len = positions.len();
+        let positions = accessor(
+            views[0],
+            len,
+            ComponentType::F32,
+            Type::Vec3,
+            Some((min, max)),
+        );
+        let normals = accessor(views[1], len, ComponentType::F32, Type::Vec3, None);
+        let colors = accessor(views[2], len, ComponentType::F32, Type::Vec4, None);
+        let indices = accessor(
+            views[3],
+            indices.len(),
+            ComponentType::U32,
+            Type::Scalar,
+            None,
+        );
+
+        let primitive = json::mesh::Primitive {
+            attributes: BTreeMap::from([
+                (Valid(json::mesh::Semantic::Positions), positions),
+                (Valid(json::mesh::Semantic::Normals), normals),
+                (Valid(json::mesh::Semantic::Colors(0)), colors),
+            ]),
+            extensions: Default::default(),
+            extras: Default::default(),
+            indices: Some(indices),
+            material: None,
+            mode: Valid(json::mesh::Mode::Triangles),
+            targets: None,
+        };
+        let mesh = root.push(json::Mesh {
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: None,
+            primitives: vec![primitive],
+            weights: None,
+        });
+        let node = root.push(json::Node {
+            mesh: Some(mesh),
+            ..Default::default()
+        });
+        let scene = root.push(json::Scene {
+            extensions: Default::default(),
+            extras: Default::default(),
+            name: None,
+            nodes: vec![node],
+        });
+        root.scene = Some(scene);
+
+        let json = json::serialize::to_vec(&root).expect("glTF json should serialize");
+        Ok(gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                // Recalculated when written
+                length: 0,
+            },
+            json: Cow::Owned(json),
+            bin: Some(Cow::Owned(bin)),
+        }
+        .to_vec()
+        .expect("glTF binary should serialize"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gltf::Semantic;
+
+    use super::*;
+
+    fn sphere() -> GeometryData {
+        GeometryData::icosahedron()
+            .subdivide_n(2)
+            .unwrap()
+            .slerp()
+            .recell()
+            .dual()
+            .unwrap()
+    }
+
+    #[test]
+    fn gltf_parses_back_with_every_corner() {
+        let geometry = sphere();
+        // Every corner of every face gets its own vertex
+        let corners = geometry.faces.len() * 3;
+        let gltf = gltf::Gltf::from_slice(&geometry.to_gltf(2.0)).unwrap();
+        assert_eq!(gltf.meshes().count(), 1);
+
+        let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+        for semantic in [Semantic::Positions, Semantic::Normals, Semantic::Colors(0)] {
+            assert_eq!(primitive.get(&semantic).unwrap().count(), corners);
+        }
+        assert_eq!(primitive.indices().unwrap().count(), corners);
+        assert_eq!(
+            gltf.blob.as_ref().map(Vec::len),
+            Some(corners * (12 + 12 + 16 + 4))
+        );
+
+        // Scaled up with everything else
+        let bounds = primitive.bounding_box();
+        let max = geometry
+            .vertices
+            .iter()
+            .fold(Vec3::MIN, |max, &v| max.max(v));
+        assert!(Vec3::from(bounds.max).abs_diff_eq(max * 2.0, 1e-5));
+    }
+
+    #[test]
+    fn cell_colors_follow_the_duplicated_vertices() {
+        let geometry = sphere();
+        let cell_colors: Vec<Color> = (0..geometry.cells.len())
+            .map(|cell| Color::srgb(cell as f32 / geometry.cells.len() as f32, 0.25, 0.75))
+            .collect();
+        let gltf =
+            gltf::Gltf::from_slice(&geometry.to_gltf_with_colors(1.0, &cell_colors).unwrap())
+                .unwrap();
+
+        let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+        let accessor = primitive.get(&Semantic::Colors(0)).unwrap();
+        let start = accessor.view().unwrap().offset() + accessor.offset();
+        let blob = gltf.blob.as_ref().unwrap();
+        let color_of = |v: usize| -> [f32; 4] {
+            std::array::from_fn(|i| {
+                let at = start + (v * 4 + i) * 4;
+                f32::from_le_bytes(blob[at..at + 4].try_into().unwrap())
+            })
+        };
+
+        // Laid out as `duplicate` lays out the vertices
+        let duplicated = geometry.clone().duplicate();
+        for (faces, color) in duplicated.cells.iter().zip(&cell_colors) {
+            let color = color.to_linear().to_f32_array();
+            for &face in faces {
+                assert!(duplicated.faces[face].iter().all(|&v| color_of(v) == color));
+            }
+        }
+
+        assert!(geometry
+            .to_gltf_with_colors(1.0, &cell_colors[1..])
+            .is_err());
+    }
+}
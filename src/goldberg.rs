@@ -1,11 +1,14 @@
-use crate::colors::HexColors;
 use crate::helpers::ordered_3tuple;
 use crate::helpers::sort_poly_vertices;
 use crate::icosahedron::Icosahedron;
 use crate::surface::Cell;
 use crate::surface::Chunk;
-use crate::surface::ChunkSizeLimit;
 use crate::surface::Surface;
+#[cfg(feature = "goldberg_demo")]
+use crate::{
+    colors::HexColors,
+    surface::{chunk_to_mesh, neighbour_chunker, ChunkSizeLimit},
+};
 use bevy::prelude::*;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
@@ -67,13 +70,15 @@ impl From<Icosahedron> for GoldbergPoly {
             adjacency[k as usize].insert(j);
         }
 
+        let points = ico_vertices.iter().map(|&v| Vec3::from(v)).collect();
+
         // Iterate over each vertex
         // vertex c is at the center of the hex, at index ci
         for (ci, c) in ico_vertices.iter().enumerate() {
             // Sort all adjacent vertices clockwise
             // adjacent has indices into the icosahedron
-            let adjacent = adjacency[ci].iter().cloned().collect::<Vec<u32>>();
-            let adjacent = sort_poly_vertices(&ico_vertices, adjacent);
+            let adjacent = adjacency[ci].iter().map(|&a| a as usize).collect();
+            let adjacent = sort_poly_vertices(&points, adjacent);
 
             // For each triple of (c, adjacent[i], adjacent[i+1]) get the center of the poly
             // This is our split location
@@ -123,12 +128,7 @@ impl From<Icosahedron> for GoldbergPoly {
         // And as a final precaution against back-face culling,
         // flip any faces order that is not clockwise
         for face in &mut gold_faces {
-            let [a, b, c] = (0..3)
-                .map(|i| Vec3::from(gold_vertices[face[i] as usize]))
-                .collect::<Vec<Vec3>>()[..3]
-            else {
-                panic!("Impossible!!")
-            };
+            let [a, b, c] = face.map(|v| Vec3::from(gold_vertices[v as usize]));
 
             // dot the normal with a vector and see if its <0
             if (b - a).cross(c - a).dot(a) < 0. {
@@ -202,47 +202,42 @@ impl GoldbergPoly {
     }
 }
 
-impl Into<Surface> for GoldbergPoly {
-    fn into(self) -> Surface {
-        let cells = self
+impl From<GoldbergPoly> for Surface {
+    fn from(gold: GoldbergPoly) -> Self {
+        let cells = gold
             .hexes
             .iter()
-            .zip(self.adjacency)
+            .zip(gold.adjacency)
             .enumerate()
             .map(|(i, (hex, adj))| {
-                let mut counter = 0;
+                // Remaps the goldberg vertices into the cell, in the order
+                // the faces first reach them
                 let mut map = BTreeMap::new();
+                let mut vertices = Vec::new();
+                let faces = gold.hex_to_face[i]
+                    .iter()
+                    .map(|&f| {
+                        gold.faces[f as usize].map(|vi| {
+                            *map.entry(vi).or_insert_with(|| {
+                                vertices.push(Vec3::from_array(gold.vertices[vi as usize]));
+                                vertices.len() - 1
+                            })
+                        })
+                    })
+                    .collect();
                 Cell {
                     position: Vec3::from_slice(hex),
                     adjacent: adj.iter().map(|&adj| adj as usize).collect(),
-                    faces: self.hex_to_face[i]
-                        .iter()
-                        .map(|&f| {
-                            self.faces[f as usize]
-                                .iter()
-                                .map(|vi| {
-                                    *map.entry(vi).or_insert_with(|| {
-                                        counter += 1;
-                                        (counter - 1) as usize
-                                    })
-                                })
-                                .collect::<Vec<_>>()
-                                .try_into()
-                                .unwrap()
-                        })
-                        .collect(),
-                    vertices: map
-                        .keys()
-                        .map(|i| Vec3::from_array(self.vertices[**i as usize]))
-                        .collect(),
+                    faces,
+                    vertices,
                 }
             })
             .collect::<Vec<_>>();
 
         // Nice and easy single chunk
         let chunks = vec![Chunk {
-            cells: (0..self.hexes.len()).collect(),
-            cell_to_local: (0..self.hexes.len()).map(|i| (i, 0)).collect(),
+            cells: (0..gold.hexes.len()).collect(),
+            cell_to_local: (0..gold.hexes.len()).map(|i| (i, 0)).collect(),
             mesh: None,
         }];
         let cell_to_chunk = vec![0; cells.len()];
@@ -255,7 +250,21 @@ impl Into<Surface> for GoldbergPoly {
     }
 }
 
-pub(crate) fn setup_hex(mut commands: Commands) {
+/// Spawns a goldberg [`Surface`] beside the planet and chunks it up, as a
+/// comparison against the geometry pipeline
+#[cfg(feature = "goldberg_demo")]
+pub(crate) struct GoldbergDemoPlugin;
+
+#[cfg(feature = "goldberg_demo")]
+impl Plugin for GoldbergDemoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_hex)
+            .add_systems(Update, (neighbour_chunker, chunk_to_mesh).chain());
+    }
+}
+
+#[cfg(feature = "goldberg_demo")]
+fn setup_hex(mut commands: Commands) {
     let mut gold = GoldbergPoly::new(8);
     gold.separate_shared_vertices();
     let surface: Surface = gold.into();
@@ -263,10 +272,10 @@ pub(crate) fn setup_hex(mut commands: Commands) {
     commands.spawn((
         HexColors {
             colors: vec![Color::srgba(0.0, 0.0, 0.0, 1.0); surface.cells.len()],
-            changed: Vec::new(),
+            ..Default::default()
         },
         surface,
-        Transform::IDENTITY.with_scale(Vec3::new(16.0, 16.0, 16.0)),
+        Transform::from_xyz(-96.0, 0.0, 0.0).with_scale(Vec3::splat(16.0)),
         ChunkSizeLimit(6000),
         InheritedVisibility::VISIBLE,
     ));
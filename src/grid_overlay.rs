@@ -0,0 +1,91 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use bevy::{color::palettes::css::WHITE, prelude::*};
+
+use crate::chunk_storage::Body;
+
+/// Settings for the latitude/longitude grid drawn over each body
+#[derive(Resource)]
+pub(crate) struct GridOverlay {
+    pub(crate) enabled: bool,
+    /// Degrees between neighbouring grid lines
+    pub(crate) spacing: f32,
+    pub(crate) color: Color,
+    /// Line segments used per full circle
+    pub(crate) resolution: usize,
+}
+
+impl Default for GridOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spacing: 15.0,
+            color: WHITE.with_alpha(0.3).into(),
+            resolution: 96,
+        }
+    }
+}
+
+pub(crate) struct GridOverlayPlugin;
+
+impl Plugin for GridOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GridOverlay>()
+            .add_systems(Update, (toggle_grid_overlay, draw_grid_overlay));
+    }
+}
+
+fn lat_long_to_point(lat: f32, long: f32) -> Vec3 {
+    Vec3::new(lat.cos() * long.cos(), lat.sin(), lat.cos() * long.sin())
+}
+
+pub(crate) fn toggle_grid_overlay(
+    mut config: ResMut<GridOverlay>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::KeyG) {
+        config.enabled = !config.enabled;
+    }
+}
+
+pub(crate) fn draw_grid_overlay(
+    config: Res<GridOverlay>,
+    bodies: Query<&Transform, With<Body>>,
+    mut gizmos: Gizmos,
+) {
+    if !config.enabled || config.spacing <= 0.0 {
+        return;
+    }
+
+    let spacing = config.spacing.to_radians();
+    let resolution = config.resolution.max(3);
+
+    for transform in bodies.iter() {
+        // Lift the lines off the surface a touch so they dont z-fight
+        let to_world = |p: Vec3| transform.transform_point(p * 1.002);
+
+        // Parallels, skipping the poles where they would collapse into a point
+        let parallels = ((FRAC_PI_2 - 1e-3) / spacing).floor() as i32;
+        for k in -parallels..=parallels {
+            let lat = k as f32 * spacing;
+            gizmos.linestrip(
+                (0..=resolution)
+                    .map(|i| to_world(lat_long_to_point(lat, TAU * i as f32 / resolution as f32))),
+                config.color,
+            );
+        }
+
+        // Meridians, which all converge at the poles
+        let meridians = (TAU / spacing).round() as i32;
+        for k in 0..meridians {
+            let long = k as f32 * spacing;
+            gizmos.linestrip(
+                (0..=resolution / 2).map(|i| {
+                    let lat = -FRAC_PI_2 + TAU * i as f32 / resolution as f32;
+                    to_world(lat_long_to_point(lat, long))
+                }),
+                config.color,
+            );
+        }
+    }
+}
@@ -1,7 +1,9 @@
+#[cfg(any(test, feature = "goldberg_demo"))]
 use std::collections::BTreeSet;
 
-use bevy::math::Vec3;
+use bevy::math::{Quat, Vec3};
 
+#[cfg(any(test, feature = "goldberg_demo"))]
 pub(crate) fn ordered_3tuple<T: Ord + Copy>((u, v, w): (T, T, T)) -> (T, T, T) {
     let mut arr = [u, v, w];
     arr.sort();
@@ -22,6 +24,7 @@ pub(crate) fn ordered_2tuple<T: Ord + Copy>(u: T, v: T) -> (T, T) {
 ///
 /// Returns the values in indices, sorted such that the corresponding points in vertices
 /// are ordered in a clockwise fashion when viewed looking onto the sphere from the outside.
+#[cfg(any(test, feature = "goldberg_demo"))]
 pub(crate) fn sort_poly_vertices(vertices: &Vec<Vec3>, indices: Vec<usize>) -> Vec<usize> {
     let mut u = indices[0];
     let mut seen = BTreeSet::from([u]);
@@ -60,3 +63,94 @@ pub(crate) fn sort_poly_vertices(vertices: &Vec<Vec3>, indices: Vec<usize>) -> V
 
     sorted
 }
+
+/// Returns `indices` sorted by the angle of their vertices around `center`,
+/// counter-clockwise when looking down onto the sphere from the outside.
+pub(crate) fn sort_around(center: Vec3, vertices: &[Vec3], indices: Vec<usize>) -> Vec<usize> {
+    // A basis for the tangent plane at center
+    let axis = center.normalize();
+    let u = axis.any_orthonormal_vector();
+    let v = axis.cross(u);
+
+    let mut keyed: Vec<_> = indices
+        .into_iter()
+        .map(|i| {
+            let d = vertices[i] - center;
+            (d.dot(v).atan2(d.dot(u)), i)
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    keyed.into_iter().map(|(_, i)| i).collect()
+}
+
+/// True spherical interpolation between two points on the unit sphere,
+/// travelling along the great circle between them.
+pub(crate) fn slerp_points(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    let theta = a.dot(b).clamp(-1.0, 1.0).acos();
+
+    if theta < 1e-5 {
+        // Close enough that a lerp is indistinguishable
+        return a.lerp(b, t);
+    }
+
+    if std::f32::consts::PI - theta < 1e-5 {
+        // Antipodal, every great circle works so just pick one
+        let axis = a.any_orthonormal_vector();
+        return Quat::from_axis_angle(axis, theta * t) * a;
+    }
+
+    let sin = theta.sin();
+    a * (((1.0 - t) * theta).sin() / sin) + b * ((t * theta).sin() / sin)
+}
+
+/// Möller–Trumbore ray/triangle intersection. Returns the distance along `dir`
+/// to the hit, if the triangle is hit in front of the origin.
+pub(crate) fn ray_triangle(origin: Vec3, dir: Vec3, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = dir.cross(ac);
+    let det = ab.dot(p);
+    if det.abs() < f32::EPSILON {
+        // Parallel to the triangle
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(ab);
+    let v = dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = ac.dot(q) * inv_det;
+    (t > 0.0).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slerp_points_follows_the_sphere() {
+        let pairs = [
+            (Vec3::X, Vec3::Y),
+            (Vec3::Z, Vec3::new(1.0, -2.0, 0.5).normalize()),
+            (Vec3::X, Vec3::X),
+            (Vec3::Y, Vec3::NEG_Y),
+        ];
+        for (a, b) in pairs {
+            assert!(slerp_points(a, b, 0.0).distance(a) < 1e-5);
+            assert!(slerp_points(a, b, 1.0).distance(b) < 1e-4);
+            for i in 0..=20 {
+                let p = slerp_points(a, b, i as f32 / 20.0);
+                assert!((p.length() - 1.0).abs() < 1e-5, "{a} -> {b} at {i} is {p}");
+            }
+        }
+    }
+}
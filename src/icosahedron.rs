@@ -1,18 +1,18 @@
-use super::helpers;
-use super::Wireframeable;
-use bevy::asset::RenderAssetUsages;
-use bevy::pbr::wireframe::Wireframe;
+use crate::geometry_data::GeometryData;
 use bevy::prelude::*;
-use bevy::render::mesh::Indices;
-use bevy::render::mesh::PrimitiveTopology::TriangleList;
 use rand::random_range;
-use std::collections::BTreeMap;
 
 pub(crate) struct Icosahedron {
     pub(crate) vertices: Vec<[f32; 3]>,
     pub(crate) faces: Vec<[u32; 3]>,
 }
 
+impl Default for Icosahedron {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Icosahedron {
     pub(crate) fn new() -> Self {
         // Generates the vertices of an icosahedron (20 faced polyhedron)
@@ -70,44 +70,18 @@ impl Icosahedron {
     }
 
     pub(crate) fn subdivide(&mut self) {
-        // Subdivides self once
-        // For each face:
-        // 1) Split each edge with a new vertex in the middle.
-        //    - Use some kind of map so that edges previously split are kept
-        //    - Use (u32, u32) pairs of indices rather than float vectors for consistency
-        //    - If it has already been split, instead get the index
-        //    - If it is not already split, create a new index at the end of vertices and add it.
-        // 2) After splitting the three edges of a face, create 4 new faces for each subtriangle.
-        // 3) Add those faces to the new face vector.
-        let mut btree: BTreeMap<(u32, u32), u32> = BTreeMap::new();
-        let mut new_faces = Vec::<[u32; 3]>::new();
-        for &[i, j, k] in &self.faces {
-            // Splits i,j, j,k and k,i into 3 new vertices:
-            let mut splits = Vec::new();
-            for (u, v) in [(i, j), (j, k), (k, i)] {
-                let index = *btree
-                    .entry(helpers::ordered_2tuple(u, v))
-                    .or_insert_with(|| {
-                        self.vertices.push({
-                            let x = self.vertices[u as usize];
-                            let y = self.vertices[v as usize];
-                            [
-                                (x[0] + y[0]) / 2.0,
-                                (x[1] + y[1]) / 2.0,
-                                (x[2] + y[2]) / 2.0,
-                            ]
-                        });
-                        (self.vertices.len() - 1) as u32
-                    });
-                splits.push(index);
-            }
-            let [ij, jk, ki] = splits[0..3] else {
-                panic!("This should be impossible")
-            };
-            new_faces.extend([[i, ij, ki], [ij, j, jk], [ki, jk, k], [ij, jk, ki]]);
-        }
-
-        std::mem::swap(&mut self.faces, &mut new_faces);
+        // Subdivides self once, sharing GeometryData's implementation
+        let ico = std::mem::replace(
+            self,
+            Icosahedron {
+                vertices: Vec::new(),
+                faces: Vec::new(),
+            },
+        );
+        *self = GeometryData::from(ico)
+            .subdivide()
+            .expect("the icosahedron is well formed")
+            .into();
     }
 
     pub(crate) fn slerp(&mut self) {
@@ -129,36 +103,58 @@ impl Icosahedron {
     }
 }
 
-pub(crate) fn icosahedron_demo(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let mut ico = Icosahedron::new();
-    for _ in 0..3 {
-        ico.subdivide();
-        ico.slerp();
+impl From<Icosahedron> for GeometryData {
+    /// Just the triangles, the result has no cells until `recell`
+    fn from(ico: Icosahedron) -> Self {
+        let mut geometry = GeometryData::default();
+        geometry.vertices = ico.vertices.into_iter().map(Vec3::from).collect();
+        geometry.faces = ico
+            .faces
+            .into_iter()
+            .map(|face| face.map(|i| i as usize))
+            .collect();
+        geometry
+    }
+}
+
+impl From<GeometryData> for Icosahedron {
+    /// Keeps the triangles, dropping any cells
+    fn from(geometry: GeometryData) -> Self {
+        Icosahedron {
+            vertices: geometry.vertices.iter().map(Vec3::to_array).collect(),
+            faces: geometry
+                .faces
+                .into_iter()
+                .map(|face| face.map(|i| i as u32))
+                .collect(),
+        }
     }
-    let Icosahedron { vertices, faces } = ico;
+}
 
-    let mesh = Mesh::new(
-        TriangleList,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-    )
-    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
-    .with_inserted_indices(Indices::U32(faces.into_flattened()))
-    .with_computed_smooth_normals();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    commands.spawn((
-        Wireframeable,
-        Wireframe,
-        Mesh3d(meshes.add(mesh.clone())),
-        Transform::from_xyz(0., 0., 0.).with_scale(Vec3::new(4.0, 4.0, 4.0)),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::srgb_u8(0, 0, 255),
-            // double_sided: true,
-            // cull_mode: None,
-            ..default()
-        })),
-    ));
+    #[test]
+    fn both_subdivisions_agree() {
+        let mut icosahedron = Icosahedron::new();
+        for _ in 0..3 {
+            icosahedron.subdivide();
+        }
+        icosahedron.slerp();
+        let from_icosahedron = GeometryData::from(icosahedron);
+        let direct = GeometryData::from(Icosahedron::new())
+            .subdivide_n(3)
+            .unwrap()
+            .slerp();
+
+        assert_eq!(from_icosahedron.vertices.len(), 642);
+        assert_eq!(from_icosahedron.vertices.len(), direct.vertices.len());
+        assert_eq!(from_icosahedron.faces, direct.faces);
+        for (a, b) in from_icosahedron.vertices.iter().zip(&direct.vertices) {
+            assert!(a.abs_diff_eq(*b, 1e-6));
+        }
+        assert!(from_icosahedron.cells.is_empty());
+        assert_eq!(from_icosahedron.validate(), Ok(()));
+    }
 }
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+
+/// A static KD-tree over points, for nearest point queries.
+///
+/// Points on a sphere surface leave most octants of an [`crate::octree::Octree`]
+/// empty, where a KD-tree splits at the median along the widest axis so it
+/// stays balanced however the points are spread. It can't be updated once
+/// built, so it suits point queries against fixed geometry.
+#[derive(Clone, Debug, Default)]
+pub struct KdTree {
+    /// Points laid out as an implicit tree: the median of each range is the
+    /// node, with the lower half of the range left of it and the upper right
+    points: Vec<(Vec3, usize)>,
+    /// The axis each node splits on, parallel to `points`
+    axes: Vec<u8>,
+}
+
+impl KdTree {
+    /// Builds the tree over `points`, the value of each being its index
+    pub fn new(points: &[Vec3]) -> Self {
+        let mut tree = Self {
+            points: points.iter().copied().zip(0..).collect(),
+            axes: vec![0; points.len()],
+        };
+        tree.build(0, points.len());
+        tree
+    }
+
+    fn build(&mut self, start: usize, end: usize) {
+        if end - start <= 1 {
+            return;
+        }
+
+        let range = &mut self.points[start..end];
+        let (min, max) = range.iter().fold(
+            (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            |(min, max), (p, _)| (min.min(*p), max.max(*p)),
+        );
+        let extent = max - min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+
+        let mid = range.len() / 2;
+        range.select_nth_unstable_by(mid, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+        self.axes[start + mid] = axis as u8;
+
+        self.build(start, start + mid);
+        self.build(start + mid + 1, end);
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The value of the point closest to `pos`, or `None` if the tree is empty
+    pub fn nearest(&self, pos: Vec3) -> Option<usize> {
+        let mut best = None;
+        let mut best_distance = f32::INFINITY;
+        self.search(pos, 0, self.points.len(), &mut best, &mut best_distance);
+        best
+    }
+
+    fn search(
+        &self,
+        pos: Vec3,
+        start: usize,
+        end: usize,
+        best: &mut Option<usize>,
+        best_distance: &mut f32,
+    ) {
+        if start >= end {
+            return;
+        }
+
+        let mid = start + (end - start) / 2;
+        let (point, value) = self.points[mid];
+        let distance = point.distance_squared(pos);
+        if distance < *best_distance {
+            *best_distance = distance;
+            *best = Some(value);
+        }
+
+        // Search the side `pos` is on first, then the other only if the
+        // splitting plane is closer than the best point so far
+        let axis = self.axes[mid] as usize;
+        let offset = pos[axis] - point[axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, mid), (mid + 1, end))
+        } else {
+            ((mid + 1, end), (start, mid))
+        };
+        self.search(pos, near.0, near.1, best, best_distance);
+        if offset * offset < *best_distance {
+            self.search(pos, far.0, far.1, best, best_distance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fibonacci_sphere::fibonacci_sphere;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn nearest_matches_a_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut points = fibonacci_sphere(2000);
+        // Some off the sphere too, so not every split is as tidy
+        points.extend((0..500).map(|_| {
+            Vec3::new(
+                rng.random_range(-2.0..2.0),
+                rng.random_range(-2.0..2.0),
+                rng.random_range(-2.0..2.0),
+            )
+        }));
+        let tree = KdTree::new(&points);
+        assert_eq!(tree.len(), points.len());
+
+        for _ in 0..1000 {
+            let query = Vec3::new(
+                rng.random_range(-3.0..3.0),
+                rng.random_range(-3.0..3.0),
+                rng.random_range(-3.0..3.0),
+            );
+            let expected = points
+                .iter()
+                .map(|p| p.distance_squared(query))
+                .fold(f32::INFINITY, f32::min);
+            let found = tree.nearest(query).unwrap();
+            assert_eq!(points[found].distance_squared(query), expected);
+        }
+
+        assert_eq!(KdTree::new(&[]).nearest(Vec3::ZERO), None);
+    }
+}
@@ -0,0 +1,186 @@
+//! A planet made of hexagonal cells, rendered in chunks that refine as the
+//! camera gets closer. The geometry pipeline ([`geometry_data`], [`octree`],
+//! [`kdtree`], [`fibonacci_sphere`]) is usable on its own, the rest is the
+//! bevy app around it, started with [`run`].
+
+mod adjacency;
+mod axes;
+mod borders;
+mod camera;
+mod chunk_manager;
+mod chunk_storage;
+mod chunk_tree;
+mod chunking;
+mod colors;
+pub mod fibonacci_sphere;
+mod fibonacci_sphere_visualiser;
+mod flatnormal;
+mod geometry_cache;
+pub mod geometry_data;
+#[cfg(feature = "gltf_export")]
+mod gltf_export;
+#[cfg(any(test, feature = "goldberg_demo"))]
+mod goldberg;
+mod grid_overlay;
+mod helpers;
+#[cfg(any(test, feature = "goldberg_demo"))]
+mod icosahedron;
+pub mod kdtree;
+mod obj_export;
+mod ocean;
+pub mod octree;
+pub mod preset;
+mod render_stats;
+mod shading;
+#[cfg(any(test, feature = "goldberg_demo"))]
+mod spherical_grid;
+mod starfield;
+#[cfg(any(test, feature = "goldberg_demo"))]
+mod surface;
+mod tooltip;
+mod yields;
+
+/// The paths of octree nodes, as [`octree::Octree`] takes and returns them
+pub use chunk_storage::{
+    chunk_index_to_string, common_prefix, parse_chunk_index, ChunkIndex, ChunkIndexExt,
+};
+/// The colors [`geometry_data::GeometryData::from_preset`] paints a planet with
+pub use colors::HexColors;
+
+use adjacency::AdjacencyOverlayPlugin;
+use axes::AxesPlugin;
+use bevy::{
+    color::palettes::css::GREEN,
+    pbr::wireframe::{Wireframe, WireframeConfig, WireframePlugin},
+    prelude::*,
+    render::{
+        settings::{RenderCreation, WgpuFeatures, WgpuSettings},
+        RenderPlugin,
+    },
+    time::common_conditions::on_timer,
+};
+use bevy_fps_counter::FpsCounterPlugin;
+use bevy_inspector_egui::quick::WorldInspectorPlugin;
+use bevy_panorbit_camera::PanOrbitCameraPlugin;
+use borders::BordersPlugin;
+use camera::CameraPlugin;
+use chunk_storage::{despawn_chunks, spawn_ready_chunks, ChunkingPlugin};
+use colors::{
+    ignite_lava, mark_visibility_changes, randomize_colors, reveal_under_camera, toggle_fog_of_war,
+    update_mesh_colors, ColorUpdateCooldown, FogOfWar,
+};
+use flatnormal::FlatNormalMaterialPlugin;
+use grid_overlay::GridOverlayPlugin;
+use ocean::OceanPlugin;
+use render_stats::RenderStatsPlugin;
+use shading::{mark_chunks_for_shading, toggle_shading, update_mesh_normals, ShadingMode};
+use starfield::StarfieldPlugin;
+use std::time::Duration;
+use tooltip::CellTooltipPlugin;
+
+#[derive(Default, Reflect, GizmoConfigGroup)]
+struct Gizmos;
+
+fn setup(mut commands: Commands) {
+    commands.spawn(DirectionalLight {
+        ..Default::default()
+    });
+}
+
+fn spin_light(mut query: Query<(&mut Transform, &DirectionalLight)>) {
+    for (mut t, d) in query.iter_mut() {
+        t.rotate_x(std::f32::consts::PI / (60. * 80.));
+        t.rotate_y(std::f32::consts::PI / (60. * 20.));
+    }
+}
+
+/// Builds and runs the game, see `main.rs`
+pub fn run() {
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(RenderPlugin {
+        render_creation: RenderCreation::Automatic(WgpuSettings {
+            features: WgpuFeatures::POLYGON_MODE_LINE,
+            ..default()
+        }),
+        ..default()
+    }))
+    .add_plugins(FlatNormalMaterialPlugin)
+    .add_plugins((WireframePlugin))
+    .add_plugins(FpsCounterPlugin)
+    .add_plugins(RenderStatsPlugin)
+    // .add_plugins(WorldInspectorPlugin::new())
+    // .add_plugins(OctreeVisualiserPlugin)
+    .add_plugins(CameraPlugin)
+    .add_plugins(ChunkingPlugin)
+    .add_plugins(GridOverlayPlugin)
+    .add_plugins(AxesPlugin)
+    .add_plugins(AdjacencyOverlayPlugin)
+    .add_plugins(OceanPlugin)
+    .add_plugins(BordersPlugin)
+    .add_plugins(CellTooltipPlugin)
+    .add_plugins(StarfieldPlugin)
+    .insert_resource(WireframeConfig {
+        global: false,
+        default_color: GREEN.into(),
+    })
+    .add_systems(Startup, setup)
+    // .add_systems(Startup, setup_demo_sphere)
+    .register_type::<HexColors>()
+    .init_resource::<ColorUpdateCooldown>()
+    .init_resource::<ShadingMode>()
+    .init_resource::<FogOfWar>()
+    .add_systems(
+        Update,
+        (
+            toggle_wireframe,
+            toggle_shading,
+            ignite_lava,
+            toggle_fog_of_war,
+        ),
+    )
+    .add_systems(FixedUpdate, spin_light)
+    .add_systems(FixedUpdate, randomize_colors)
+    .add_systems(FixedUpdate, update_mesh_colors.after(despawn_chunks))
+    .add_systems(
+        FixedUpdate,
+        (
+            reveal_under_camera.run_if(on_timer(Duration::from_millis(500))),
+            mark_visibility_changes,
+        )
+            .chain()
+            .before(update_mesh_colors),
+    )
+    .add_systems(
+        FixedUpdate,
+        (mark_chunks_for_shading, update_mesh_normals)
+            .chain()
+            .after(despawn_chunks),
+    );
+    #[cfg(feature = "gltf_export")]
+    app.add_systems(Update, gltf_export::export_bodies);
+    #[cfg(feature = "goldberg_demo")]
+    app.add_plugins(goldberg::GoldbergDemoPlugin);
+    #[cfg(feature = "chunk_manager_demo")]
+    app.add_plugins(chunking::ChunkManagerDemoPlugin);
+    app.run();
+}
+
+#[derive(Component)]
+struct Wireframeable;
+
+fn toggle_wireframe(
+    mut commands: Commands,
+    with_wireframe: Query<Entity, (With<Wireframeable>, With<Wireframe>)>,
+    without_wireframe: Query<Entity, (With<Wireframeable>, Without<Wireframe>)>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::Space) {
+        for entity in with_wireframe.iter() {
+            commands.entity(entity).remove::<Wireframe>();
+        }
+
+        for entity in without_wireframe.iter() {
+            commands.entity(entity).insert(Wireframe);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Exporting a whole (unchunked) planet as Wavefront OBJ, for use in other
+//! tools such as Blender.
+
+use std::io::{self, Write};
+
+use crate::geometry_data::GeometryData;
+
+impl GeometryData {
+    /// The geometry as OBJ text, see [`Self::write_obj`]
+    pub fn to_obj(&self, group_cells: bool) -> String {
+        let mut out = Vec::new();
+        self.write_obj(&mut out, group_cells)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(out).expect("OBJ is written as ASCII")
+    }
+
+    /// Writes the geometry as OBJ, a `v` for every vertex and an `f` and `vn`
+    /// for every face. A face's normal is its cell's (or its own, for faces in
+    /// no cell), so cells sharing a vertex are still flat shaded. With
+    /// `group_cells` each cell becomes its own object, `cell_N`, and any faces
+    /// in no cell are left in a final `loose` object.
+    pub fn write_obj(&self, out: &mut impl Write, group_cells: bool) -> io::Result<()> {
+        writeln!(out, "# {} cells", self.cells.len())?;
+        for v in &self.vertices {
+            writeln!(out, "v {} {} {}", v.x, v.y, v.z)?;
+        }
+        let mut normals = self.face_normals();
+        for (faces, normal) in self.cells.iter().zip(&self.cell_normals) {
+            for &face in faces {
+                normals[face] = normal.normalize_or_zero();
+            }
+        }
+        for n in normals {
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+
+        // OBJ counts from 1, and each face uses the normal of the same index
+        let write_face = |out: &mut dyn Write, face: usize| {
+            let [a, b, c] = self.faces[face].map(|v| v + 1);
+            let n = face + 1;
+            writeln!(out, "f {a}//{n} {b}//{n} {c}//{n}")
+        };
+
+        if !group_cells {
+            for face in 0..self.faces.len() {
+                write_face(out, face)?;
+            }
+            return Ok(());
+        }
+
+        let mut loose = vec![true; self.faces.len()];
+        for (cell, faces) in self.cells.iter().enumerate() {
+            writeln!(out, "o cell_{cell}")?;
+            for &face in faces {
+                loose[face] = false;
+                write_face(out, face)?;
+            }
+        }
+        if loose.contains(&true) {
+            writeln!(out, "o loose")?;
+            for face in (0..self.faces.len()).filter(|&face| loose[face]) {
+                write_face(out, face)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn obj_gives_each_face_its_cells_normal() {
+        // Welded, so neighboring cells share the vertices along their border
+        let mut geometry = GeometryData::icosahedron()
+            .subdivide_n(2)
+            .unwrap()
+            .slerp()
+            .recell()
+            .dual()
+            .unwrap();
+        // A stray face in no cell
+        geometry.faces.push([0, 1, 2]);
+        let obj = geometry.to_obj(true);
+
+        let lines = |prefix: &str| -> Vec<Vec<&str>> {
+            obj.lines()
+                .filter_map(|line| line.strip_prefix(prefix))
+                .map(|rest| rest.split_whitespace().collect())
+                .collect()
+        };
+        assert_eq!(lines("v ").len(), geometry.vertices.len());
+        assert_eq!(lines("o cell_").len(), geometry.cells.len());
+        assert_eq!(lines("o loose").len(), 1);
+
+        let normals: Vec<Vec3> = lines("vn ")
+            .iter()
+            .map(|xyz| {
+                Vec3::from_slice(&xyz.iter().map(|c| c.parse().unwrap()).collect::<Vec<f32>>())
+            })
+            .collect();
+        assert_eq!(normals.len(), geometry.faces.len());
+
+        // Faces keep their vertices, and share a normal with their cell
+        let mut written = vec![false; geometry.faces.len()];
+        for corners in lines("f ") {
+            let corners: Vec<(usize, usize)> = corners
+                .iter()
+                .map(|corner| {
+                    let (v, n) = corner.split_once("//").unwrap();
+                    (
+                        v.parse::<usize>().unwrap() - 1,
+                        n.parse::<usize>().unwrap() - 1,
+                    )
+                })
+                .collect();
+            let face = corners[0].1;
+            assert!(corners.iter().all(|&(_, n)| n == face));
+            assert_eq!(
+                corners.iter().map(|&(v, _)| v).collect::<Vec<_>>(),
+                geometry.faces[face]
+            );
+            written[face] = true;
+        }
+        assert!(written.iter().all(|&written| written));
+        for (cell, faces) in geometry.cells.iter().enumerate() {
+            let normal = geometry.cell_normals[cell].normalize();
+            assert!(faces
+                .iter()
+                .all(|&face| normals[face].abs_diff_eq(normal, 1e-5)));
+        }
+        let stray = geometry.face_normals()[geometry.faces.len() - 1];
+        assert!(normals[geometry.faces.len() - 1].abs_diff_eq(stray, 1e-5));
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+    utils::HashMap,
+};
+
+use crate::{
+    camera::effective_fov,
+    chunk_storage::{Body, POV},
+    geometry_data::GeometryData,
+};
+
+/// A sea level shell drawn around the body it is on. Its tessellation follows
+/// how much of the body is in view, like the terrain LOD does. Setting
+/// `min_subdivisions` and `max_subdivisions` equal fixes it instead.
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub(crate) struct OceanShell {
+    /// Radius of the shell, relative to the body
+    pub(crate) level: f32,
+    /// Subdivisions used when the whole body is in view
+    pub(crate) min_subdivisions: usize,
+    /// Subdivisions never exceeded, however close the view gets
+    pub(crate) max_subdivisions: usize,
+    /// The view footprint (altitude in body radii times the field of view)
+    /// below which subdivisions start being added, one per halving
+    pub(crate) far_footprint: f32,
+}
+
+impl Default for OceanShell {
+    fn default() -> Self {
+        Self {
+            level: 0.995,
+            min_subdivisions: 3,
+            max_subdivisions: 6,
+            far_footprint: 1.0,
+        }
+    }
+}
+
+impl OceanShell {
+    /// The subdivisions to use when viewed from `altitude` body radii above the
+    /// surface through a camera with a vertical field of view of `fov` radians
+    pub(crate) fn subdivisions_at(&self, altitude: f32, fov: f32) -> usize {
+        let footprint = (altitude * fov).max(1e-6);
+        let extra = (self.far_footprint / footprint).log2().max(0.0).round() as usize;
+        (self.min_subdivisions + extra).clamp(self.min_subdivisions, self.max_subdivisions)
+    }
+}
+
+/// The ocean mesh of a body, spawned as its child
+#[derive(Component)]
+pub(crate) struct OceanMesh {
+    pub(crate) subdivisions: usize,
+}
+
+/// The shared ocean material, and a mesh per subdivision level built so far
+#[derive(Resource)]
+pub(crate) struct OceanAssets {
+    pub(crate) material: Handle<StandardMaterial>,
+    pub(crate) meshes: HashMap<usize, Handle<Mesh>>,
+}
+
+pub(crate) struct OceanPlugin;
+
+impl Plugin for OceanPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<OceanShell>()
+            .add_systems(Startup, setup_ocean_assets)
+            .add_systems(Update, (toggle_oceans, update_oceans).chain());
+    }
+}
+
+/// A smooth unit icosphere subdivided `subdivisions` times
+pub(crate) fn ocean_mesh(subdivisions: usize) -> Mesh {
+    let geometry = GeometryData::icosahedron()
+        .subdivide_n(subdivisions)
+        .expect("the icosahedron is well formed")
+        .slerp();
+    let normals: Vec<Vec3> = geometry.vertices.iter().map(|v| v.normalize()).collect();
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, geometry.vertices)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_indices(Indices::U32(
+        geometry.faces.iter().flatten().map(|&f| f as u32).collect(),
+    ))
+}
+
+fn setup_ocean_assets(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    let material = materials.add(StandardMaterial {
+        base_color: Color::srgba(0.05, 0.2, 0.55, 0.8),
+        perceptual_roughness: 0.1,
+        alpha_mode: AlphaMode::Blend,
+        ..default()
+    });
+    commands.insert_resource(OceanAssets {
+        material,
+        meshes: HashMap::default(),
+    });
+}
+
+/// Adds or removes `OceanShell` on every body
+pub(crate) fn toggle_oceans(
+    mut commands: Commands,
+    bodies: Query<(Entity, Has<OceanShell>), With<Body>>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if !input.just_pressed(KeyCode::KeyO) {
+        return;
+    }
+
+    for (entity, shown) in bodies.iter() {
+        if shown {
+            commands.entity(entity).remove::<OceanShell>();
+        } else {
+            commands.entity(entity).insert(OceanShell::default());
+        }
+    }
+}
+
+/// Spawns, retessellates and despawns ocean meshes to match the `OceanShell`s
+pub(crate) fn update_oceans(
+    mut commands: Commands,
+    mut assets: ResMut<OceanAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    cameras: Query<(&GlobalTransform, &Projection), With<POV>>,
+    bodies: Query<(Entity, &OceanShell, &GlobalTransform)>,
+    mut oceans: Query<(Entity, &Parent, &mut OceanMesh, &mut Mesh3d, &mut Transform)>,
+) {
+    let Ok((camera, projection)) = cameras.get_single() else {
+        return;
+    };
+
+    let OceanAssets {
+        material,
+        meshes: ocean_meshes,
+    } = &mut *assets;
+    let mut mesh_for = |subdivisions: usize| {
+        ocean_meshes
+            .entry(subdivisions)
+            .or_insert_with(|| meshes.add(ocean_mesh(subdivisions)))
+            .clone()
+    };
+
+    let subdivisions_for = |shell: &OceanShell, transform: &GlobalTransform| {
+        let radius = transform.scale().x * shell.level;
+        let distance = camera.translation().distance(transform.translation());
+        let fov = effective_fov(projection, distance);
+        shell.subdivisions_at((distance / radius - 1.0).max(0.0), fov)
+    };
+
+    let mut has_ocean = Vec::new();
+    for (entity, parent, mut ocean, mut mesh, mut transform) in oceans.iter_mut() {
+        let Ok((_, shell, body_transform)) = bodies.get(parent.get()) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        has_ocean.push(parent.get());
+
+        transform.scale = Vec3::splat(shell.level);
+        let subdivisions = subdivisions_for(shell, body_transform);
+        if ocean.subdivisions != subdivisions {
+            ocean.subdivisions = subdivisions;
+            mesh.0 = mesh_for(subdivisions);
+        }
+    }
+
+    for (body, shell, body_transform) in bodies.iter() {
+        if has_ocean.contains(&body) {
+            continue;
+        }
+
+        let subdivisions = subdivisions_for(shell, body_transform);
+        commands.entity(body).with_child((
+            OceanMesh { subdivisions },
+            Mesh3d(mesh_for(subdivisions)),
+            MeshMaterial3d(material.clone()),
+            Transform::from_scale(Vec3::splat(shell.level)),
+            Name::new("Ocean"),
+        ));
+    }
+}
@@ -1,23 +1,64 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeSet, BinaryHeap},
+    sync::Arc,
+};
 
 use bevy::{math::NormedVectorSpace, pbr::wireframe::Wireframe, prelude::*};
 use bevy_panorbit_camera::PanOrbitCamera;
 
+use crate::chunk_storage::ChunkIndexExt;
 use crate::chunking::ChunkManager;
 
 // The plan:
 // Break space up into cubic chunks, each containing cells.
 
 #[derive(Debug, Clone)]
-pub(crate) struct Point {
-    pub(crate) position: Vec3,
-    pub(crate) value: usize,
+pub struct Point {
+    pub position: Vec3,
+    pub value: usize,
+}
+
+/// How deep nodes can go. Points closer together than a node this deep is
+/// wide (e.g. several at the same position) stay together in one leaf, past
+/// its capacity, rather than splitting forever.
+const MAX_DEPTH: usize = 20;
+
+/// How to build the octree over a bodies cells. Capacity is the most cells a
+/// chunk can be split into, so lower means more, smaller chunks (more draw
+/// calls but finer LOD).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OctreeConfig {
+    pub capacity: usize,
+    /// Half the width of the root cube, which has to hold every cell
+    pub bounds: f32,
+    pub center: Vec3,
+}
+
+impl Default for OctreeConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 16,
+            bounds: 1.0,
+            center: Vec3::ZERO,
+        }
+    }
+}
+
+impl OctreeConfig {
+    /// An octree holding each of `positions`, the value of each being its index
+    pub fn build(self, positions: &[Vec3]) -> Octree {
+        let mut octree = Octree::new(self.capacity, self.center, self.bounds, 0, vec![]);
+        for (value, &position) in positions.iter().enumerate() {
+            octree.insert(Point { position, value });
+        }
+        octree
+    }
 }
 
 /// an octree that performs redistribution of ALL points into children
 /// when the capacity is met
 #[derive(Component, Debug, Clone)]
-pub(crate) struct Octree {
+pub struct Octree {
     pub(crate) children: Box<[Option<Octree>; 8]>,
     pub(crate) center: Vec3,
     pub(crate) points: Option<Vec<Point>>,
@@ -30,7 +71,7 @@ pub(crate) struct Octree {
 }
 
 impl Octree {
-    pub(crate) fn new(
+    pub fn new(
         capacity: usize,
         center: Vec3,
         bounds: f32,
@@ -65,13 +106,15 @@ impl Octree {
         index as usize
     }
 
-    pub(crate) fn insert(&mut self, point: Point) {
+    pub fn insert(&mut self, point: Point) {
         self.cell_count += 1;
 
         // Add points to self if points is some and within capacity
-        if self.points.is_some() && self.points.as_ref().unwrap().len() <= self.capacity {
-            self.points.as_mut().unwrap().push(point);
-            return;
+        if let Some(points) = &mut self.points {
+            if points.len() < self.capacity || self.depth >= MAX_DEPTH {
+                points.push(point);
+                return;
+            }
         }
 
         // Otherwise (points is none or we exceed cap)
@@ -98,6 +141,8 @@ impl Octree {
         if self.points.is_some() {
             if let Some(points) = std::mem::take(&mut self.points) {
                 for point in points {
+                    // Already counted when it was first inserted
+                    self.cell_count -= 1;
                     self.insert(point);
                 }
             }
@@ -110,7 +155,90 @@ impl Octree {
         }
     }
 
-    pub(crate) fn cells(&self) -> Vec<usize> {
+    /// Moves the point `value` from `old_pos` to `new_pos`, e.g. when a cell is
+    /// displaced. Nodes left with no more than `capacity` points below them are
+    /// collapsed back into a leaf, and `new_pos` is inserted as usual. Returns
+    /// false (and changes nothing) if there is no such point at `old_pos`.
+    pub fn update(&mut self, value: usize, old_pos: Vec3, new_pos: Vec3) -> bool {
+        let Some(mut point) = self.remove_at(value, old_pos) else {
+            return false;
+        };
+        point.position = new_pos;
+        self.insert(point);
+        true
+    }
+
+    /// Removes the point `value` wherever it is, e.g. when its cell is
+    /// destroyed. Nodes left with no more than `capacity` points below them
+    /// are collapsed back into a leaf, and heights are updated along the way.
+    /// Returns false if there is no such point.
+    pub fn remove(&mut self, value: usize) -> bool {
+        let Some(pos) = self.position_of(value) else {
+            return false;
+        };
+        self.remove_at(value, pos).is_some()
+    }
+
+    /// Where the point `value` is, searching every leaf
+    fn position_of(&self, value: usize) -> Option<Vec3> {
+        match &self.points {
+            Some(points) => points.iter().find(|p| p.value == value).map(|p| p.position),
+            None => self
+                .children
+                .iter()
+                .flatten()
+                .find_map(|child| child.position_of(value)),
+        }
+    }
+
+    /// Takes the point `value` out of the leaf `pos` falls in, collapsing any
+    /// node on the way that no longer needs its children
+    fn remove_at(&mut self, value: usize, pos: Vec3) -> Option<Point> {
+        let removed = if let Some(points) = &mut self.points {
+            let i = points.iter().position(|p| p.value == value)?;
+            points.swap_remove(i)
+        } else {
+            let index = self.pos_to_child(pos);
+            let child = self.children[index].as_mut()?;
+            let removed = child.remove_at(value, pos)?;
+            if child.cell_count == 0 {
+                self.children[index] = None;
+            }
+            removed
+        };
+        self.cell_count -= 1;
+
+        if self.points.is_none() && self.cell_count <= self.capacity {
+            let mut points = Vec::new();
+            self.collect_points(&mut points);
+            if points.len() <= self.capacity {
+                self.cell_count = points.len();
+                self.points = Some(points);
+                *self.children = [const { None }; 8];
+            }
+        }
+        self.height = self
+            .children
+            .iter()
+            .flatten()
+            .map(|child| child.height + 1)
+            .max()
+            .unwrap_or(0);
+
+        Some(removed)
+    }
+
+    fn collect_points(&self, results: &mut Vec<Point>) {
+        if let Some(points) = &self.points {
+            results.extend(points.iter().cloned());
+        } else {
+            for child in self.children.iter().flatten() {
+                child.collect_points(results);
+            }
+        }
+    }
+
+    pub fn cells(&self) -> Vec<usize> {
         let mut results = Vec::new();
         if let Some(points) = &self.points {
             results.extend(points.iter().map(|p| p.value).collect::<Vec<_>>());
@@ -125,6 +253,195 @@ impl Octree {
         results
     }
 
+    /// Every leaf below this node with how many points it holds, depth first
+    /// in child order, e.g. for a histogram of chunk sizes
+    pub fn iter_leaves(&self) -> impl Iterator<Item = (&[u8], usize)> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                match &node.points {
+                    Some(points) => return Some((node.octree_index.as_slice(), points.len())),
+                    None => stack.extend(node.children.iter().flatten().rev()),
+                }
+            }
+            None
+        })
+    }
+
+    /// Returns the value of every point within `radius` of `pos`
+    pub fn within_distance(&self, pos: Vec3, radius: f32) -> Vec<usize> {
+        let mut results = Vec::new();
+        self.collect_within_distance(pos, radius, &mut results);
+        results.into_iter().map(|p| p.value).collect()
+    }
+
+    /// Returns the value of every point within `angular_radius` (in radians)
+    /// of `center` seen from the origin, i.e. the cells in a cap around it,
+    /// for points on the unit sphere as `GeometryData::create_octree` has them
+    pub fn within_radius(&self, center: Vec3, angular_radius: f32) -> Vec<usize> {
+        let Some(center) = center.try_normalize() else {
+            return Vec::new();
+        };
+        // On the unit sphere the cap is everything within the chord across it,
+        // which lets the cube pruning of `within_distance` do the work. A
+        // little extra lets in points that sit just off the sphere.
+        let chord = 2.0 * (angular_radius.clamp(0.0, std::f32::consts::PI) / 2.0).sin();
+        let mut results = Vec::new();
+        self.collect_within_distance(center, chord * 1.01 + 1e-4, &mut results);
+        let min_cos = angular_radius.cos();
+        results
+            .into_iter()
+            .filter(|p| p.position.normalize_or_zero().dot(center) >= min_cos)
+            .map(|p| p.value)
+            .collect()
+    }
+
+    fn collect_within_distance<'a>(&'a self, pos: Vec3, radius: f32, results: &mut Vec<&'a Point>) {
+        // Skip the whole node if the closest point of its cube is out of range
+        if self.distance_squared_to(pos) > radius * radius {
+            return;
+        }
+
+        if let Some(points) = &self.points {
+            results.extend(
+                points
+                    .iter()
+                    .filter(|p| p.position.distance_squared(pos) <= radius * radius),
+            );
+        } else {
+            for child in self.children.iter().flatten() {
+                child.collect_within_distance(pos, radius, results);
+            }
+        }
+    }
+
+    /// The squared distance from `pos` to the closest point of this nodes
+    /// cube, zero if `pos` is inside it
+    fn distance_squared_to(&self, pos: Vec3) -> f32 {
+        let closest = pos.clamp(
+            self.center - Vec3::splat(self.bounds),
+            self.center + Vec3::splat(self.bounds),
+        );
+        closest.distance_squared(pos)
+    }
+
+    /// Returns the value of the point closest to `pos`, or `None` if there
+    /// are no points
+    pub fn nearest(&self, pos: Vec3) -> Option<usize> {
+        let mut best = None;
+        let mut best_distance = f32::INFINITY;
+        self.search_nearest(pos, &mut best, &mut best_distance);
+        best
+    }
+
+    fn search_nearest(&self, pos: Vec3, best: &mut Option<usize>, best_distance: &mut f32) {
+        if let Some(points) = &self.points {
+            for point in points {
+                let distance = point.position.distance_squared(pos);
+                if distance < *best_distance {
+                    *best_distance = distance;
+                    *best = Some(point.value);
+                }
+            }
+            return;
+        }
+
+        // Closest children first, so the best point so far is soon close
+        // enough to skip the rest
+        let mut children: Vec<_> = self
+            .children
+            .iter()
+            .flatten()
+            .map(|child| (child.distance_squared_to(pos), child))
+            .collect();
+        children.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (distance, child) in children {
+            if distance >= *best_distance {
+                break;
+            }
+            child.search_nearest(pos, best, best_distance);
+        }
+    }
+
+    /// Returns the values of the `k` points closest to `pos`, closest first.
+    /// Fewer if there aren't `k` points.
+    pub fn k_nearest(&self, pos: Vec3, k: usize) -> Vec<usize> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut heap = BinaryHeap::with_capacity(k.min(self.cell_count) + 1);
+        self.search_k_nearest(pos, k, &mut heap);
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// Keeps the `k` closest points seen in `heap`, furthest on top. Distances
+    /// are never negative, so their bits order the same as they do.
+    fn search_k_nearest(&self, pos: Vec3, k: usize, heap: &mut BinaryHeap<(u32, usize)>) {
+        let furthest = |heap: &BinaryHeap<(u32, usize)>| match heap.peek() {
+            Some(&(distance, _)) if heap.len() == k => f32::from_bits(distance),
+            _ => f32::INFINITY,
+        };
+
+        if let Some(points) = &self.points {
+            for point in points {
+                let distance = point.position.distance_squared(pos);
+                if distance < furthest(heap) {
+                    heap.push((distance.to_bits(), point.value));
+                    if heap.len() > k {
+                        heap.pop();
+                    }
+                }
+            }
+            return;
+        }
+
+        let mut children: Vec<_> = self
+            .children
+            .iter()
+            .flatten()
+            .map(|child| (child.distance_squared_to(pos), child))
+            .collect();
+        children.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (distance, child) in children {
+            if distance >= furthest(heap) {
+                break;
+            }
+            child.search_k_nearest(pos, k, heap);
+        }
+    }
+
+    /// Returns the value of the point nearest where the ray from `origin`
+    /// along `dir` first hits the unit sphere, e.g. the cell under the cursor
+    /// for the cell centers `GeometryData::create_octree` indexes. A ray
+    /// starting inside the sphere uses where it leaves. `None` if it misses.
+    ///
+    /// The nearest center isn't always in the cell containing the hit, right
+    /// by a border it can be the neighbors. Check with
+    /// `GeometryData::cell_contains` where that matters, as the tooltip does.
+    pub fn raycast(&self, origin: Vec3, dir: Vec3) -> Option<usize> {
+        let dir = dir.try_normalize()?;
+        // Solving |origin + t * dir| = 1 for the smallest t that isn't behind
+        let b = origin.dot(dir);
+        let c = origin.length_squared() - 1.0;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let t = if -b - root >= 0.0 {
+            -b - root
+        } else {
+            -b + root
+        };
+        if t < 0.0 {
+            return None;
+        }
+        self.nearest(origin + dir * t)
+    }
+
     pub(crate) fn get_chunks(&self, target: Vec3) -> Vec<Vec<usize>> {
         let multiplier = (1.0 / self.height as f32) * self.bounds; // 1/max_depth steps
 
@@ -156,16 +473,46 @@ impl Octree {
         results
     }
 
-    pub(crate) fn get_chunk_indices(
+    pub fn get_chunk_indices(&self, cell_count: usize, target: Vec3, zoom: f32) -> Vec<Vec<u8>> {
+        let pct = self.cell_count as f32 / cell_count as f32;
+        let heuristic = self.lod_heuristic(cell_count, target, zoom);
+
+        let mut results = Vec::new();
+        if heuristic >= pct as f32 {
+            results.push(self.octree_index.clone());
+        } else {
+            for child in self.children.iter().flatten() {
+                results.extend(child.get_chunk_indices(cell_count, target, zoom));
+            }
+        }
+
+        if results.len() == 0 {
+            results.push(self.octree_index.clone());
+        }
+
+        results
+    }
+
+    /// How far the node at `index_path` is past being split into its children
+    /// by `get_chunk_indices`. Below 1 it is drawn whole, above 1 it is split,
+    /// and the higher it is the closer the camera is to the detail inside it.
+    pub(crate) fn split_pressure(
         &self,
+        index_path: &[u8],
         cell_count: usize,
         target: Vec3,
         zoom: f32,
-    ) -> Vec<Vec<u8>> {
+    ) -> Option<f32> {
+        let node = self.node_for_index(index_path)?;
+        let pct = node.cell_count as f32 / cell_count as f32;
+        Some(pct / node.lod_heuristic(cell_count, target, zoom))
+    }
+
+    /// The largest share of all cells this node may hold and still be drawn
+    /// whole, for the camera looking along `target` at `zoom`
+    fn lod_heuristic(&self, cell_count: usize, target: Vec3, zoom: f32) -> f32 {
         let projected = self.center + (target - self.center).clamp_length_max(self.bounds);
         let dist = (projected.distance(target)).max(0.0) / 2.0;
-        let local_cells = self.cell_count;
-        let pct = local_cells as f32 / cell_count as f32;
 
         let mut l = 0.5;
         let mut k = 14.0;
@@ -198,34 +545,71 @@ impl Octree {
             // k += 2.0;
         }
 
-        let heuristic = m + (l - m) / (1.0 + (k * (x0 - dist)).exp());
+        m + (l - m) / (1.0 + (k * (x0 - dist)).exp())
+    }
 
+    /// The index of every node `depth` levels below the root, plus any leaves
+    /// that stop short of it, so together they still cover every cell
+    pub fn indices_at_depth(&self, depth: usize) -> Vec<Vec<u8>> {
         let mut results = Vec::new();
-        if heuristic >= pct as f32 {
+        self.collect_indices_at_depth(depth, &mut results);
+        results
+    }
+
+    fn collect_indices_at_depth(&self, depth: usize, results: &mut Vec<Vec<u8>>) {
+        if self.depth >= depth || self.points.is_some() {
             results.push(self.octree_index.clone());
         } else {
             for child in self.children.iter().flatten() {
-                results.extend(child.get_chunk_indices(cell_count, target, zoom));
+                child.collect_indices_at_depth(depth, results);
             }
         }
+    }
 
-        if results.len() == 0 {
-            results.push(self.octree_index.clone());
-        }
+    pub fn get_cells_for_index(&self, index_path: &[u8]) -> Option<Vec<usize>> {
+        self.node_for_index(index_path).map(|node| node.cells())
+    }
 
-        results
+    /// The world space center of the node at `index_path`, if it exists
+    pub fn center_for_index(&self, index_path: &[u8]) -> Option<Vec3> {
+        self.node_for_index(index_path).map(|node| node.center)
+    }
+
+    /// The half-width of the node at `index_path`, if it exists
+    pub fn bounds_for_index(&self, index_path: &[u8]) -> Option<f32> {
+        self.node_for_index(index_path).map(|node| node.bounds)
+    }
+
+    /// Whether `indices` are a sound LOD selection, as `get_chunk_indices` should
+    /// return: every path leads to a node, and no chunk contains another, so no
+    /// cell is drawn twice.
+    pub(crate) fn is_valid_selection<'a>(
+        &self,
+        indices: impl IntoIterator<Item = &'a Vec<u8>>,
+    ) -> bool {
+        let sorted: BTreeSet<&Vec<u8>> = indices.into_iter().collect();
+        // Everything between a path and one of its extensions shares the path
+        // as a prefix when sorted, so only neighbors need comparing
+        let nested = sorted
+            .iter()
+            .zip(sorted.iter().skip(1))
+            .any(|(a, b)| a.is_ancestor_of(b));
+
+        !nested
+            && sorted
+                .iter()
+                .all(|index| self.node_for_index(index).is_some())
     }
 
-    pub(crate) fn get_cells_for_index(&self, index_path: &[u8]) -> Option<Vec<usize>> {
+    fn node_for_index(&self, index_path: &[u8]) -> Option<&Octree> {
         if self.octree_index == index_path {
-            return Some(self.cells());
+            return Some(self);
         }
 
-        if index_path.starts_with(&self.octree_index) && index_path.len() > self.octree_index.len()
-        {
+        if self.octree_index.is_ancestor_of(index_path) {
             let next_child = index_path[self.octree_index.len()] as usize;
             if let Some(ref child) = self.children[next_child] {
-                return child.get_cells_for_index(index_path);
+                return child.node_for_index(index_path);
             } else {
                 return None;
             }
@@ -307,3 +691,272 @@ impl Plugin for OctreeVisualiserPlugin {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fibonacci_sphere::fibonacci_sphere;
+
+    fn sphere_octree(n: u32) -> (Octree, usize) {
+        let points = fibonacci_sphere(n);
+        (OctreeConfig::default().build(&points), points.len())
+    }
+
+    /// Every cell in exactly one selected chunk
+    fn assert_covers_once(octree: &Octree, indices: &[Vec<u8>], cell_count: usize) {
+        assert!(octree.is_valid_selection(indices));
+        let mut seen = vec![0; cell_count];
+        for index in indices {
+            for cell in octree.get_cells_for_index(index).unwrap() {
+                seen[cell] += 1;
+            }
+        }
+        assert!(seen.iter().all(|&count| count == 1), "{seen:?}");
+    }
+
+    #[test]
+    fn chunk_indices_cover_the_sphere_once() {
+        let (octree, cell_count) = sphere_octree(2000);
+        for target in [Vec3::X, Vec3::NEG_Y, Vec3::new(1.0, 2.0, 3.0).normalize()] {
+            for zoom in [0.3, 0.45, 0.7, 0.85, 1.0, 1.3] {
+                let indices = octree.get_chunk_indices(cell_count, target, zoom);
+                assert_covers_once(&octree, &indices, cell_count);
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_indices_refine_as_the_camera_zooms_in() {
+        let (octree, cell_count) = sphere_octree(2000);
+        let target = Vec3::new(1.0, 2.0, 3.0).normalize();
+        let under_camera = octree.nearest(target).unwrap();
+
+        let mut last: Option<(usize, usize)> = None;
+        for zoom in [1.3, 1.1, 0.9, 0.7, 0.45, 0.35] {
+            let indices = octree.get_chunk_indices(cell_count, target, zoom);
+            let chunk_size = indices
+                .iter()
+                .map(|index| octree.get_cells_for_index(index).unwrap())
+                .find(|cells| cells.contains(&under_camera))
+                .unwrap()
+                .len();
+            if let Some((last_count, last_size)) = last {
+                assert!(indices.len() >= last_count);
+                assert!(chunk_size <= last_size);
+            }
+            last = Some((indices.len(), chunk_size));
+        }
+    }
+
+    #[test]
+    fn node_splits_when_capacity_is_exceeded() {
+        let capacity = 4;
+        let mut octree = Octree::new(capacity, Vec3::ZERO, 1.0, 0, vec![]);
+        // All in the same octant, so they land in the same child
+        let point = |value| Point {
+            position: Vec3::splat(0.1 + value as f32 * 0.1),
+            value,
+        };
+
+        for value in 0..capacity {
+            octree.insert(point(value));
+        }
+        assert_eq!(octree.points.as_ref().map(Vec::len), Some(capacity));
+        assert!(octree.children.iter().all(Option::is_none));
+
+        octree.insert(point(capacity));
+        assert!(octree.points.is_none());
+        assert_eq!(octree.cell_count, capacity + 1);
+        assert_eq!(octree.cells().len(), capacity + 1);
+        let child = octree.children[7].as_ref().unwrap();
+        assert_eq!(child.cell_count, capacity + 1);
+    }
+
+    #[test]
+    fn identical_positions_stop_at_max_depth() {
+        let mut octree = Octree::new(4, Vec3::ZERO, 1.0, 0, vec![]);
+        for value in 0..100 {
+            octree.insert(Point {
+                position: Vec3::splat(0.3),
+                value,
+            });
+        }
+        assert_eq!(octree.height, MAX_DEPTH);
+        assert_eq!(octree.cell_count, 100);
+
+        let leaves: Vec<_> = octree.iter_leaves().collect();
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(leaves[0].0.len(), MAX_DEPTH);
+        assert_eq!(leaves[0].1, 100);
+    }
+
+    /// Every node counts the points below it and is one taller than its
+    /// tallest child
+    fn assert_consistent(node: &Octree) {
+        match &node.points {
+            Some(points) => {
+                assert!(node.children.iter().all(Option::is_none));
+                assert_eq!(node.cell_count, points.len());
+                assert_eq!(node.height, 0);
+            }
+            None => {
+                let children: Vec<_> = node.children.iter().flatten().collect();
+                children.iter().for_each(|child| assert_consistent(child));
+                let count = children.iter().map(|child| child.cell_count).sum::<usize>();
+                let height = children.iter().map(|child| child.height + 1).max();
+                assert_eq!(node.cell_count, count);
+                assert_eq!(Some(node.height), height);
+            }
+        }
+    }
+
+    #[test]
+    fn removing_points_collapses_nodes() {
+        let (mut octree, cell_count) = sphere_octree(200);
+        assert!(octree.height > 1);
+
+        // A value that was never inserted changes nothing
+        assert!(!octree.remove(cell_count));
+        assert_eq!(octree.cell_count, cell_count);
+
+        let mut remaining: Vec<usize> = (0..cell_count).collect();
+        while remaining.len() > octree.capacity {
+            // Spread the removals over the sphere
+            let value = remaining.remove(remaining.len() * 7 / 11);
+            assert!(octree.remove(value));
+            assert!(!octree.remove(value));
+            assert_eq!(octree.cell_count, remaining.len());
+            assert_consistent(&octree);
+
+            let indices = octree.get_chunk_indices(octree.cell_count, Vec3::X, 0.5);
+            assert!(octree.is_valid_selection(&indices));
+            let mut cells: Vec<usize> = indices
+                .iter()
+                .flat_map(|index| octree.get_cells_for_index(index).unwrap())
+                .collect();
+            cells.sort();
+            assert_eq!(cells, remaining);
+        }
+
+        assert!(octree.children.iter().all(Option::is_none));
+        assert_eq!(octree.points.as_ref().map(Vec::len), Some(octree.capacity));
+        assert_eq!(octree.height, 0);
+    }
+
+    #[test]
+    fn updating_a_point_moves_it_to_another_leaf() {
+        let points = fibonacci_sphere(200);
+        let mut octree = OctreeConfig::default().build(&points);
+        let leaf_of = |octree: &Octree, value: usize| -> Vec<u8> {
+            octree
+                .iter_leaves()
+                .map(|(path, _)| path.to_vec())
+                .find(|path| octree.get_cells_for_index(path).unwrap().contains(&value))
+                .unwrap()
+        };
+
+        let before = leaf_of(&octree, 0);
+        assert_eq!(before[0] as usize, octree.pos_to_child(points[0]));
+
+        // Across to the opposite octant
+        assert!(octree.update(0, points[0], -points[0]));
+        let after = leaf_of(&octree, 0);
+        assert_eq!(after[0] as usize, octree.pos_to_child(-points[0]));
+        assert_ne!(before[0], after[0]);
+        assert!(octree
+            .get_cells_for_index(&before)
+            .is_none_or(|cells| !cells.contains(&0)));
+        assert_eq!(octree.cell_count, points.len());
+        assert_consistent(&octree);
+
+        // The old position no longer finds it
+        assert!(!octree.update(0, points[0], points[0]));
+    }
+
+    #[test]
+    fn node_centers_and_bounds_follow_each_points_path() {
+        let points = fibonacci_sphere(500);
+        let octree = OctreeConfig::default().build(&points);
+
+        for (value, &position) in points.iter().enumerate() {
+            // Walk down to the points leaf, working out where each node should be
+            let (mut path, mut center, mut bounds) = (vec![], Vec3::ZERO, 1.0);
+            loop {
+                assert_eq!(octree.center_for_index(&path), Some(center));
+                assert_eq!(octree.bounds_for_index(&path), Some(bounds));
+                assert!((position - center).abs().max_element() <= bounds);
+
+                let node = octree.node_for_index(&path).unwrap();
+                if let Some(leaf) = &node.points {
+                    assert!(leaf.iter().any(|point| point.value == value));
+                    break;
+                }
+                let child = node.pos_to_child(position);
+                let octant = Vec3::new(
+                    if child & 1 == 0 { -1.0 } else { 1.0 },
+                    if child & 2 == 0 { -1.0 } else { 1.0 },
+                    if child & 4 == 0 { -1.0 } else { 1.0 },
+                );
+                path.push(child as u8);
+                bounds /= 2.0;
+                center += octant * bounds;
+            }
+        }
+
+        assert_eq!(octree.center_for_index(&[0; MAX_DEPTH + 1]), None);
+        assert_eq!(octree.bounds_for_index(&[0; MAX_DEPTH + 1]), None);
+    }
+
+    #[test]
+    fn indices_at_depth_are_every_node_that_deep() {
+        let (octree, cell_count) = sphere_octree(2000);
+
+        fn nodes<'a>(node: &'a Octree, all: &mut Vec<&'a Octree>) {
+            all.push(node);
+            for child in node.children.iter().flatten() {
+                nodes(child, all);
+            }
+        }
+        let mut all = vec![];
+        nodes(&octree, &mut all);
+
+        for depth in 0..=octree.height + 1 {
+            let mut indices = octree.indices_at_depth(depth);
+            assert_covers_once(&octree, &indices, cell_count);
+
+            // The nodes that deep, and the leaves that stop short of it
+            let mut expected: Vec<Vec<u8>> = all
+                .iter()
+                .filter(|node| node.depth == depth || (node.depth < depth && node.points.is_some()))
+                .map(|node| node.octree_index.clone())
+                .collect();
+            indices.sort();
+            expected.sort();
+            assert_eq!(indices, expected);
+        }
+        assert_eq!(octree.indices_at_depth(0), vec![Vec::<u8>::new()]);
+    }
+
+    #[test]
+    fn leaves_hold_every_point_once() {
+        let (octree, cell_count) = sphere_octree(2000);
+        let leaves: Vec<(&[u8], usize)> = octree.iter_leaves().collect();
+        assert_eq!(
+            leaves.iter().map(|&(_, count)| count).sum::<usize>(),
+            cell_count
+        );
+
+        let mut seen = vec![false; cell_count];
+        for &(path, count) in &leaves {
+            let cells = octree.get_cells_for_index(path).unwrap();
+            assert_eq!(cells.len(), count);
+            for cell in cells {
+                assert!(!std::mem::replace(&mut seen[cell], true));
+            }
+        }
+        assert!(seen.iter().all(|&seen| seen));
+
+        // Depth first in child order, so the paths come out sorted
+        assert!(leaves.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+}
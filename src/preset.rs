@@ -0,0 +1,319 @@
+//! Reproducible planets: everything needed to build and color one from scratch,
+//! so the same preset always gives the same world.
+
+use bevy::prelude::*;
+use noisy_bevy::fbm_simplex_3d;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    colors::HexColors,
+    geometry_data::GeometryData,
+    yields::{compute_yields, CellYield},
+};
+
+/// Fractal noise layered over the plates to roughen the terrain
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct NoiseParams {
+    /// How many features fit around the planet
+    pub frequency: f32,
+    /// How far the noise moves elevation either way
+    pub amplitude: f32,
+    pub octaves: usize,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            frequency: 2.0,
+            amplitude: 0.5,
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+/// What covers a cell, decided by its elevation and latitude
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub(crate) enum Biome {
+    Ocean,
+    /// Frozen over ocean near the poles
+    SeaIce,
+    Beach,
+    Grassland,
+    Forest,
+    Mountain,
+    /// The poles and the highest peaks
+    Snow,
+}
+
+/// Everything that decides what a planet looks like
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct PlanetPreset {
+    /// Times the icosahedron is subdivided before taking the dual
+    pub subdivisions: usize,
+    pub seed: u64,
+    pub noise: NoiseParams,
+    /// Elevation below which cells are ocean, elevations being roughly in -1..1
+    pub sea_level: f32,
+    /// Number of tectonic plates, each either continental or oceanic
+    pub plate_count: usize,
+    /// How far land is raised per unit of elevation above sea level, as a
+    /// fraction of the radius. The sea floor is left flat.
+    pub relief: f32,
+}
+
+impl Default for PlanetPreset {
+    fn default() -> Self {
+        Self {
+            subdivisions: 8,
+            seed: 0,
+            noise: NoiseParams::default(),
+            sea_level: 0.0,
+            plate_count: 12,
+            relief: 0.02,
+        }
+    }
+}
+
+impl PlanetPreset {
+    /// The elevation of every cell of `geometry`, roughly in -1..1. Each cell
+    /// starts at the height of the plate it is nearest the center of, and has
+    /// noise added on top.
+    pub(crate) fn elevations(&self, geometry: &GeometryData) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let plates: Vec<(Vec3, f32)> = (0..self.plate_count.max(1))
+            .map(|_| {
+                let center = Vec3::new(
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                )
+                .try_normalize()
+                .unwrap_or(Vec3::Y);
+                // Roughly two fifths of the plates are continental
+                let height = if rng.random_bool(0.4) {
+                    rng.random_range(0.1..0.5)
+                } else {
+                    rng.random_range(-0.6..-0.2)
+                };
+                (center, height)
+            })
+            .collect();
+
+        // Moves the noise somewhere else for every seed
+        let offset = Vec3::new(
+            rng.random_range(-1000.0..1000.0),
+            rng.random_range(-1000.0..1000.0),
+            rng.random_range(-1000.0..1000.0),
+        );
+
+        let noise = self.noise;
+        geometry
+            .cell_normals
+            .iter()
+            .map(|normal| {
+                let normal = normal.normalize();
+                let (_, plate_height) = plates
+                    .iter()
+                    .max_by(|a, b| normal.dot(a.0).total_cmp(&normal.dot(b.0)))
+                    .expect("there is at least one plate");
+                let roughness = fbm_simplex_3d(
+                    normal * noise.frequency + offset,
+                    noise.octaves,
+                    noise.lacunarity,
+                    noise.gain,
+                );
+                plate_height + roughness * noise.amplitude
+            })
+            .collect()
+    }
+
+    /// The biome of a cell at `elevation` and `latitude` (in radians)
+    pub(crate) fn biome(&self, elevation: f32, latitude: f32) -> Biome {
+        let height = elevation - self.sea_level;
+        let polar = latitude.abs() > 1.2 - height.max(0.0) * 0.5;
+
+        if height < 0.0 {
+            if polar {
+                Biome::SeaIce
+            } else {
+                Biome::Ocean
+            }
+        } else if polar || height > 0.6 {
+            Biome::Snow
+        } else if height < 0.03 {
+            Biome::Beach
+        } else if height < 0.25 {
+            Biome::Grassland
+        } else if height < 0.4 {
+            Biome::Forest
+        } else {
+            Biome::Mountain
+        }
+    }
+
+    /// The color of a cell at `elevation` and `latitude` (in radians)
+    pub(crate) fn biome_color(&self, elevation: f32, latitude: f32) -> Color {
+        match self.biome(elevation, latitude) {
+            Biome::Ocean => {
+                let depth = (self.sea_level - elevation).min(1.0);
+                let shallow = Color::srgb(0.1, 0.4, 0.7);
+                let deep = Color::srgb(0.02, 0.08, 0.3);
+                shallow.mix(&deep, depth)
+            }
+            Biome::SeaIce => Color::srgb(0.8, 0.9, 0.95),
+            Biome::Snow => Color::srgb(0.95, 0.95, 0.97),
+            Biome::Beach => Color::srgb(0.86, 0.8, 0.55),
+            Biome::Grassland => Color::srgb(0.3, 0.6, 0.2),
+            Biome::Forest => Color::srgb(0.15, 0.4, 0.15),
+            Biome::Mountain => Color::srgb(0.45, 0.4, 0.35),
+        }
+    }
+}
+
+/// The terrain a preset gave a body, kept to look up what is in each cell
+#[derive(Component, Clone, Debug)]
+pub(crate) struct Terrain {
+    pub(crate) preset: PlanetPreset,
+    /// The elevation of each cell, see [`PlanetPreset::elevations`]
+    pub(crate) elevations: Vec<f32>,
+    /// What each cell yields, see [`compute_yields`]
+    pub(crate) yields: Vec<CellYield>,
+}
+
+impl Terrain {
+    /// The biome of `cell`, whose center is in direction `normal`
+    pub(crate) fn biome(&self, cell: usize, normal: Vec3) -> Biome {
+        self.preset.biome(self.elevations[cell], latitude(normal))
+    }
+}
+
+/// The latitude (in radians) of direction `normal`
+fn latitude(normal: Vec3) -> f32 {
+    normal.normalize().y.clamp(-1.0, 1.0).asin()
+}
+
+impl GeometryData {
+    /// Builds, terrains and colors a planet from `preset`. Two calls with the
+    /// same preset give identical geometry and colors.
+    pub fn from_preset(preset: &PlanetPreset) -> (GeometryData, HexColors) {
+        let (geometry, colors, _) = GeometryData::icosahedron()
+            .subdivide_n(preset.subdivisions)
+            .expect("the icosahedron is well formed")
+            .slerp()
+            .recell()
+            .dual()
+            .expect("recell puts a cell around every vertex")
+            .with_preset(preset);
+        (geometry, colors)
+    }
+
+    /// The slope of each cell raised by `heights`: the rise to each neighbor
+    /// over the run (the angle between their centers), averaged
+    fn slopes(&self, heights: &[f32]) -> Vec<f32> {
+        self.cell_neighbors
+            .iter()
+            .enumerate()
+            .map(|(cell, neighbors)| {
+                let normal = self.cell_normals[cell];
+                let total: f32 = neighbors
+                    .iter()
+                    .map(|&n| {
+                        let run = normal.angle_between(self.cell_normals[n]);
+                        let rise = (heights[n] - heights[cell]).abs();
+                        if run > 0.0 {
+                            rise / run
+                        } else {
+                            0.0
+                        }
+                    })
+                    .sum();
+                total / neighbors.len().max(1) as f32
+            })
+            .collect()
+    }
+
+    /// Terrains and colors an already built sphere as `preset` says, e.g. one
+    /// loaded from the geometry cache. Land is raised by its elevation above
+    /// sea level times the presets `relief`.
+    pub(crate) fn with_preset(
+        mut self,
+        preset: &PlanetPreset,
+    ) -> (GeometryData, HexColors, Terrain) {
+        let elevations = preset.elevations(&self);
+
+        let colors: Vec<Color> = elevations
+            .iter()
+            .zip(&self.cell_normals)
+            .map(|(&elevation, &normal)| preset.biome_color(elevation, latitude(normal)))
+            .collect();
+
+        let heights: Vec<f32> = elevations
+            .iter()
+            .map(|elevation| (elevation - preset.sea_level).max(0.0) * preset.relief)
+            .collect();
+        let biomes: Vec<Biome> = elevations
+            .iter()
+            .zip(&self.cell_normals)
+            .map(|(&elevation, &normal)| preset.biome(elevation, latitude(normal)))
+            .collect();
+        let yields = compute_yields(&biomes, &self.cell_areas(), &self.slopes(&heights));
+        self.displace(&heights)
+            .expect("there is an elevation for every cell");
+
+        (
+            self,
+            HexColors {
+                changed: (0..colors.len()).collect(),
+                colors,
+                ..Default::default()
+            },
+            Terrain {
+                preset: *preset,
+                elevations,
+                yields,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_preset_same_planet() {
+        let preset = PlanetPreset {
+            subdivisions: 3,
+            seed: 42,
+            ..Default::default()
+        };
+        let (a, a_colors) = GeometryData::from_preset(&preset);
+        let (b, b_colors) = GeometryData::from_preset(&preset);
+
+        let bits = |vs: &[Vec3]| -> Vec<[u32; 3]> {
+            vs.iter().map(|v| v.to_array().map(f32::to_bits)).collect()
+        };
+        assert_eq!(bits(&a.vertices), bits(&b.vertices));
+        assert_eq!(a.faces, b.faces);
+        assert_eq!(a.cells, b.cells);
+        assert_eq!(a_colors.colors, b_colors.colors);
+
+        // And the terrain actually raised some land
+        let flat = GeometryData::icosahedron()
+            .subdivide_n(3)
+            .unwrap()
+            .slerp()
+            .recell()
+            .dual()
+            .unwrap();
+        assert_ne!(bits(&a.vertices), bits(&flat.vertices));
+
+        let other = PlanetPreset { seed: 43, ..preset };
+        assert_ne!(GeometryData::from_preset(&other).1.colors, a_colors.colors);
+    }
+}
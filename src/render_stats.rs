@@ -0,0 +1,110 @@
+use bevy::{prelude::*, render::mesh::PrimitiveTopology};
+
+use crate::chunk_storage::Chunk;
+
+/// How many triangles of chunk meshes were on screen last frame
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct RenderedTriangles(pub usize);
+
+/// The triangle count of a chunks mesh, cached as chunk meshes never change
+/// shape once spawned. Line chunks have none.
+#[derive(Component, Clone, Copy)]
+struct TriangleCount(usize);
+
+/// The most chunks to count the triangles of in a frame, so a wave of newly
+/// spawned chunks doesn't make for a slow frame
+const COUNTS_PER_FRAME: usize = 64;
+
+/// Chunks whose triangles haven't been counted yet
+type Uncounted = (With<Chunk>, Without<TriangleCount>);
+
+#[derive(Component)]
+struct RenderedTrianglesText;
+
+pub(crate) struct RenderStatsPlugin;
+
+impl Plugin for RenderStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderedTriangles>()
+            .add_systems(Startup, spawn_rendered_triangles_text)
+            .add_systems(
+                Update,
+                (
+                    count_chunk_triangles,
+                    sum_rendered_triangles,
+                    update_rendered_triangles_text,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn count_chunk_triangles(
+    mut commands: Commands,
+    chunks: Query<(Entity, &Mesh3d), Uncounted>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    let mut counted = 0;
+    for (entity, mesh) in chunks.iter() {
+        if counted == COUNTS_PER_FRAME {
+            return;
+        }
+        // Meshes not added yet are picked up on a later frame
+        let Some(mesh) = meshes.get(&mesh.0) else {
+            continue;
+        };
+
+        let triangles = match mesh.primitive_topology() {
+            PrimitiveTopology::TriangleList => {
+                mesh.indices()
+                    .map_or(mesh.count_vertices(), |indices| indices.len())
+                    / 3
+            }
+            _ => 0,
+        };
+        commands.entity(entity).insert(TriangleCount(triangles));
+        counted += 1;
+    }
+}
+
+fn sum_rendered_triangles(
+    chunks: Query<(&TriangleCount, &ViewVisibility)>,
+    mut rendered: ResMut<RenderedTriangles>,
+) {
+    let total = chunks
+        .iter()
+        .filter(|(_, visibility)| visibility.get())
+        .map(|(TriangleCount(count), _)| count)
+        .sum();
+    // Only touch the resource when it changes, so the text isn't rewritten every frame
+    rendered.set_if_neq(RenderedTriangles(total));
+}
+
+fn spawn_rendered_triangles_text(mut commands: Commands) {
+    commands.spawn((
+        Text::new("Triangles: ..."),
+        TextFont {
+            font_size: 20.0,
+            ..Default::default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.0),
+            left: Val::Px(0.0),
+            ..Default::default()
+        },
+        RenderedTrianglesText,
+    ));
+}
+
+fn update_rendered_triangles_text(
+    rendered: Res<RenderedTriangles>,
+    mut texts: Query<&mut Text, With<RenderedTrianglesText>>,
+) {
+    if !rendered.is_changed() {
+        return;
+    }
+    for mut text in texts.iter_mut() {
+        text.0 = format!("Triangles: {}", rendered.0);
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::chunk_storage::{Chunk, ChunkCells, LineChunk, WeldedChunk};
+
+/// How chunk normals are generated
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ShadingMode {
+    /// Every cell uses its own normal, giving the faceted hex look
+    #[default]
+    Flat,
+    /// Normals are averaged across neighboring faces
+    Smooth,
+}
+
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub(crate) struct NeedsShading;
+
+pub(crate) fn toggle_shading(mut mode: ResMut<ShadingMode>, input: Res<ButtonInput<KeyCode>>) {
+    if input.just_pressed(KeyCode::KeyN) {
+        *mode = match *mode {
+            ShadingMode::Flat => ShadingMode::Smooth,
+            ShadingMode::Smooth => ShadingMode::Flat,
+        };
+    }
+}
+
+/// Meshed chunks of filled cells, whose normals follow the shading mode
+type ShadedChunk = (With<Chunk>, With<Mesh3d>, Without<LineChunk>);
+
+/// Chunks just given a mesh that was built flat, since welded chunks come
+/// out of the mesher smooth
+type NewlyMeshed = (
+    With<Chunk>,
+    Added<Mesh3d>,
+    Without<LineChunk>,
+    Without<WeldedChunk>,
+);
+
+/// Flags chunks whose normals no longer match the shading mode
+pub(crate) fn mark_chunks_for_shading(
+    mut commands: Commands,
+    mode: Res<ShadingMode>,
+    all_chunks: Query<Entity, ShadedChunk>,
+    new_chunks: Query<Entity, NewlyMeshed>,
+) {
+    if mode.is_changed() {
+        for entity in all_chunks.iter() {
+            commands.entity(entity).insert(NeedsShading);
+        }
+    } else if *mode != ShadingMode::Flat {
+        // Duplicated chunks are always meshed with flat normals
+        for entity in new_chunks.iter() {
+            commands.entity(entity).insert(NeedsShading);
+        }
+    }
+}
+
+pub(crate) fn update_mesh_normals(
+    mut commands: Commands,
+    mode: Res<ShadingMode>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    chunks: Query<(Entity, &Mesh3d, &ChunkCells, Has<WeldedChunk>), With<NeedsShading>>,
+) {
+    let time = Instant::now();
+    for (entity, mesh3d, chunk_cells, welded) in chunks.iter() {
+        if Instant::now().duration_since(time) > Duration::from_millis(3) {
+            return;
+        }
+
+        let Some(local_geometry) = &chunk_cells.local_geometry else {
+            continue;
+        };
+        let Some(mesh) = meshes.get_mut(&mesh3d.0) else {
+            continue;
+        };
+
+        // Welded cells share vertices, so they can't each have their own normal
+        let normals = match *mode {
+            ShadingMode::Flat if !welded => local_geometry.flat_normals(0.0, 0),
+            _ => local_geometry.smooth_normals(),
+        };
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+
+        commands.entity(entity).remove::<NeedsShading>();
+    }
+}
@@ -0,0 +1,141 @@
+//! The cell grid on a sphere, independent of how it was built. Gameplay and
+//! rendering code written against [`SphericalGrid`] works on the dual of a
+//! subdivided icosahedron ([`GeometryData`]) as well as on a goldberg
+//! polyhedron turned into a [`Surface`].
+
+use std::collections::BTreeSet;
+
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology::TriangleList},
+};
+
+use crate::{geometry_data::GeometryData, surface::Surface};
+
+/// A sphere split into cells, indexed `0..cell_count()`
+pub(crate) trait SphericalGrid {
+    fn cell_count(&self) -> usize;
+
+    /// The outward unit normal through the middle of `cell`
+    fn cell_normal(&self, cell: usize) -> Vec3;
+
+    /// The cells sharing an edge with `cell`
+    fn neighbors(&self, cell: usize) -> &BTreeSet<usize>;
+
+    /// The triangles making up `cell`, wound counter clockwise seen from
+    /// outside the sphere
+    fn cell_triangles(&self, cell: usize) -> Vec<[Vec3; 3]>;
+
+    /// A flat shaded mesh of just `cell`
+    fn cell_mesh(&self, cell: usize) -> Mesh {
+        let positions: Vec<Vec3> = self.cell_triangles(cell).into_iter().flatten().collect();
+        let len = positions.len();
+        Mesh::new(
+            TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_indices(Indices::U32((0..len as u32).collect()))
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, vec![self.cell_normal(cell); len])
+    }
+
+    /// The cell whose normal is closest to `dir`, by checking every cell
+    fn nearest_cell(&self, dir: Vec3) -> Option<usize> {
+        (0..self.cell_count()).max_by(|&a, &b| {
+            self.cell_normal(a)
+                .dot(dir)
+                .total_cmp(&self.cell_normal(b).dot(dir))
+        })
+    }
+}
+
+impl SphericalGrid for GeometryData {
+    fn cell_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cell_normal(&self, cell: usize) -> Vec3 {
+        self.cell_normals[cell].normalize_or_zero()
+    }
+
+    fn neighbors(&self, cell: usize) -> &BTreeSet<usize> {
+        &self.cell_neighbors[cell]
+    }
+
+    fn cell_triangles(&self, cell: usize) -> Vec<[Vec3; 3]> {
+        self.cells[cell]
+            .iter()
+            .map(|&face| self.faces[face].map(|v| self.vertices[v]))
+            .collect()
+    }
+}
+
+impl SphericalGrid for Surface {
+    fn cell_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cell_normal(&self, cell: usize) -> Vec3 {
+        self.cells[cell].position.normalize_or_zero()
+    }
+
+    fn neighbors(&self, cell: usize) -> &BTreeSet<usize> {
+        &self.cells[cell].adjacent
+    }
+
+    fn cell_triangles(&self, cell: usize) -> Vec<[Vec3; 3]> {
+        let cell = &self.cells[cell];
+        cell.faces
+            .iter()
+            .map(|face| face.map(|v| cell.vertices[v]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::goldberg::GoldbergPoly;
+
+    /// Checks what any cell grid on a sphere should guarantee
+    fn assert_well_formed(grid: &impl SphericalGrid) {
+        let mut pentagons = 0;
+        for cell in 0..grid.cell_count() {
+            let normal = grid.cell_normal(cell);
+            assert!((normal.length() - 1.0).abs() < 1e-4, "cell {cell} normal");
+            assert_eq!(grid.nearest_cell(normal), Some(cell));
+
+            let neighbors = grid.neighbors(cell);
+            assert!((5..=6).contains(&neighbors.len()), "cell {cell} degree");
+            pentagons += (neighbors.len() == 5) as usize;
+            for &n in neighbors {
+                assert!(grid.neighbors(n).contains(&cell), "{cell} -> {n} one way");
+            }
+
+            for [a, b, c] in grid.cell_triangles(cell) {
+                assert!(
+                    (b - a).cross(c - a).dot(normal) > 0.0,
+                    "cell {cell} winding"
+                );
+            }
+        }
+        assert_eq!(pentagons, 12);
+    }
+
+    #[test]
+    fn both_grids_are_well_formed() {
+        let geometry = GeometryData::icosahedron()
+            .subdivide_n(2)
+            .unwrap()
+            .slerp()
+            .recell()
+            .dual()
+            .unwrap();
+        let surface: Surface = GoldbergPoly::new(2).into();
+
+        assert_eq!(geometry.cell_count(), surface.cell_count());
+        assert_well_formed(&geometry);
+        assert_well_formed(&surface);
+    }
+}
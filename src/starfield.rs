@@ -0,0 +1,157 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    render::mesh::PrimitiveTopology,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{camera::GameCamera, fibonacci_sphere::fibonacci_sphere};
+
+/// How far the stars are from the camera. They follow it around, so this only
+/// has to be inside the far plane and well outside anything else.
+const STARFIELD_RADIUS: f32 = 900.0;
+
+/// The space backdrop behind the planets
+#[derive(Resource, Clone, Reflect)]
+#[reflect(Resource)]
+pub(crate) struct StarfieldConfig {
+    /// Whether to draw the stars. The background color is always used.
+    pub(crate) enabled: bool,
+    pub(crate) star_count: u32,
+    /// Stars are laid out the same way for the same seed
+    pub(crate) seed: u64,
+    /// The clear color of the scene
+    pub(crate) background: Color,
+}
+
+impl Default for StarfieldConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            star_count: 4000,
+            seed: 0,
+            background: Color::srgb(0.0, 0.0, 0.02),
+        }
+    }
+}
+
+/// Marks the starfield mesh
+#[derive(Component)]
+pub(crate) struct Starfield;
+
+pub(crate) struct StarfieldPlugin;
+
+impl Plugin for StarfieldPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<StarfieldConfig>()
+            .init_resource::<StarfieldConfig>()
+            .add_systems(
+                Update,
+                (
+                    toggle_starfield,
+                    rebuild_starfield.after(toggle_starfield),
+                    follow_camera.after(rebuild_starfield),
+                ),
+            );
+    }
+}
+
+pub(crate) fn toggle_starfield(
+    mut config: ResMut<StarfieldConfig>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::KeyB) {
+        config.enabled = !config.enabled;
+    }
+}
+
+/// Stars spread evenly over a unit sphere, each nudged off the fibonacci
+/// spiral by up to about the gap between them so they don't look like a grid.
+/// The colors are mostly dim, with the odd bright (and slightly tinted) star.
+pub(crate) fn star_points(star_count: u32, seed: u64) -> (Vec<Vec3>, Vec<[f32; 4]>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let spacing = (4.0 * std::f32::consts::PI / star_count.max(1) as f32).sqrt();
+
+    fibonacci_sphere(star_count)
+        .into_iter()
+        .map(|point| {
+            let jitter = Vec3::new(
+                rng.random_range(-1.0..=1.0),
+                rng.random_range(-1.0..=1.0),
+                rng.random_range(-1.0..=1.0),
+            ) * spacing;
+            let position = (point + jitter).normalize_or(point);
+
+            let brightness = rng.random_range(0.0..=1.0f32).powi(3);
+            let warmth = rng.random_range(-0.15..=0.15);
+            let color = LinearRgba::new(
+                brightness * (1.0 + warmth),
+                brightness,
+                brightness * (1.0 - warmth),
+                1.0,
+            );
+            (position, color.to_f32_array())
+        })
+        .unzip()
+}
+
+fn rebuild_starfield(
+    mut commands: Commands,
+    config: Res<StarfieldConfig>,
+    existing: Query<Entity, With<Starfield>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+
+    commands.insert_resource(ClearColor(config.background));
+    for entity in existing.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !config.enabled {
+        return;
+    }
+
+    let (points, colors) = star_points(config.star_count, config.seed);
+    let mesh = Mesh::new(
+        PrimitiveTopology::PointList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        points
+            .into_iter()
+            .map(|p| p * STARFIELD_RADIUS)
+            .collect::<Vec<_>>(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+
+    commands.spawn((
+        Mesh3d(meshes.add(mesh)),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            unlit: true,
+            ..default()
+        })),
+        Transform::default(),
+        NotShadowCaster,
+        NotShadowReceiver,
+        Starfield,
+        Name::new("Starfield"),
+    ));
+}
+
+/// Keeps the stars centered on the camera, so they never get any closer
+fn follow_camera(
+    camera: Query<&Transform, (With<GameCamera>, Without<Starfield>)>,
+    mut starfield: Query<&mut Transform, With<Starfield>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    for mut transform in starfield.iter_mut() {
+        transform.translation = camera.translation;
+    }
+}
@@ -3,19 +3,28 @@
 //! (useful in case i want to use something other than goldberg in
 //! the future e.g. a voronoi style mesh from fibonacci sphere)
 
-use bevy::{
-    asset::RenderAssetUsages,
-    pbr::{wireframe::Wireframe, ExtendedMaterial, OpaqueRendererMethod},
-    prelude::*,
-    render::mesh::{Indices, PrimitiveTopology::TriangleList},
+use bevy::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "goldberg_demo")]
+use {
+    crate::{
+        flatnormal::{
+            FlatNormalMaterial, ATTRIBUTE_BLEND_COLOR, ATTRIBUTE_EMISSIVE, ATTRIBUTE_MORPH_TARGET,
+        },
+        Wireframeable,
+    },
+    bevy::{
+        asset::RenderAssetUsages,
+        pbr::{ExtendedMaterial, OpaqueRendererMethod},
+        render::mesh::{Indices, PrimitiveTopology::TriangleList},
+    },
+    rand::{random, random_range},
+    std::collections::VecDeque,
 };
-use rand::{random, random_range};
-use std::collections::{vec_deque, BTreeMap, BTreeSet, VecDeque};
-
-use crate::{flatnormal::FlatNormalMaterial, Wireframeable};
 
 // Easy way to tell chunks to split until they are under this
 // size limit.
+#[cfg(feature = "goldberg_demo")]
 #[derive(Component)]
 pub(crate) struct ChunkSizeLimit(pub usize);
 
@@ -44,8 +53,9 @@ pub(crate) struct Cell {
 
 /// Looks for surfaces with chunks that are too big and
 /// starts splitting them up using voronoi-style chunks
+#[cfg(feature = "goldberg_demo")]
 pub(crate) fn neighbour_chunker(mut surfaces: Query<(&ChunkSizeLimit, &mut Surface)>) {
-    for (limit, mut surface) in surfaces.iter_mut() {
+    for (limit, surface) in surfaces.iter_mut() {
         let mut splits = Vec::new();
         let Surface {
             cells,
@@ -53,13 +63,11 @@ pub(crate) fn neighbour_chunker(mut surfaces: Query<(&ChunkSizeLimit, &mut Surfa
             cell_to_chunk,
         } = surface.into_inner();
 
-        let mut counter = 0;
         let chunks_len = chunks.len();
         for (i, chunk) in chunks.iter_mut().enumerate() {
             if chunk.mesh.is_some() || (chunk.cells.len() < limit.0) {
                 continue;
             }
-            counter += 1;
 
             let mut frontier = VecDeque::from([chunk.cells[random_range(0..chunk.cells.len())]]);
             let mut seen = BTreeSet::new();
@@ -84,7 +92,7 @@ pub(crate) fn neighbour_chunker(mut surfaces: Query<(&ChunkSizeLimit, &mut Surfa
                 }
             }
 
-            chunk.cell_to_local.retain(|c, l| !seen.contains(c));
+            chunk.cell_to_local.retain(|c, _| !seen.contains(c));
             chunk.cells.retain(|c| !seen.contains(c));
 
             splits.push(Chunk {
@@ -98,45 +106,9 @@ pub(crate) fn neighbour_chunker(mut surfaces: Query<(&ChunkSizeLimit, &mut Surfa
     }
 }
 
-pub(crate) fn orderless_chunker(mut surfaces: Query<(&ChunkSizeLimit, &mut Surface)>) {
-    for (limit, mut surface) in surfaces.iter_mut() {
-        let mut splits = Vec::new();
-        let Surface {
-            cells,
-            chunks,
-            cell_to_chunk,
-        } = surface.into_inner();
-
-        let len = chunks.len();
-        for chunk in &mut *chunks {
-            if chunk.mesh.is_some() || (limit.0 > chunk.cells.len()) {
-                continue;
-            }
-
-            // Here we can chunk it!
-            chunk.cell_to_local.retain(|c, l| *l >= limit.0);
-            let new_cells = chunk.cells.split_off(limit.0);
-            splits.push(Chunk {
-                cells: new_cells.clone(),
-                cell_to_local: new_cells
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, c)| (c, i))
-                    .collect(),
-                mesh: None,
-            });
-
-            for cell in &splits.last().unwrap().cells {
-                cell_to_chunk[*cell] = len + splits.len()
-            }
-        }
-
-        chunks.extend(splits);
-    }
-}
-
 /// Looks for chunks that dont have a child mesh object
 /// Creates the mesh based on chunk info
+#[cfg(feature = "goldberg_demo")]
 pub(crate) fn chunk_to_mesh(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -145,11 +117,7 @@ pub(crate) fn chunk_to_mesh(
     mut surfaces: Query<(Entity, Option<&ChunkSizeLimit>, &mut Surface)>,
 ) {
     for (parent, limit, surface) in surfaces.iter_mut() {
-        let Surface {
-            cells,
-            chunks,
-            cell_to_chunk,
-        } = surface.into_inner();
+        let Surface { cells, chunks, .. } = surface.into_inner();
 
         for chunk in chunks {
             if chunk.mesh.is_some() || (limit.is_some() && chunk.cells.len() > limit.unwrap().0) {
@@ -183,13 +151,17 @@ pub(crate) fn chunk_to_mesh(
                         }) as u32;
                         faces.push(i);
                         normals.push(cell.position);
-                        colors.push(color.clone());
+                        colors.push(color);
                     }
                 }
                 c = faces.len();
             }
 
-            // Generate a mesh for this chunk
+            // Generate a mesh for this chunk. `FlatNormalMaterial` needs every
+            // attribute a hexsphere chunk has, so the demo fills in the ones it
+            // doesn't use with values that leave it unblended, unlit and unmorphed.
+            let len = vertices.len();
+            let morph_targets = vertices.clone();
             let mesh = Mesh::new(
                 TriangleList,
                 RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
@@ -201,7 +173,10 @@ pub(crate) fn chunk_to_mesh(
                 // vec![[1.0, 0.0, 0.0, 1.0]; normals.len()],
                 colors,
             )
-            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+            .with_inserted_attribute(ATTRIBUTE_BLEND_COLOR, vec![[1.0, 0.0, 0.0, 1.0]; len])
+            .with_inserted_attribute(ATTRIBUTE_EMISSIVE, vec![[0.0; 4]; len])
+            .with_inserted_attribute(ATTRIBUTE_MORPH_TARGET, morph_targets);
 
             // Update the chunk reference
             let mut entity = commands.spawn((
@@ -215,7 +190,7 @@ pub(crate) fn chunk_to_mesh(
                         opaque_render_method: OpaqueRendererMethod::Auto,
                         ..Default::default()
                     },
-                    extension: FlatNormalMaterial {},
+                    extension: FlatNormalMaterial::default(),
                 })),
             ));
 
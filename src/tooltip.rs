@@ -0,0 +1,267 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContexts, EguiPlugin};
+
+use crate::{
+    chunk_storage::{
+        chunk_at_direction, chunk_index_to_string, Body, Chunk, ChunkBounds, ChunkCells, POV,
+    },
+    colors::{CellVisibility, HexColors},
+    preset::Terrain,
+};
+
+/// Shows a tooltip with the details of the cell under the cursor
+pub(crate) struct CellTooltipPlugin;
+
+impl Plugin for CellTooltipPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app.add_systems(Update, cell_tooltip);
+    }
+}
+
+/// The cell of `body` under `dir`, a direction in the bodies local space. The
+/// nearest cell center is nearly always right, but near borders between cells
+/// of different sizes it can be a neighbor, so its neighbors are checked too.
+fn cell_at_direction(body: &Body, dir: Vec3) -> Option<usize> {
+    let nearest = body.octree.nearest(dir)?;
+    let geometry = &body.geometry;
+    if geometry.cell_contains(nearest, dir) {
+        return Some(nearest);
+    }
+    geometry.cell_neighbors[nearest]
+        .iter()
+        .copied()
+        .find(|&neighbor| geometry.cell_contains(neighbor, dir))
+        .or(Some(nearest))
+}
+
+/// Where `ray` first hits the sphere of radius `radius` around `center`
+fn ray_sphere(ray: Ray3d, center: Vec3, radius: f32) -> Option<f32> {
+    let offset = ray.origin - center;
+    let b = offset.dot(*ray.direction);
+    let c = offset.length_squared() - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrt = discriminant.sqrt();
+    [-b - sqrt, -b + sqrt].into_iter().find(|&t| t >= 0.0)
+}
+
+/// Where `ray` hits the body under `transform`, treated as a sphere: the
+/// distance along the ray, and the direction of the hit in the bodies local space
+fn local_hit(ray: Ray3d, transform: &GlobalTransform) -> Option<(f32, Vec3)> {
+    let (scale, _, center) = transform.to_scale_rotation_translation();
+    let distance = ray_sphere(ray, center, scale.x)?;
+    let local = transform
+        .affine()
+        .inverse()
+        .transform_point3(ray.get_point(distance))
+        .normalize();
+    Some((distance, local))
+}
+
+/// The chunk under the ray from `origin` along `dir`, and the cell hit as an
+/// index into the chunks local geometry, e.g. to recolor its mesh directly.
+/// Simplified chunks have just the one cell. Where chunks overlap while the LOD
+/// changes, the most detailed one wins.
+pub(crate) fn chunk_and_cell_at_ray(
+    origin: Vec3,
+    dir: Dir3,
+    bodies: &Query<(Entity, &Body, &GlobalTransform)>,
+    chunks: &Query<(Entity, &Chunk, &ChunkCells)>,
+) -> Option<(Entity, usize)> {
+    let ray = Ray3d::new(origin, dir);
+    let (_, body_entity, body, local) = bodies
+        .iter()
+        .filter_map(|(entity, body, transform)| {
+            let (distance, local) = local_hit(ray, transform)?;
+            Some((distance, entity, body, local))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))?;
+    let cell = cell_at_direction(body, local)?;
+
+    chunks
+        .iter()
+        .filter(|(_, chunk, _)| chunk.body == body_entity)
+        .filter_map(|(entity, chunk, chunk_cells)| {
+            let local_cell = chunk_cells.cells_to_local.as_ref()?.get(&cell)?;
+            Some((chunk.index.len(), entity, *local_cell))
+        })
+        .max_by_key(|&(depth, ..)| depth)
+        .map(|(_, entity, local_cell)| (entity, local_cell))
+}
+
+/// A body the cursor can be over, with what the tooltip says about its cells
+type HoveredBody<'a> = (
+    Entity,
+    &'a Body,
+    &'a GlobalTransform,
+    Option<&'a HexColors>,
+    Option<&'a Terrain>,
+);
+
+pub(crate) fn cell_tooltip(
+    mut contexts: EguiContexts,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<POV>>,
+    bodies: Query<HoveredBody>,
+    hit_bodies: Query<(Entity, &Body, &GlobalTransform)>,
+    chunks: Query<(Entity, &Chunk, &ChunkCells)>,
+    bounded_chunks: Query<(Entity, &Chunk, &ChunkBounds)>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = cameras.get_single() else {
+        return;
+    };
+    let Ok(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+        return;
+    };
+
+    // The closest body under the cursor, treating each as a sphere
+    let hit = bodies
+        .iter()
+        .filter_map(|(entity, body, transform, colors, terrain)| {
+            let (distance, local) = local_hit(ray, transform)?;
+            Some((distance, entity, body, transform, local, colors, terrain))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+    let Some((_, body_entity, body, transform, local, colors, terrain)) = hit else {
+        return;
+    };
+
+    let Some(cell) = cell_at_direction(body, local) else {
+        return;
+    };
+
+    // Which chunk is drawing it, and as which of its cells. Chunks without a
+    // map to their local cells are still found by their bounds.
+    let chunk = chunk_and_cell_at_ray(ray.origin, ray.direction, &hit_bodies, &chunks)
+        .and_then(|(entity, local_cell)| Some((chunks.get(entity).ok()?.1, Some(local_cell))))
+        .or_else(|| {
+            let dir = transform.rotation() * local;
+            let entity = chunk_at_direction(
+                &bounded_chunks,
+                body_entity,
+                &transform.compute_transform(),
+                dir,
+            )?;
+            Some((bounded_chunks.get(entity).ok()?.1, None))
+        });
+
+    let normal = body.geometry.cell_normals[cell].normalize();
+    let latitude = normal.y.clamp(-1.0, 1.0).asin().to_degrees();
+    let longitude = normal.z.atan2(normal.x).to_degrees();
+    let color = colors.and_then(|colors| colors.colors.get(cell));
+    // Fog of war hides what is in a cell until it is explored
+    let hidden = colors.is_some_and(|colors| colors.visibility(cell) == CellVisibility::Hidden);
+    let biome = terrain.map(|terrain| terrain.biome(cell, normal));
+    let cell_yield = terrain.and_then(|terrain| terrain.yields.get(cell));
+
+    // How far the cell sits above (or below) the undisplaced sphere, in world units
+    let corners = body.geometry.cell_vertex_positions(cell);
+    let radius = corners.iter().map(|v| v.length()).sum::<f32>() / corners.len().max(1) as f32;
+    let height = (radius - 1.0) * transform.scale().x;
+
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+    egui::Area::new(egui::Id::new("cell_tooltip"))
+        .fixed_pos(egui::pos2(cursor.x + 16.0, cursor.y + 16.0))
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!("Cell {cell}"));
+                if let Some((chunk, local_cell)) = chunk {
+                    let path = chunk_index_to_string(&chunk.index);
+                    match local_cell {
+                        Some(local_cell) => ui.label(format!("Chunk {path}, cell {local_cell}")),
+                        None => ui.label(format!("Chunk {path}")),
+                    };
+                }
+                if hidden {
+                    ui.label("Unexplored");
+                    return;
+                }
+                ui.label(format!("Lat {latitude:.2}°, Long {longitude:.2}°"));
+                ui.label(format!("Height {height:.3}"));
+                if let Some(biome) = biome {
+                    ui.label(format!("Biome {biome:?}"));
+                }
+                if let Some(cell_yield) = cell_yield {
+                    ui.label(format!(
+                        "Food {:.1}, Production {:.1}, Trade {:.1}",
+                        cell_yield.food, cell_yield.production, cell_yield.trade
+                    ));
+                }
+                if let Some(color) = color {
+                    let [r, g, b, _] = color.to_srgba().to_u8_array();
+                    ui.label(format!("Color #{r:02x}{g:02x}{b:02x}"));
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk_storage::ChunkIndex, geometry_data::GeometryData};
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn ray_finds_the_cell_in_the_most_detailed_chunk() {
+        let mut world = World::new();
+        let body = Body::new(GeometryData::subdivided_to_cells(642));
+        let cell = cell_at_direction(&body, Vec3::Z).unwrap();
+        let all: Vec<usize> = (0..body.geometry.cells.len()).collect();
+        let child = vec![body.octree.pos_to_child(Vec3::Z) as u8];
+        let child_cells = body.octree.get_cells_for_index(&child).unwrap();
+        let geometry = body.geometry.clone();
+        let body = world
+            .spawn((body, GlobalTransform::from_scale(Vec3::splat(2.0))))
+            .id();
+
+        let spawn_chunk =
+            |world: &mut World, index: ChunkIndex, cells: &[usize], simplify_threshold| {
+                let (cells, local, cells_to_local, _) =
+                    geometry.build_chunk_mesh(cells, simplify_threshold);
+                let chunk_cells = ChunkCells {
+                    cells: Some(cells.into_iter().collect()),
+                    cells_to_local: Some(cells_to_local),
+                    local_geometry: Some(local),
+                };
+                world.spawn((Chunk { body, index }, chunk_cells)).id()
+            };
+        let hit = |world: &mut World, origin: Vec3| {
+            world
+                .run_system_once(
+                    move |bodies: Query<(Entity, &Body, &GlobalTransform)>,
+                          chunks: Query<(Entity, &Chunk, &ChunkCells)>| {
+                        chunk_and_cell_at_ray(origin, Dir3::NEG_Z, &bodies, &chunks)
+                    },
+                )
+                .unwrap()
+        };
+
+        // Seen from afar the whole body is simplified into one cell
+        let root = spawn_chunk(&mut world, vec![], &all, 4);
+        assert_eq!(hit(&mut world, Vec3::Z * 10.0), Some((root, 0)));
+        assert_eq!(hit(&mut world, Vec3::new(3.0, 0.0, 10.0)), None);
+
+        // Once the more detailed child under the ray is spawned, it wins
+        let detailed = spawn_chunk(&mut world, child, &child_cells, 256);
+        let (entity, local_cell) = hit(&mut world, Vec3::Z * 10.0).unwrap();
+        assert_eq!(entity, detailed);
+        let local = world.get::<ChunkCells>(detailed).unwrap();
+        let local_normal = local.local_geometry.as_ref().unwrap().cell_normals[local_cell];
+        assert!(local_normal.abs_diff_eq(geometry.cell_normals[cell], 1e-6));
+    }
+}
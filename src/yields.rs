@@ -0,0 +1,102 @@
+//! What each cell is worth to whoever owns it, for the strategy layer on top
+//! of the terrain.
+
+use bevy::prelude::*;
+
+use crate::preset::Biome;
+
+/// The resources a cell produces each turn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub(crate) struct CellYield {
+    pub(crate) food: f32,
+    pub(crate) production: f32,
+    pub(crate) trade: f32,
+}
+
+impl CellYield {
+    const fn new(food: f32, production: f32, trade: f32) -> Self {
+        Self {
+            food,
+            production,
+            trade,
+        }
+    }
+}
+
+impl Biome {
+    /// What a cell of average size and no slope in this biome yields
+    pub(crate) fn base_yield(self) -> CellYield {
+        match self {
+            Biome::Ocean => CellYield::new(1.0, 0.0, 1.0),
+            Biome::SeaIce => CellYield::new(0.0, 0.0, 0.0),
+            Biome::Beach => CellYield::new(1.0, 0.0, 2.0),
+            Biome::Grassland => CellYield::new(2.0, 1.0, 0.0),
+            Biome::Forest => CellYield::new(1.0, 2.0, 0.0),
+            Biome::Mountain => CellYield::new(0.0, 2.0, 0.0),
+            Biome::Snow => CellYield::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// The yield of every cell, from its biome, its area and its slope (the rise
+/// in elevation over the run to its neighbors, 0 being flat).
+///
+/// Yields scale with area relative to the average cell, so the larger cells
+/// around the pentagons aren't worth more than their size. Steep cells grow
+/// less food but more production, and slope does nothing at sea.
+pub(crate) fn compute_yields(biomes: &[Biome], areas: &[f32], slopes: &[f32]) -> Vec<CellYield> {
+    let mean_area = areas.iter().sum::<f32>() / areas.len().max(1) as f32;
+
+    biomes
+        .iter()
+        .zip(areas)
+        .zip(slopes)
+        .map(|((&biome, &area), &slope)| {
+            let CellYield {
+                food,
+                production,
+                trade,
+            } = biome.base_yield();
+            let size = if mean_area > 0.0 {
+                area / mean_area
+            } else {
+                1.0
+            };
+            let slope = match biome {
+                Biome::Ocean | Biome::SeaIce => 0.0,
+                _ => slope.clamp(0.0, 1.0),
+            };
+
+            CellYield {
+                food: food * (1.0 - slope) * size,
+                production: production * (1.0 + slope) * size,
+                trade: trade * size,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ocean_produces_nothing_and_grassland_feeds() {
+        let biomes = [
+            Biome::Ocean,
+            Biome::Grassland,
+            Biome::Ocean,
+            Biome::Grassland,
+        ];
+        let areas = [1.0, 1.0, 1.5, 0.5];
+        let slopes = [0.0, 0.0, 0.8, 0.3];
+        let yields = compute_yields(&biomes, &areas, &slopes);
+
+        assert_eq!(yields[0].production, 0.0);
+        assert_eq!(yields[2].production, 0.0);
+        assert!(yields[1].food > 0.0);
+        assert!(yields[3].food > 0.0);
+        // Smaller and steeper grows less
+        assert!(yields[3].food < yields[1].food);
+    }
+}